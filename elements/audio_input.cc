@@ -0,0 +1,124 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+//
+// -----------------------------------------------------------------------------
+//
+// Gain, noise gate, routing, level meter and envelope follower of the codec
+// input.
+
+#include "elements/audio_input.h"
+
+#include <algorithm>
+#include <cmath>
+
+namespace elements {
+
+using namespace std;
+
+// The gate follows the squared signal, opening fast and closing slowly.
+const float kGateAttack = 0.1f;
+const float kGateRelease = 0.0001f;
+// About 14 dB per second at 32kHz.
+const float kMeterDecay = 0.99995f;
+const float kMeterFloor = -100.0f;
+// Instant attack, 30ms release at 32kHz.
+const float kEnvelopeRelease = 0.001f;
+
+const float kDefaultThreshold = -40.0f;
+
+void AudioInput::Init() {
+  routing_ = INPUT_ROUTING_STEREO;
+  for (size_t i = 0; i < INPUT_CHANNEL_LAST; ++i) {
+    Configure(static_cast<InputChannel>(i), 0.0f, kDefaultThreshold);
+    gate_level_[i] = 0.0f;
+    peak_[i] = 0.0f;
+  }
+  envelope_ = 0.0f;
+}
+
+void AudioInput::Configure(
+    InputChannel channel, float gain, float threshold) {
+  gain_[channel] = powf(10.0f, gain / 20.0f);
+  // compared with the squared signal
+  threshold_[channel] = powf(10.0f, threshold / 10.0f);
+}
+
+float AudioInput::Gate(InputChannel channel, float sample) {
+  float error = sample * sample - gate_level_[channel];
+  gate_level_[channel] += error * (error > 0.0f ? kGateAttack : kGateRelease);
+  float threshold = threshold_[channel];
+  float gain = gate_level_[channel] <= threshold
+      ? gate_level_[channel] / threshold : 1.0f;
+  return gain * sample;
+}
+
+void AudioInput::Process(
+    const Codec::Frame* input, float* strike, float* blow, size_t size) {
+  for (size_t i = 0; i < size; ++i) {
+    float left = static_cast<float>(input[i].l) / 32768.0f;
+    float right = static_cast<float>(input[i].r) / 32768.0f;
+    left *= gain_[INPUT_CHANNEL_LEFT];
+    right *= gain_[INPUT_CHANNEL_RIGHT];
+    
+    peak_[INPUT_CHANNEL_LEFT] = max(
+        fabsf(left), peak_[INPUT_CHANNEL_LEFT] * kMeterDecay);
+    peak_[INPUT_CHANNEL_RIGHT] = max(
+        fabsf(right), peak_[INPUT_CHANNEL_RIGHT] * kMeterDecay);
+
+    float amplitude = max(fabsf(left), fabsf(right));
+    if (amplitude > envelope_) {
+      envelope_ = amplitude;
+    } else {
+      envelope_ += (amplitude - envelope_) * kEnvelopeRelease;
+    }
+
+    left = Gate(INPUT_CHANNEL_LEFT, left);
+    right = Gate(INPUT_CHANNEL_RIGHT, right);
+    switch (routing_) {
+      case INPUT_ROUTING_STEREO:
+        strike[i] = left;
+        blow[i] = right;
+        break;
+        
+      case INPUT_ROUTING_MONO:
+        strike[i] = blow[i] = 0.5f * (left + right);
+        break;
+        
+      default:
+        strike[i] = blow[i] = 0.0f;
+        break;
+    }
+  }
+}
+
+static float ToDecibels(float amplitude) {
+  return amplitude > 0.0f
+      ? max(20.0f * log10f(amplitude), kMeterFloor)
+      : kMeterFloor;
+}
+
+float AudioInput::level(InputChannel channel) const {
+  return ToDecibels(peak_[channel]);
+}
+
+float AudioInput::envelope_level() const {
+  return ToDecibels(envelope_);
+}
+
+}  // namespace elements
@@ -0,0 +1,88 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+//
+// -----------------------------------------------------------------------------
+//
+// Gain, noise gate, routing, level meter and envelope follower of the codec
+// input.
+
+#ifndef ELEMENTS_AUDIO_INPUT_H_
+#define ELEMENTS_AUDIO_INPUT_H_
+
+#include "stmlib/stmlib.h"
+
+#include "elements/drivers/codec.h"
+
+namespace elements {
+
+enum InputRouting {
+  INPUT_ROUTING_STEREO,  // left to the strike input, right to the blow input
+  INPUT_ROUTING_MONO,  // both channels mixed to both inputs
+  INPUT_ROUTING_OFF,
+  INPUT_ROUTING_LAST
+};
+
+enum InputChannel {
+  INPUT_CHANNEL_LEFT,
+  INPUT_CHANNEL_RIGHT,
+  INPUT_CHANNEL_LAST
+};
+
+class AudioInput {
+ public:
+  AudioInput() { }
+  ~AudioInput() { }
+
+  void Init();
+
+  // Gain in dB, the gate closes below the threshold in dBFS.
+  void Configure(InputChannel channel, float gain, float threshold);
+  
+  inline void set_routing(InputRouting routing) {
+    routing_ = routing;
+  }
+  
+  void Process(
+      const Codec::Frame* input, float* strike, float* blow, size_t size);
+  
+  // Peak level after the gain, in dBFS.
+  float level(InputChannel channel) const;
+  
+  // Follows the louder channel after the gain, 0.0 to 1.0.
+  inline float envelope() const { return envelope_; }
+  
+  // Envelope in dBFS.
+  float envelope_level() const;
+  
+ private:
+  float Gate(InputChannel channel, float sample);
+
+  InputRouting routing_;
+  float gain_[INPUT_CHANNEL_LAST];
+  float threshold_[INPUT_CHANNEL_LAST];
+  float gate_level_[INPUT_CHANNEL_LAST];
+  float peak_[INPUT_CHANNEL_LAST];
+  float envelope_;
+  
+  DISALLOW_COPY_AND_ASSIGN(AudioInput);
+};
+
+}  // namespace elements
+
+#endif  // ELEMENTS_AUDIO_INPUT_H_
@@ -41,6 +41,8 @@ namespace elements {
 using namespace std;
 using namespace stmlib;
 
+const size_t kNumBuiltInSamples = 9;
+
 void Exciter::Init() {
   set_model(EXCITER_MODEL_MALLET);
   set_parameter(0.0f);
@@ -109,17 +111,30 @@ void Exciter::ProcessGranularSamplePlayer(
 
 void Exciter::ProcessSamplePlayer(
     const uint8_t flags, float* out, size_t size) {
-  float index = (1.0f - parameter_) * 8.0f;
+  const int16_t* sample_data = smp_sample_data;
+  const size_t* boundaries = smp_boundaries;
+  size_t count = kNumBuiltInSamples;
+  // read once, it may be replaced between two blocks
+  const SampleBank* bank = sample_bank_;
+  if (bank) {
+    sample_data = bank->data;
+    boundaries = bank->boundaries;
+    count = bank->count;
+  }
+  
+  float index = (1.0f - parameter_) * static_cast<float>(count - 1);
   MAKE_INTEGRAL_FRACTIONAL(index);
-  if (index_integral == 8) {
-    index_integral = 7;
-    index_fractional = 1.0f;
+  if (index_integral >= static_cast<int32_t>(count - 1)) {
+    index_integral = count - 1;
+    index_fractional = 0.0f;
   }
+  const size_t next_index = min(
+      static_cast<size_t>(index_integral) + 1, count - 1);
   
-  const uint32_t offset_1 = smp_boundaries[index_integral];
-  const uint32_t offset_2 = smp_boundaries[index_integral + 1];
-  const uint32_t length_1 = offset_2 - offset_1 - 1;
-  const uint32_t length_2 = smp_boundaries[index_integral + 2] - offset_2 - 1;
+  const uint32_t offset_1 = boundaries[index_integral];
+  const uint32_t offset_2 = boundaries[next_index];
+  const uint32_t length_1 = boundaries[index_integral + 1] - offset_1 - 1;
+  const uint32_t length_2 = boundaries[next_index + 1] - offset_2 - 1;
   const uint32_t phase_increment = static_cast<uint32_t>(
       65536.0f * SemitonesToRatio(72.0f * timbre_ - 36.0f + 7.0f));
   
@@ -141,14 +156,14 @@ void Exciter::ProcessSamplePlayer(
     float sample_2 = 0.0f;
     bool step = false;
     if (phase_integral < length_1) {
-      const int16_t* base = &smp_sample_data[offset_1 + phase_integral];
+      const int16_t* base = &sample_data[offset_1 + phase_integral];
       float a = static_cast<float>(base[0]);
       float b = static_cast<float>(base[1]);
       sample_1 = a + (b - a) * phase_fractional;
       step = true;
     }
     if (phase_integral < length_2) {
-      const int16_t* base = &smp_sample_data[offset_2 + phase_integral];
+      const int16_t* base = &sample_data[offset_2 + phase_integral];
       float a = static_cast<float>(base[0]);
       float b = static_cast<float>(base[1]);
       sample_2 = a + (b - a) * phase_fractional;
@@ -275,6 +290,9 @@ void Exciter::ProcessNoise(const uint8_t flags, float* out, size_t size) {
   }
 }
 
+/* static */
+const SampleBank* volatile Exciter::sample_bank_ = NULL;
+
 /* static */
 Exciter::ProcessFn Exciter::fn_table_[] = {
   &Exciter::ProcessGranularSamplePlayer,
@@ -52,6 +52,14 @@ enum ExciterFlags {
   EXCITER_FLAG_GATE = 4
 };
 
+// One-shot samples, each followed by a copy of its last value for the
+// interpolation. Sample i spans boundaries[i] to boundaries[i + 1].
+struct SampleBank {
+  const int16_t* data;
+  const size_t* boundaries;
+  size_t count;
+};
+
 class Exciter {
  public:
   typedef void (Exciter::*ProcessFn)(const uint8_t, float*, size_t);
@@ -87,6 +95,11 @@ class Exciter {
     }
   }
   
+  // Replaces the built-in strike samples, NULL restores them.
+  static inline void set_sample_bank(const SampleBank* bank) {
+    sample_bank_ = bank;
+  }
+  
   inline float damping() const {
     return damping_;
   }
@@ -124,6 +137,8 @@ class Exciter {
   uint32_t plectrum_delay_;
   
   static ProcessFn fn_table_[];
+  // read by the audio interrupt
+  static const SampleBank* volatile sample_bank_;
   
   DISALLOW_COPY_AND_ASSIGN(Exciter);
 };
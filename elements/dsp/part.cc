@@ -28,6 +28,8 @@
 
 #include "elements/dsp/part.h"
 
+#include <cmath>
+
 #include "elements/resources.h"
 
 namespace elements {
@@ -57,9 +59,13 @@ void Part::Init(uint16_t* reverb_buffer) {
   patch_.space = 0.5f;
   previous_gate_ = false;
   active_voice_ = 0;
+  unison_ = false;
+  unison_detune_ = 0.0f;
+  stereo_spread_ = 0.0f;
   
   fill(&silence_[0], &silence_[kMaxBlockSize], 0.0f);
   fill(&note_[0], &note_[kNumVoices], 69.0f);
+  fill(&voice_gate_[0], &voice_gate_[kNumVoices], false);
   
   for (size_t i = 0; i < kNumVoices; ++i) {
     voice_[i].Init();
@@ -114,6 +120,28 @@ void Part::Process(
     float* main,
     float* aux,
     size_t size) {
+  // When a new note is played, cycle to the next voice.
+  size_t voice = active_voice_;
+  if (performance_state.gate && !previous_gate_) {
+    voice = (voice + 1) % kNumVoices;
+  }
+  note_[voice] = performance_state.note;
+  
+  for (size_t i = 0; i < kNumVoices; ++i) {
+    voice_state_[i] = performance_state;
+    voice_state_[i].note = note_[i];
+    voice_state_[i].gate = i == voice && performance_state.gate;
+  }
+  Process(voice_state_, blow_in, strike_in, main, aux, size);
+}
+
+void Part::Process(
+    const PerformanceState* voice_states,
+    const float* blow_in,
+    const float* strike_in,
+    float* main,
+    float* aux,
+    size_t size) {
 
   // Copy inputs to outputs when bypass mode is enabled.
   if (bypass_ || panic_) {
@@ -132,16 +160,27 @@ void Part::Process(
     return;
   }
 
-  // When a new note is played, cycle to the next voice.
-  if (performance_state.gate && !previous_gate_) {
-    ++active_voice_;
-    if (active_voice_ >= kNumVoices) {
-      active_voice_ = 0;
+  // The audio inputs follow the most recently triggered voice.
+  for (size_t i = 0; i < kNumVoices; ++i) {
+    if (voice_states[i].gate && !voice_gate_[i]) {
+      active_voice_ = i;
     }
+    voice_gate_[i] = voice_states[i].gate;
   }
   
-  previous_gate_ = performance_state.gate;
-  note_[active_voice_] = performance_state.note;
+  previous_gate_ = voice_states[active_voice_].gate;
+  
+  // In unison mode, the voices are stacked on the last triggered note.
+  float voice_gain = 1.0f;
+  if (unison_ && kNumVoices > 1) {
+    for (size_t i = 0; i < kNumVoices; ++i) {
+      float position = static_cast<float>(i) / (kNumVoices - 1) - 0.5f;
+      unison_state_[i] = voice_states[active_voice_];
+      unison_state_[i].note += position * unison_detune_;
+    }
+    voice_states = unison_state_;
+    voice_gain = 1.0f / sqrtf(static_cast<float>(kNumVoices));
+  }
   fill(&main[0], &main[size], 0.0f);
   fill(&aux[0], &aux[size], 0.0f);
   
@@ -157,15 +196,16 @@ void Part::Process(
   
   // Render each voice.
   for (size_t i = 0; i < kNumVoices; ++i) {
-    float midi_pitch = note_[i] + performance_state.modulation;
+    const PerformanceState& performance_state = voice_states[i];
+    float midi_pitch = performance_state.note + performance_state.modulation;
     if (easter_egg_) {
       ominous_voice_[i].Process(
           patch_,
           midi_pitch,
           performance_state.strength,
-          i == active_voice_ && performance_state.gate,
-          (i == active_voice_) ? blow_in : silence_,
-          (i == active_voice_) ? strike_in : silence_,
+          performance_state.gate,
+          (unison_ || i == active_voice_) ? blow_in : silence_,
+          (unison_ || i == active_voice_) ? strike_in : silence_,
           raw_buffer_,
           center_buffer_,
           sides_buffer_,
@@ -184,20 +224,25 @@ void Part::Process(
           patch_,
           lut_midi_to_f_high[pitch >> 8] * lut_midi_to_f_low[pitch & 0xff],
           performance_state.strength,
-          i == active_voice_ && performance_state.gate,
-          (i == active_voice_) ? blow_in : silence_,
-          (i == active_voice_) ? strike_in : silence_,
+          performance_state.gate,
+          (unison_ || i == active_voice_) ? blow_in : silence_,
+          (unison_ || i == active_voice_) ? strike_in : silence_,
           raw_buffer_,
           center_buffer_,
           sides_buffer_,
           size);
     }
     
-    // Mixdown.
+    // Mixdown, each voice gets its own place in the stereo field.
+    float pan = kNumVoices > 1
+        ? stereo_spread_ * (2.0f * i / (kNumVoices - 1) - 1.0f)
+        : 0.0f;
+    float r_gain = voice_gain * (pan > 0.0f ? 1.0f - pan : 1.0f);
+    float l_gain = voice_gain * (pan < 0.0f ? 1.0f + pan : 1.0f);
     for (size_t j = 0; j < size; ++j) {
       float side = sides_buffer_[j] * spread;
-      float r = center_buffer_[j] - side;
-      float l = center_buffer_[j] + side;;
+      float r = (center_buffer_[j] - side) * r_gain;
+      float l = (center_buffer_[j] + side) * l_gain;
       main[j] += r;
       aux[j] += l + (raw_buffer_[j] - l) * raw_gain;
     }
@@ -46,8 +46,13 @@ struct PerformanceState {
 };
 
 // Polyphony is actually possible, but you have to reduce the number of modes
-// to 16, and this doesn't sound very good...
-const size_t kNumVoices = 1;
+// to 16 (see ELEMENTS_MAX_MODES in resonator.h), and this doesn't sound very
+// good on a STM32F4. Build with -DELEMENTS_NUM_VOICES=4 on faster hardware.
+#ifndef ELEMENTS_NUM_VOICES
+#define ELEMENTS_NUM_VOICES 1
+#endif  // ELEMENTS_NUM_VOICES
+
+const size_t kNumVoices = ELEMENTS_NUM_VOICES;
 
 class Part {
  public:
@@ -64,6 +69,16 @@ class Part {
       float* aux,
       size_t n);
 
+  // Renders each voice with its own performance state, voice_states holds
+  // kNumVoices entries. The audio inputs excite the last triggered voice.
+  void Process(
+      const PerformanceState* voice_states,
+      const float* blow_in,
+      const float* strike_in,
+      float* main,
+      float* aux,
+      size_t n);
+
   inline Patch* mutable_patch() { return &patch_; }
   
   void Seed(uint32_t* seed, size_t size);
@@ -73,12 +88,21 @@ class Part {
   inline float exciter_level() const { return scaled_exciter_level_; }
   inline float resonator_level() const { return scaled_resonator_level_; }
   inline bool gate() const { return previous_gate_; }
+  inline size_t active_voice() const { return active_voice_; }
   inline bool bypass() const { return bypass_; }
   inline void set_bypass(bool bypass) { bypass_ = bypass; }
 
   inline bool easter_egg() const { return easter_egg_; }
   inline void set_easter_egg(bool easter_egg) { easter_egg_ = easter_egg; }
 
+  // All voices play the last triggered note, spread over detune semitones.
+  inline void set_unison(bool unison, float detune) {
+    unison_ = unison;
+    unison_detune_ = detune;
+  }
+  // Pans the voices from right to left, 0 keeps them all centered.
+  inline void set_stereo_spread(float spread) { stereo_spread_ = spread; }
+
   inline ResonatorModel resonator_model() const { return resonator_model_; }
   inline void set_resonator_model(ResonatorModel r) { resonator_model_ = r; }
   
@@ -92,6 +116,13 @@ class Part {
   bool easter_egg_;
   bool previous_gate_;
   float note_[kNumVoices];
+  bool voice_gate_[kNumVoices];
+  PerformanceState voice_state_[kNumVoices];
+  PerformanceState unison_state_[kNumVoices];
+  
+  bool unison_;
+  float unison_detune_;
+  float stereo_spread_;
   
   size_t num_voices_;
   size_t active_voice_;
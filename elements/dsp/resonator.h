@@ -40,7 +40,12 @@
 
 namespace elements {
 
-const size_t kMaxModes = 64;
+// Fewer modes leave room for more voices, see ELEMENTS_NUM_VOICES in part.h.
+#ifndef ELEMENTS_MAX_MODES
+#define ELEMENTS_MAX_MODES 64
+#endif  // ELEMENTS_MAX_MODES
+
+const size_t kMaxModes = ELEMENTS_MAX_MODES;
 const size_t kMaxBowedModes = 8;
 const size_t kMaxDelayLineSize = 1024;
 
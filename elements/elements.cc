@@ -30,8 +30,10 @@
 #include "elements/drivers/debug_port.h"
 #include "elements/drivers/pots_adc.h"
 #include "elements/drivers/system.h"
+#include "elements/audio_input.h"
 #include "elements/dsp/part.h"
 #include "elements/cv_scaler.h"
+#include "elements/modulation.h"
 #include "elements/ui.h"
 #include "elements/elements.h"
 
@@ -52,6 +54,17 @@ CvScaler cv_scaler;
 DebugPort debug_port;
 Part part;
 Ui ui;
+Modulator modulator;
+AudioInput audio_input;
+
+// Patch as edited by the user, the part renders it with modulations applied.
+Patch patch;
+// Patch after Init and Seed, the reverb settings differ per unit.
+Patch init_patch;
+ModulationSettings modulation_settings;
+
+// User strike samples, stored in flash by Kawa.
+SampleBank user_samples;
 
 uint16_t reverb_buffer[32768] __attribute__ ((section (".ccmdata")));
 
@@ -142,33 +155,29 @@ float strike_in[kAudioChunkSize];
 float out[kAudioChunkSize];
 float aux[kAudioChunkSize];
 
-const float kNoiseGateThreshold = 0.0001f;
-float strike_in_level = 0.0f;
-float blow_in_level = 0.0f;
-PerformanceState state;
+// Written by the voice allocator, one state per voice of the part.
+PerformanceState voice_state[kNumVoices];
+// Pitch bend of the whole part, added to the bend of each voice.
+float global_modulation;
 
 void FillBuffer(Codec::Frame* input, Codec::Frame* output, size_t n) {
 #ifdef PROFILE_INTERRUPT
   TIC
 #endif  // PROFILE_INTERRUPT
   //cv_scaler.Read(part.mutable_patch(), &s);
-  for (size_t i = 0; i < n; ++i) {
-    float blow_in_sample = static_cast<float>(input[i].r) / 32768.0f;
-    float strike_in_sample = static_cast<float>(input[i].l) / 32768.0f;
-
-    float error, gain;
-    error = strike_in_sample * strike_in_sample - strike_in_level;
-    strike_in_level += error * (error > 0.0f ? 0.1f : 0.0001f);
-    gain = strike_in_level <= kNoiseGateThreshold 
-          ? (1.0f / kNoiseGateThreshold) * strike_in_level : 1.0f;
-    strike_in[i] = gain * strike_in_sample;
-    
-    error = blow_in_sample * blow_in_sample - blow_in_level;
-    blow_in_level += error * (error > 0.0f ? 0.1f : 0.0001f);
-    gain = blow_in_level <= kNoiseGateThreshold 
-          ? (1.0f / kNoiseGateThreshold) * blow_in_level : 1.0f;
-    blow_in[i] = gain * blow_in_sample;
+  audio_input.Process(input, strike_in, blow_in, n);
+  PerformanceState state[kNumVoices];
+  for (size_t i = 0; i < kNumVoices; ++i) {
+    state[i] = voice_state[i];
+    state[i].modulation += global_modulation;
   }
+  modulator.set_input_level(audio_input.envelope());
+  modulator.Process(
+      modulation_settings,
+      state[part.active_voice()],
+      patch,
+      part.mutable_patch(),
+      n);
   part.Process(state, blow_in, strike_in, out, aux, n);
   for (size_t i = 0; i < n; ++i) {
     output[i].r = SoftConvert(out[i]);
@@ -180,23 +189,102 @@ void FillBuffer(Codec::Frame* input, Codec::Frame* output, size_t n) {
 }
 
 Patch *Elements_GetPatch() {
-  return part.mutable_patch();
+  return &patch;
+}
+
+const Patch *Elements_GetInitPatch() {
+  return &init_patch;
+}
+
+ModulationSettings *Elements_GetModulation() {
+  return &modulation_settings;
+}
+
+void Elements_SetAftertouch(float newAftertouch) {
+  modulator.set_aftertouch(newAftertouch);
+}
+
+void Elements_SetModWheel(float newModWheel) {
+  modulator.set_mod_wheel(newModWheel);
+}
+
+void Elements_SetController(
+    uint32_t controller, uint32_t parameter, float amount) {
+  modulator.set_controller(controller, parameter, amount);
+}
+
+void Elements_SetTempo(float newTempo) {
+  modulator.set_tempo(newTempo);
+}
+
+void Elements_ClockStart(bool reset) {
+  modulator.ClockStart(reset);
+}
+
+void Elements_ClockStop() {
+  modulator.ClockStop();
+}
+
+void Elements_ClockTick() {
+  modulator.ClockTick();
+}
+
+void Elements_Lock(uint32_t parameter, float value) {
+  modulator.Lock(parameter, value);
+}
+
+void Elements_ClearLocks() {
+  modulator.ClearLocks();
+}
+
+uint32_t Elements_GetNumVoices() {
+  return kNumVoices;
+}
+
+PerformanceState *Elements_GetVoiceState(uint32_t voice) {
+  return &voice_state[voice];
+}
+
+void Elements_SetUnison(bool unison, float detune, float spread) {
+  part.set_unison(unison, detune);
+  part.set_stereo_spread(spread);
+}
+
+void Elements_SetSamples(
+    const int16_t* data, const size_t* boundaries, uint32_t count) {
+  if (count == 0) {
+    Exciter::set_sample_bank(NULL);
+    return;
+  }
+  // the exciter reads the bank in the audio interrupt, the barriers keep the
+  // compiler from moving the field stores out from between the pointer stores
+  Exciter::set_sample_bank(NULL);
+  __asm volatile ("" ::: "memory");
+  user_samples.data = data;
+  user_samples.boundaries = boundaries;
+  user_samples.count = count;
+  __asm volatile ("" ::: "memory");
+  Exciter::set_sample_bank(&user_samples);
+}
+
+void Elements_SetInputRouting(uint32_t routing) {
+  audio_input.set_routing(static_cast<InputRouting>(routing));
 }
 
-void Elements_SetGate(bool newGate) {
-  state.gate = newGate;
+void Elements_SetInputChannel(uint32_t channel, float gain, float threshold) {
+  audio_input.Configure(static_cast<InputChannel>(channel), gain, threshold);
 }
 
-void Elements_SetNote(float newNote) {
-  state.note = newNote;
+float Elements_GetInputLevel(uint32_t channel) {
+  return audio_input.level(static_cast<InputChannel>(channel));
 }
 
-void Elements_SetStrength(float newStrength) {
-  state.strength = newStrength;
+float Elements_GetInputEnvelope() {
+  return audio_input.envelope_level();
 }
 
 void Elements_SetModulation(float newModulation) {
-  state.modulation = newModulation;
+  global_modulation = newModulation;
 }
 
 void Elements_Pause(bool pause) {
@@ -211,6 +299,10 @@ void Elements_Init(bool application) {
   // Init and seed the random parameters and generators with the serial number.
   part.Init(reverb_buffer);
   part.Seed((uint32_t*)(0x7a10), 3);
+  patch = *part.mutable_patch();
+  init_patch = patch;
+  modulator.Init(&modulation_settings);
+  audio_input.Init();
 
   cv_scaler.Init();
   ui.Init(&part, &cv_scaler);
@@ -246,9 +338,9 @@ void TestElements(bool application) {
 
   Elements_Init(application);
 
-  state.gate = true;
-  state.note = 50;
-  state.strength = 1;
+  voice_state[0].gate = true;
+  voice_state[0].note = 50;
+  voice_state[0].strength = 1;
     
   GPIO_InitTypeDef gpio;
 
@@ -1,14 +1,33 @@
 #pragma once
 
 #include "dsp/patch.h"
+#include "modulation.h"
 
 extern "C" {
   void Elements_Init(bool application);
   elements::Patch *Elements_GetPatch();
+  const elements::Patch *Elements_GetInitPatch();
+  elements::ModulationSettings *Elements_GetModulation();
   void Elements_Pause(bool pause);
-  void Elements_SetGate(bool newGate);
-  void Elements_SetNote(float newNote);
-  void Elements_SetStrength(float newStrength);
+  uint32_t Elements_GetNumVoices();
+  elements::PerformanceState *Elements_GetVoiceState(uint32_t voice);
+  void Elements_SetUnison(bool unison, float detune, float spread);
+  void Elements_SetSamples(
+      const int16_t* data, const size_t* boundaries, uint32_t count);
+  void Elements_SetInputRouting(uint32_t routing);
+  void Elements_SetInputChannel(uint32_t channel, float gain, float threshold);
+  float Elements_GetInputLevel(uint32_t channel);
+  float Elements_GetInputEnvelope();
   void Elements_SetModulation(float newModulation);
+  void Elements_SetAftertouch(float newAftertouch);
+  void Elements_SetModWheel(float newModWheel);
+  void Elements_SetController(
+      uint32_t controller, uint32_t parameter, float amount);
+  void Elements_SetTempo(float newTempo);
+  void Elements_ClockStart(bool reset);
+  void Elements_ClockStop();
+  void Elements_ClockTick();
+  void Elements_Lock(uint32_t parameter, float value);
+  void Elements_ClearLocks();
   void Elements_DMA1_Stream5_IRQHandler(void);
 }
@@ -0,0 +1,270 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+//
+// -----------------------------------------------------------------------------
+//
+// Modulation matrix applied to the patch once per audio block.
+
+#include "elements/modulation.h"
+
+#include <algorithm>
+
+#include "stmlib/dsp/dsp.h"
+#include "stmlib/dsp/units.h"
+#include "stmlib/utils/random.h"
+
+#include "elements/dsp/dsp.h"
+#include "elements/resources.h"
+
+namespace elements {
+
+using namespace std;
+using namespace stmlib;
+
+// Full scale of each Patch parameter, in the order of the Patch fields.
+// Most of them are normalized, the resonator LFO runs in cycles per sample.
+static const float kParameterRange[kNumPatchParameters] = {
+  1.0f, 1.0f, 1.0f, 1.0f, 1.0f, 1.0f, 1.0f, 1.0f, 1.0f, 1.0f,
+  1.0f, 1.0f, 1.0f, 1.0f, 2.0f / kSampleRate, 1.0f, 1.0f, 1.0f, 1.0f, 1.0f
+};
+
+// Length of each LfoDivision in beats.
+static const float kLfoDivisionBeats[LFO_DIVISION_LAST] = {
+  0.0f, 8.0f, 4.0f, 2.0f, 4.0f / 3.0f, 1.5f, 1.0f, 2.0f / 3.0f,
+  0.75f, 0.5f, 1.0f / 3.0f, 0.375f, 0.25f, 1.0f / 6.0f, 0.125f
+};
+
+// All divisions fit a whole number of times, the beat position wraps there
+// to keep its precision.
+const int32_t kClockWrapBeats = 48;
+
+void Modulator::Init(ModulationSettings* settings) {
+  for (size_t i = 0; i < kNumLfos; ++i) {
+    settings->lfo[i].rate = 0.5f;
+    settings->lfo[i].shape = LFO_SHAPE_SINE;
+    settings->lfo[i].division = LFO_DIVISION_FREE;
+    lfo_phase_[i] = 0.0f;
+    lfo_random_value_[i] = 0.0f;
+  }
+  settings->envelope_attack = 0.2f;
+  settings->envelope_decay = 0.5f;
+  settings->envelope_sustain = 0.5f;
+  settings->envelope_release = 0.5f;
+  for (size_t i = 0; i < kNumModulationSlots; ++i) {
+    settings->slot[i].source = MODULATION_SOURCE_NONE;
+    settings->slot[i].destination = 0;
+    settings->slot[i].amount = 0.0f;
+  }
+  for (size_t i = 0; i < kNumVelocitySlots; ++i) {
+    settings->velocity[i].destination = 0;
+    settings->velocity[i].amount = 0.0f;
+  }
+
+  envelope_.Init();
+  previous_gate_ = false;
+  aftertouch_ = 0.0f;
+  mod_wheel_ = 0.0f;
+  input_level_ = 0.0f;
+
+  tempo_ = 120.0f;
+  external_clock_ = false;
+  clock_ticks_ = 0;
+  beat_position_ = 0.0f;
+
+  lock_mask_ = 0;
+  for (size_t i = 0; i < CONTROLLER_LAST; ++i) {
+    controller_parameter_[i] = kNumPatchParameters;
+    controller_amount_[i] = 0.0f;
+  }
+}
+
+void Modulator::Lock(size_t parameter, float value) {
+  if (parameter < kNumPatchParameters) {
+    lock_value_[parameter] = value;
+    lock_mask_ |= 1 << parameter;
+  }
+}
+
+void Modulator::ClockStart(bool reset) {
+  external_clock_ = true;
+  if (reset) {
+    // The first tick after a start is the downbeat.
+    clock_ticks_ = -1;
+    beat_position_ = 0.0f;
+    for (size_t i = 0; i < kNumLfos; ++i) {
+      lfo_phase_[i] = 0.0f;
+    }
+  }
+}
+
+void Modulator::ClockStop() {
+  external_clock_ = false;
+}
+
+void Modulator::ClockTick() {
+  if (!external_clock_) {
+    return;
+  }
+  ++clock_ticks_;
+  if (clock_ticks_ >= kClockWrapBeats * kClockTicksPerBeat) {
+    clock_ticks_ = 0;
+  }
+  beat_position_ = static_cast<float>(clock_ticks_) / kClockTicksPerBeat;
+}
+
+void Modulator::ProcessClock(size_t size) {
+  beat_position_ += tempo_ / 60.0f * size / kSampleRate;
+  if (external_clock_) {
+    // Runs at the estimated tempo between ticks, but never ahead of the clock.
+    float next_tick = static_cast<float>(clock_ticks_ + 1) / kClockTicksPerBeat;
+    if (beat_position_ > next_tick) {
+      beat_position_ = next_tick;
+    }
+  } else if (beat_position_ >= kClockWrapBeats) {
+    beat_position_ -= kClockWrapBeats;
+  }
+}
+
+float Modulator::ProcessLfo(
+    const LfoSettings& settings,
+    size_t index,
+    size_t size) {
+  float phase;
+  if (settings.division != LFO_DIVISION_FREE &&
+      settings.division < LFO_DIVISION_LAST) {
+    phase = beat_position_ / kLfoDivisionBeats[settings.division];
+    phase -= static_cast<int32_t>(phase);
+  } else {
+    // 0.05 Hz to 20 Hz.
+    float frequency = 0.05f * SemitonesToRatio(settings.rate * 103.7f);
+    phase = lfo_phase_[index] + frequency * size / kSampleRate;
+    if (phase >= 1.0f) {
+      phase -= 1.0f;
+    }
+  }
+  if (phase < lfo_phase_[index]) {
+    lfo_random_value_[index] = 2.0f * Random::GetFloat() - 1.0f;
+  }
+  lfo_phase_[index] = phase;
+
+  switch (settings.shape) {
+    case LFO_SHAPE_TRIANGLE:
+      return phase < 0.5f ? 4.0f * phase - 1.0f : 3.0f - 4.0f * phase;
+    case LFO_SHAPE_SAW:
+      return 2.0f * phase - 1.0f;
+    case LFO_SHAPE_SQUARE:
+      return phase < 0.5f ? 1.0f : -1.0f;
+    case LFO_SHAPE_RANDOM:
+      return lfo_random_value_[index];
+    default:
+      return InterpolateWrap(lut_sine, phase, 4096.0f);
+  }
+}
+
+float Modulator::ProcessEnvelope(
+    const ModulationSettings& settings,
+    bool gate,
+    size_t size) {
+  envelope_.set_adsr(
+      settings.envelope_attack,
+      settings.envelope_decay,
+      settings.envelope_sustain,
+      settings.envelope_release);
+
+  uint8_t flags = gate ? ENVELOPE_FLAG_GATE : 0;
+  if (gate && !previous_gate_) {
+    flags |= ENVELOPE_FLAG_RISING_EDGE;
+  } else if (!gate && previous_gate_) {
+    flags |= ENVELOPE_FLAG_FALLING_EDGE;
+  }
+  previous_gate_ = gate;
+
+  // The envelope times are calibrated per sample, so it runs at audio rate
+  // even though only the last value of the block is used.
+  float value = envelope_.Process(flags);
+  for (size_t i = 1; i < size; ++i) {
+    value = envelope_.Process(gate ? ENVELOPE_FLAG_GATE : 0);
+  }
+  return value;
+}
+
+void Modulator::Process(
+    const ModulationSettings& settings,
+    const PerformanceState& performance_state,
+    const Patch& base,
+    Patch* patch,
+    size_t size) {
+  ProcessClock(size);
+
+  float sources[MODULATION_SOURCE_LAST];
+  sources[MODULATION_SOURCE_NONE] = 0.0f;
+  sources[MODULATION_SOURCE_LFO_1] = ProcessLfo(settings.lfo[0], 0, size);
+  sources[MODULATION_SOURCE_LFO_2] = ProcessLfo(settings.lfo[1], 1, size);
+  sources[MODULATION_SOURCE_ENVELOPE] = ProcessEnvelope(
+      settings, performance_state.gate, size);
+  sources[MODULATION_SOURCE_VELOCITY] = performance_state.strength;
+  sources[MODULATION_SOURCE_AFTERTOUCH] = aftertouch_;
+  sources[MODULATION_SOURCE_MOD_WHEEL] = mod_wheel_;
+  sources[MODULATION_SOURCE_NOTE] = performance_state.note / 127.0f;
+  sources[MODULATION_SOURCE_INPUT] = input_level_;
+
+  *patch = base;
+  float* parameters = reinterpret_cast<float*>(patch);
+  for (size_t i = 0; i < kNumPatchParameters; ++i) {
+    if (lock_mask_ & (1 << i)) {
+      parameters[i] = lock_value_[i];
+    }
+  }
+  for (size_t i = 0; i < CONTROLLER_LAST; ++i) {
+    size_t parameter = controller_parameter_[i];
+    if (parameter >= kNumPatchParameters) {
+      continue;
+    }
+    float range = kParameterRange[parameter];
+    float value = parameters[parameter] + controller_amount_[i] * range;
+    CONSTRAIN(value, 0.0f, range);
+    parameters[parameter] = value;
+  }
+  for (size_t i = 0; i < kNumModulationSlots; ++i) {
+    const ModulationSlot& slot = settings.slot[i];
+    if (slot.source == MODULATION_SOURCE_NONE ||
+        slot.source >= MODULATION_SOURCE_LAST ||
+        slot.destination >= kNumPatchParameters) {
+      continue;
+    }
+    float range = kParameterRange[slot.destination];
+    float value = parameters[slot.destination];
+    value += sources[slot.source] * slot.amount * range;
+    CONSTRAIN(value, 0.0f, range);
+    parameters[slot.destination] = value;
+  }
+  for (size_t i = 0; i < kNumVelocitySlots; ++i) {
+    const VelocitySlot& slot = settings.velocity[i];
+    if (slot.amount == 0.0f || slot.destination >= kNumPatchParameters) {
+      continue;
+    }
+    float range = kParameterRange[slot.destination];
+    float value = parameters[slot.destination];
+    value += sources[MODULATION_SOURCE_VELOCITY] * slot.amount * range;
+    CONSTRAIN(value, 0.0f, range);
+    parameters[slot.destination] = value;
+  }
+}
+
+}  // namespace elements
@@ -0,0 +1,191 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+//
+// -----------------------------------------------------------------------------
+//
+// Modulation matrix applied to the patch once per audio block.
+
+#ifndef ELEMENTS_MODULATION_H_
+#define ELEMENTS_MODULATION_H_
+
+#include "stmlib/stmlib.h"
+
+#include "elements/dsp/multistage_envelope.h"
+#include "elements/dsp/part.h"
+#include "elements/dsp/patch.h"
+
+namespace elements {
+
+enum ModulationSource {
+  MODULATION_SOURCE_NONE,
+  MODULATION_SOURCE_LFO_1,
+  MODULATION_SOURCE_LFO_2,
+  MODULATION_SOURCE_ENVELOPE,
+  MODULATION_SOURCE_VELOCITY,
+  MODULATION_SOURCE_AFTERTOUCH,
+  MODULATION_SOURCE_MOD_WHEEL,
+  MODULATION_SOURCE_NOTE,
+  MODULATION_SOURCE_INPUT,
+  MODULATION_SOURCE_LAST
+};
+
+enum LfoShape {
+  LFO_SHAPE_SINE,
+  LFO_SHAPE_TRIANGLE,
+  LFO_SHAPE_SAW,
+  LFO_SHAPE_SQUARE,
+  LFO_SHAPE_RANDOM,
+  LFO_SHAPE_LAST
+};
+
+// Note divisions for LFOs synced to the clock.
+enum LfoDivision {
+  LFO_DIVISION_FREE,
+  LFO_DIVISION_2_1,
+  LFO_DIVISION_1_1,
+  LFO_DIVISION_1_2,
+  LFO_DIVISION_1_2_TRIPLET,
+  LFO_DIVISION_1_4_DOTTED,
+  LFO_DIVISION_1_4,
+  LFO_DIVISION_1_4_TRIPLET,
+  LFO_DIVISION_1_8_DOTTED,
+  LFO_DIVISION_1_8,
+  LFO_DIVISION_1_8_TRIPLET,
+  LFO_DIVISION_1_16_DOTTED,
+  LFO_DIVISION_1_16,
+  LFO_DIVISION_1_16_TRIPLET,
+  LFO_DIVISION_1_32,
+  LFO_DIVISION_LAST
+};
+
+// Performance controls routed to a single parameter.
+enum Controller {
+  CONTROLLER_SLIDE,
+  CONTROLLER_PRESSURE,
+  CONTROLLER_LAST
+};
+
+const size_t kNumLfos = 2;
+const int32_t kClockTicksPerBeat = 24;
+const size_t kNumModulationSlots = 8;
+const size_t kNumVelocitySlots = 2;
+const size_t kNumPatchParameters = sizeof(Patch) / sizeof(float);
+
+struct LfoSettings {
+  float rate;  // 0.0 to 1.0, from 0.05 Hz to 20 Hz.
+  uint32_t shape;
+  uint32_t division;  // LFO_DIVISION_FREE runs at rate, others follow the clock.
+};
+
+struct ModulationSlot {
+  uint32_t source;
+  uint32_t destination;  // Index of the parameter in Patch.
+  float amount;  // -1.0 to 1.0, relative to the range of the parameter.
+};
+
+// Scales a parameter with the velocity, on top of the modulation slots.
+struct VelocitySlot {
+  uint32_t destination;  // Index of the parameter in Patch.
+  float amount;  // -1.0 to 1.0, 0.0 disables the slot.
+};
+
+struct ModulationSettings {
+  LfoSettings lfo[kNumLfos];
+  float envelope_attack;
+  float envelope_decay;
+  float envelope_sustain;
+  float envelope_release;
+  ModulationSlot slot[kNumModulationSlots];
+  VelocitySlot velocity[kNumVelocitySlots];
+};
+
+class Modulator {
+ public:
+  Modulator() { }
+  ~Modulator() { }
+
+  void Init(ModulationSettings* settings);
+
+  // Writes the base patch with all modulations applied to patch.
+  void Process(
+      const ModulationSettings& settings,
+      const PerformanceState& performance_state,
+      const Patch& base,
+      Patch* patch,
+      size_t size);
+
+  inline void set_aftertouch(float aftertouch) { aftertouch_ = aftertouch; }
+  inline void set_mod_wheel(float mod_wheel) { mod_wheel_ = mod_wheel; }
+  inline void set_input_level(float input_level) { input_level_ = input_level; }
+  inline void set_tempo(float tempo) { tempo_ = tempo; }
+
+  // MIDI clock, 24 ticks per beat. Without a running external clock, synced
+  // LFOs follow the last tempo.
+  void ClockStart(bool reset);
+  void ClockStop();
+  void ClockTick();
+
+  // Parameter locks of sequencer steps replace the base value of a parameter
+  // until cleared, modulations still apply on top of them.
+  void Lock(size_t parameter, float value);
+  inline void ClearLocks() { lock_mask_ = 0; }
+
+  // Each controller moves one parameter, amount is -1.0 to 1.0 relative to its
+  // range. Parameters past the patch disable the controller.
+  inline void set_controller(size_t controller, size_t parameter, float amount) {
+    if (controller < CONTROLLER_LAST) {
+      controller_parameter_[controller] = parameter;
+      controller_amount_[controller] = amount;
+    }
+  }
+
+ private:
+  void ProcessClock(size_t size);
+  float ProcessLfo(const LfoSettings& settings, size_t index, size_t size);
+  float ProcessEnvelope(
+      const ModulationSettings& settings,
+      bool gate,
+      size_t size);
+
+  float lfo_phase_[kNumLfos];
+  float lfo_random_value_[kNumLfos];
+  MultistageEnvelope envelope_;
+  bool previous_gate_;
+
+  float aftertouch_;
+  float mod_wheel_;
+  float input_level_;
+
+  float tempo_;
+  bool external_clock_;
+  int32_t clock_ticks_;
+  float beat_position_;
+
+  uint32_t lock_mask_;
+  float lock_value_[kNumPatchParameters];
+
+  size_t controller_parameter_[CONTROLLER_LAST];
+  float controller_amount_[CONTROLLER_LAST];
+
+  DISALLOW_COPY_AND_ASSIGN(Modulator);
+};
+
+}  // namespace elements
+
+#endif  // ELEMENTS_MODULATION_H_
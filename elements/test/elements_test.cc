@@ -366,6 +366,148 @@ void TestPart() {
 }
 
 
+const float kChord[] = { 48.0f, 55.0f, 60.0f, 64.0f };
+
+void InitChordPart(Part* part, uint16_t* reverb_buffer) {
+  part->Init(reverb_buffer);
+
+  Patch* p = part->mutable_patch();
+  p->exciter_envelope_shape = 0.0f;
+  p->exciter_strike_level = 0.5f;
+  p->exciter_strike_meta = 0.5f;
+  p->exciter_strike_timbre = 0.3f;
+  p->resonator_geometry = 0.4f;
+  p->resonator_brightness = 0.7f;
+  p->resonator_damping = 0.8f;
+  p->resonator_position = 0.3f;
+  p->space = 0.3f;
+}
+
+void InitChordVoices(PerformanceState* voices) {
+  for (size_t v = 0; v < kNumVoices; ++v) {
+    voices[v].note = kChord[v % 4];
+    voices[v].modulation = 0.0f;
+    voices[v].strength = 0.5f;
+    voices[v].gate = false;
+  }
+}
+
+// Build with NUM_VOICES=4 MAX_MODES=16 to hear a chord, each voice is released
+// and restarted on its own.
+void TestPolyphony() {
+  FILE* fp = fopen("elements_polyphony.wav", "wb");
+  write_wav_header(fp, ::kSampleRate * 10, 2);
+
+  uint16_t reverb_buffer[32768];
+  Part part;
+  InitChordPart(&part, reverb_buffer);
+
+  PerformanceState voices[kNumVoices];
+  InitChordVoices(voices);
+
+  float silence[16];
+  std::fill(&silence[0], &silence[16], 0.0f);
+
+  for (uint32_t i = 0; i < ::kSampleRate * 10; i += 16) {
+    // Voices come in one after the other every 250ms, then all of them are
+    // held for a second and released.
+    uint32_t t = i % (::kSampleRate * 2);
+    for (size_t v = 0; v < kNumVoices; ++v) {
+      voices[v].gate = t >= v * ::kSampleRate / 4 && t < ::kSampleRate * 3 / 2;
+    }
+
+    float main[16];
+    float aux[16];
+    part.Process(voices, silence, silence, main, aux, 16);
+
+    for (size_t j = 0; j < 16; ++j) {
+      float output[2];
+      short output_sample[2];
+      output[0] = main[j];
+      output[1] = aux[j];
+
+      for (int k = 0; k < 2; ++k) {
+        output[k] *= 32767.0f;
+        if (output[k] > 32767) output[k] = 32767;
+        if (output[k] < -32767) output[k] = -32767;
+        output_sample[k] = output[k];
+      }
+      fwrite(output_sample, sizeof(int16_t), 2, fp);
+    }
+  }
+  fclose(fp);
+}
+
+const size_t kCheckSize = ::kSampleRate * 2;
+
+// Renders a second with only `voice` held, main and aux interleaved.
+void RenderVoice(size_t voice, float* output) {
+  uint16_t reverb_buffer[32768];
+  Part part;
+  InitChordPart(&part, reverb_buffer);
+
+  PerformanceState voices[kNumVoices];
+  InitChordVoices(voices);
+  voices[voice].gate = true;
+
+  float silence[16];
+  std::fill(&silence[0], &silence[16], 0.0f);
+
+  for (size_t i = 0; i < kCheckSize; i += 32) {
+    float main[16];
+    float aux[16];
+    part.Process(voices, silence, silence, main, aux, 16);
+    for (size_t j = 0; j < 16; ++j) {
+      output[i + 2 * j] = main[j];
+      output[i + 2 * j + 1] = aux[j];
+    }
+  }
+}
+
+// Mean square of a - b, or of a alone when b is NULL.
+float MeanSquare(const float* a, const float* b) {
+  float sum = 0.0f;
+  for (size_t i = 0; i < kCheckSize; ++i) {
+    float x = b ? a[i] - b[i] : a[i];
+    sum += x * x;
+  }
+  return sum / kCheckSize;
+}
+
+// Every voice has to be heard on its own and sound different from the others,
+// a voice that stays silent or copies another one fails. Build with
+// NUM_VOICES=4 to check the four voices of the chord.
+void CheckPolyphony() {
+  static float output[kNumVoices][kCheckSize];
+  float level[kNumVoices];
+  bool failed = false;
+
+  for (size_t v = 0; v < kNumVoices; ++v) {
+    RenderVoice(v, output[v]);
+    level[v] = MeanSquare(output[v], NULL);
+    printf("Voice %d: note %.0f, level %.6f\n", int(v), kChord[v % 4], level[v]);
+    if (level[v] < 1e-6f) {
+      printf("Voice %d is silent\n", int(v));
+      failed = true;
+    }
+  }
+
+  for (size_t v = 0; v < kNumVoices; ++v) {
+    for (size_t w = v + 1; w < kNumVoices; ++w) {
+      // close to 1 for unrelated signals, 0 for the same one
+      float difference = MeanSquare(output[v], output[w]) / (level[v] + level[w]);
+      if (!(difference > 0.5f)) {
+        printf("Voices %d and %d sound the same\n", int(v), int(w));
+        failed = true;
+      }
+    }
+  }
+
+  if (failed) {
+    exit(1);
+  }
+}
+
 void TestEasterEgg() {
   FILE* fp = fopen("elements_easter_egg.wav", "wb");
   write_wav_header(fp, ::kSampleRate * 20, 2);
@@ -495,6 +637,8 @@ int main(void) {
   _MM_SET_FLUSH_ZERO_MODE(_MM_FLUSH_ZERO_ON);
   // TestFilterAccuracy();
   TestPart();
+  TestPolyphony();
+  CheckPolyphony();
   // TestExciter();
   // TestResonator();
   // TestEasterEgg();
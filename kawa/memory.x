@@ -3,11 +3,16 @@ MEMORY
   /* NOTE 1 K = 1 KiBi = 1024 bytes */
   /* TODO Adjust these memory regions to match your device memory layout */
   /* These values correspond to the LM3S6965, one of the few devices QEMU can emulate */
-  FLASH : ORIGIN = 0x08000000, LENGTH = 1024K
+  FLASH : ORIGIN = 0x08000000, LENGTH = 512K /* sector 8 holds the samples, 9 the tunings, the last two sectors the patches and settings */
   RAM : ORIGIN = 0x20000000, LENGTH = 128K
   CCMRAM : ORIGIN = 0x10000000, LENGTH = 64K
 }
 
+/* The stores start at sector 8. An image growing into them fails the link here instead
+   of being erased by the first save. */
+ASSERT(__sidata + (__edata - __sdata) <= ORIGIN(FLASH) + LENGTH(FLASH),
+       "the firmware overlaps the flash sectors of the stores, see memory.x");
+
 SECTIONS {
 
   .init_array (NOLOAD) :
@@ -0,0 +1,430 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// Plays the held notes one by one on the clock. It only produces events, so the note
+// stream can be checked without the synth.
+
+use crate::midi_clock::DIVISION_TICKS;
+use alloc::vec::Vec;
+
+pub const MODE_OFF: u32 = 0;
+pub const MODE_UP: u32 = 1;
+pub const MODE_DOWN: u32 = 2;
+pub const MODE_UP_DOWN: u32 = 3;
+pub const MODE_RANDOM: u32 = 4;
+pub const MODE_AS_PLAYED: u32 = 5;
+pub const MODE_LAST: u32 = 6;
+
+pub const MIN_OCTAVES: u32 = 1;
+pub const MAX_OCTAVES: u32 = 4;
+// gate length in percent of the step, the full step ties the notes
+pub const MIN_GATE: u32 = 5;
+pub const MAX_GATE: u32 = 100;
+
+const DEFAULT_DIVISION: u32 = 10;
+
+const MAX_HELD_NOTES: usize = 16;
+const MAX_NOTE: u8 = 127;
+const OCTAVE: u8 = 12;
+
+#[derive(Clone, Copy)]
+pub struct ArpSettings {
+    pub mode: u32,
+    pub octaves: u32,
+    pub gate: u32,
+    // index in DIVISION_TICKS
+    pub division: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArpEvent {
+    NoteOn { note: u8, velocity: u8 },
+    NoteOff,
+}
+
+#[derive(Clone, Copy)]
+struct HeldNote {
+    note: u8,
+    velocity: u8,
+}
+
+pub struct Arpeggiator {
+    pub settings: ArpSettings,
+    // in the order they were played
+    held: Vec<HeldNote>,
+    // ordered by pitch
+    sorted: Vec<HeldNote>,
+    step: usize,
+    ticks_to_step: u32,
+    gate_on: bool,
+    gate_off_at: Option<u32>,
+    random: u32,
+}
+
+impl Default for Arpeggiator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Arpeggiator {
+    pub fn new() -> Self {
+        Arpeggiator {
+            settings: ArpSettings {
+                mode: MODE_OFF,
+                octaves: MIN_OCTAVES,
+                gate: 50,
+                division: DEFAULT_DIVISION,
+            },
+            held: Vec::with_capacity(MAX_HELD_NOTES),
+            sorted: Vec::with_capacity(MAX_HELD_NOTES),
+            step: 0,
+            ticks_to_step: 0,
+            gate_on: false,
+            gate_off_at: None,
+            random: 0x1234_5678,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.settings.mode != MODE_OFF
+    }
+
+    // Whether the key is held for the arpeggio, keys pressed before it was turned on play
+    // their own voices
+    pub fn holds(&self, note: u8) -> bool {
+        self.held.iter().any(|held| held.note == note)
+    }
+
+    // Forgets the held keys, so the notes played in the old mode end with their keys
+    pub fn set_mode(&mut self, mode: u32) -> Option<ArpEvent> {
+        self.settings.mode = mode;
+        self.held.clear();
+        self.sorted.clear();
+        self.reset();
+        self.stop()
+    }
+
+    pub fn note_on(&mut self, note: u8, velocity: u8) {
+        if self.held.is_empty() {
+            self.reset();
+        }
+
+        let held = HeldNote { note, velocity };
+        if let Some(index) = self.held.iter().position(|held| held.note == note) {
+            self.held[index] = held;
+        } else if self.held.len() < MAX_HELD_NOTES {
+            self.held.push(held);
+        } else {
+            return;
+        }
+
+        self.sorted.retain(|held| held.note != note);
+        let index = self
+            .sorted
+            .iter()
+            .position(|held| held.note > note)
+            .unwrap_or(self.sorted.len());
+        self.sorted.insert(index, held);
+    }
+
+    pub fn note_off(&mut self, note: u8) -> Option<ArpEvent> {
+        self.held.retain(|held| held.note != note);
+        self.sorted.retain(|held| held.note != note);
+        if self.held.is_empty() {
+            self.stop()
+        } else {
+            None
+        }
+    }
+
+    // Starts the pattern over on the next clock tick
+    pub fn reset(&mut self) {
+        self.step = 0;
+        self.ticks_to_step = 0;
+    }
+
+    // Called on every clock tick at `now` milliseconds, `tick_ms` is the length of a tick
+    // at the current tempo
+    pub fn clock(&mut self, now: u32, tick_ms: f32) -> Option<ArpEvent> {
+        if !self.enabled() || self.held.is_empty() {
+            return self.stop();
+        }
+        if self.ticks_to_step > 0 {
+            self.ticks_to_step -= 1;
+            return None;
+        }
+
+        let step_ticks = DIVISION_TICKS[self.settings.division as usize % DIVISION_TICKS.len()];
+        self.ticks_to_step = step_ticks - 1;
+
+        self.gate_on = true;
+        self.gate_off_at = if self.settings.gate < MAX_GATE {
+            let gate_ms = step_ticks as f32 * tick_ms * self.settings.gate as f32 / 100.0;
+            Some(now.wrapping_add((gate_ms as u32).max(1)))
+        } else {
+            None
+        };
+
+        let held = self.next_note();
+        Some(ArpEvent::NoteOn {
+            note: held.note,
+            velocity: held.velocity,
+        })
+    }
+
+    // Called every millisecond to end the gate of the current step
+    pub fn update(&mut self, now: u32) -> Option<ArpEvent> {
+        match self.gate_off_at {
+            Some(gate_off_at) if now.wrapping_sub(gate_off_at) as i32 >= 0 => self.stop(),
+            _ => None,
+        }
+    }
+
+    fn stop(&mut self) -> Option<ArpEvent> {
+        self.gate_off_at = None;
+        if self.gate_on {
+            self.gate_on = false;
+            Some(ArpEvent::NoteOff)
+        } else {
+            None
+        }
+    }
+
+    fn next_note(&mut self) -> HeldNote {
+        let count = self.held.len();
+        let octaves = self.settings.octaves.clamp(MIN_OCTAVES, MAX_OCTAVES) as usize;
+        let length = count * octaves;
+
+        let index = match self.settings.mode {
+            MODE_DOWN => length - 1 - self.step % length,
+            MODE_UP_DOWN if length > 1 => {
+                // the top and bottom notes are not repeated
+                let cycle = 2 * length - 2;
+                let position = self.step % cycle;
+                if position < length {
+                    position
+                } else {
+                    cycle - position
+                }
+            }
+            MODE_RANDOM => self.next_random() as usize % length,
+            _ => self.step % length,
+        };
+        self.step = self.step.wrapping_add(1);
+
+        let held = if self.settings.mode == MODE_AS_PLAYED {
+            self.held[index % count]
+        } else {
+            self.sorted[index % count]
+        };
+        let transpose = OCTAVE as usize * (index / count);
+        HeldNote {
+            note: (held.note as usize + transpose).min(MAX_NOTE as usize) as u8,
+            velocity: held.velocity,
+        }
+    }
+
+    // xorshift32
+    fn next_random(&mut self) -> u32 {
+        let mut x = self.random;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.random = x;
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1/32, three ticks a step
+    const SHORT_DIVISION: u32 = 12;
+    const TICK_MS: f32 = 10.0;
+
+    fn arpeggiator(mode: u32, octaves: u32) -> Arpeggiator {
+        let mut arp = Arpeggiator::new();
+        arp.settings = ArpSettings {
+            mode,
+            octaves,
+            gate: MAX_GATE,
+            division: SHORT_DIVISION,
+        };
+        arp
+    }
+
+    // Clocks `steps` steps and returns the notes started
+    fn play(arp: &mut Arpeggiator, steps: usize) -> Vec<u8> {
+        let step_ticks = DIVISION_TICKS[SHORT_DIVISION as usize] as usize;
+        (0..steps * step_ticks)
+            .filter_map(|tick| match arp.clock(tick as u32 * 10, TICK_MS) {
+                Some(ArpEvent::NoteOn { note, .. }) => Some(note),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn up_plays_the_keys_by_pitch() {
+        let mut arp = arpeggiator(MODE_UP, 1);
+        arp.note_on(64, 100);
+        arp.note_on(60, 100);
+        arp.note_on(67, 100);
+        assert_eq!(play(&mut arp, 6), [60, 64, 67, 60, 64, 67]);
+    }
+
+    #[test]
+    fn down_starts_from_the_top() {
+        let mut arp = arpeggiator(MODE_DOWN, 1);
+        arp.note_on(60, 100);
+        arp.note_on(64, 100);
+        arp.note_on(67, 100);
+        assert_eq!(play(&mut arp, 4), [67, 64, 60, 67]);
+    }
+
+    #[test]
+    fn up_down_does_not_repeat_the_ends() {
+        let mut arp = arpeggiator(MODE_UP_DOWN, 1);
+        arp.note_on(60, 100);
+        arp.note_on(64, 100);
+        arp.note_on(67, 100);
+        assert_eq!(play(&mut arp, 6), [60, 64, 67, 64, 60, 64]);
+    }
+
+    #[test]
+    fn as_played_keeps_the_key_order() {
+        let mut arp = arpeggiator(MODE_AS_PLAYED, 1);
+        arp.note_on(67, 100);
+        arp.note_on(60, 100);
+        arp.note_on(64, 100);
+        assert_eq!(play(&mut arp, 3), [67, 60, 64]);
+    }
+
+    #[test]
+    fn random_plays_only_held_keys() {
+        let mut arp = arpeggiator(MODE_RANDOM, 1);
+        arp.note_on(60, 100);
+        arp.note_on(64, 100);
+        assert!(play(&mut arp, 32)
+            .iter()
+            .all(|&note| note == 60 || note == 64));
+    }
+
+    #[test]
+    fn octaves_repeat_the_keys_higher() {
+        let mut arp = arpeggiator(MODE_UP, 3);
+        arp.note_on(60, 100);
+        arp.note_on(64, 100);
+        assert_eq!(play(&mut arp, 6), [60, 64, 72, 76, 84, 88]);
+    }
+
+    #[test]
+    fn octaves_stop_at_the_top_note() {
+        let mut arp = arpeggiator(MODE_UP, 2);
+        arp.note_on(120, 100);
+        assert_eq!(play(&mut arp, 2), [120, 127]);
+    }
+
+    #[test]
+    fn repeated_key_takes_the_new_velocity() {
+        let mut arp = arpeggiator(MODE_UP, 1);
+        arp.note_on(60, 100);
+        arp.note_on(60, 20);
+        assert_eq!(
+            arp.clock(0, TICK_MS),
+            Some(ArpEvent::NoteOn {
+                note: 60,
+                velocity: 20
+            })
+        );
+        assert_eq!(play(&mut arp, 2), [60, 60]);
+    }
+
+    #[test]
+    fn released_key_leaves_the_pattern() {
+        let mut arp = arpeggiator(MODE_UP, 1);
+        arp.note_on(60, 100);
+        arp.note_on(64, 100);
+        assert_eq!(play(&mut arp, 1), [60]);
+        assert_eq!(arp.note_off(64), None);
+        assert_eq!(play(&mut arp, 2), [60, 60]);
+    }
+
+    #[test]
+    fn first_key_starts_the_pattern_over() {
+        let mut arp = arpeggiator(MODE_UP, 1);
+        arp.note_on(60, 100);
+        arp.note_on(64, 100);
+        assert_eq!(play(&mut arp, 1), [60]);
+        assert_eq!(arp.note_off(60), None);
+        assert_eq!(arp.note_off(64), Some(ArpEvent::NoteOff));
+        arp.note_on(67, 100);
+        arp.note_on(72, 100);
+        assert_eq!(play(&mut arp, 2), [67, 72]);
+    }
+
+    #[test]
+    fn gate_ends_the_note_within_the_step() {
+        let mut arp = arpeggiator(MODE_UP, 1);
+        arp.settings.gate = 50;
+        arp.note_on(60, 100);
+        assert!(arp.clock(0, TICK_MS).is_some());
+        // half of three ticks of 10ms
+        assert_eq!(arp.update(14), None);
+        assert_eq!(arp.update(15), Some(ArpEvent::NoteOff));
+        assert_eq!(arp.update(16), None);
+    }
+
+    #[test]
+    fn full_gate_ties_the_notes() {
+        let mut arp = arpeggiator(MODE_UP, 1);
+        arp.note_on(60, 100);
+        assert!(arp.clock(0, TICK_MS).is_some());
+        assert_eq!(arp.update(1_000), None);
+    }
+
+    #[test]
+    fn off_plays_nothing() {
+        let mut arp = arpeggiator(MODE_OFF, 1);
+        arp.note_on(60, 100);
+        assert!(play(&mut arp, 4).is_empty());
+    }
+
+    #[test]
+    fn mode_change_forgets_the_held_keys() {
+        let mut arp = arpeggiator(MODE_UP, 1);
+        arp.note_on(60, 100);
+        assert!(arp.clock(0, TICK_MS).is_some());
+        assert!(arp.holds(60));
+        assert_eq!(arp.set_mode(MODE_DOWN), Some(ArpEvent::NoteOff));
+        assert!(!arp.holds(60));
+        assert!(play(&mut arp, 2).is_empty());
+        assert_eq!(arp.set_mode(MODE_OFF), None);
+    }
+
+    #[test]
+    fn keys_held_before_turning_on_are_not_held() {
+        let mut arp = arpeggiator(MODE_OFF, 1);
+        arp.set_mode(MODE_UP);
+        assert!(!arp.holds(60));
+        assert_eq!(arp.note_off(60), None);
+    }
+}
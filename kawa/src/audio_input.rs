@@ -0,0 +1,65 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// Settings of the codec input processing, the synth applies gain and gate to each
+// channel and routes them to the exciter inputs. The engine gets them through
+// elements_handlers::InputProcessing.
+
+pub const ROUTING_STEREO: u32 = 0;
+pub const ROUTING_MONO: u32 = 1;
+pub const ROUTING_OFF: u32 = 2;
+pub const ROUTING_LAST: u32 = 3;
+
+pub const CHANNEL_LEFT: usize = 0;
+pub const CHANNEL_RIGHT: usize = 1;
+pub const NUM_CHANNELS: usize = 2;
+
+// in dB
+pub const MAX_GAIN: u32 = 40;
+// The gate closes below the threshold, set in dB above this level in dBFS
+pub const THRESHOLD_FLOOR: i32 = -80;
+pub const MAX_THRESHOLD: u32 = 80;
+pub const DEFAULT_THRESHOLD: u32 = 40;
+
+pub struct AudioInput {
+    pub routing: u32,
+    pub gain: [u32; NUM_CHANNELS],
+    pub threshold: [u32; NUM_CHANNELS],
+}
+
+impl Default for AudioInput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioInput {
+    pub fn new() -> Self {
+        AudioInput {
+            routing: ROUTING_STEREO,
+            gain: [0; NUM_CHANNELS],
+            threshold: [DEFAULT_THRESHOLD; NUM_CHANNELS],
+        }
+    }
+
+    // in dBFS
+    pub fn threshold_level(&self, channel: usize) -> i32 {
+        THRESHOLD_FLOOR + self.threshold[channel] as i32
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use hal::stm32;
+use stm32::FLASH;
+use stm32f4xx_hal as hal;
+
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xCDEF_89AB;
+// program 32 bits at a time, valid for 2.7V-3.6V supply
+const PSIZE_X32: u8 = 0b10;
+// error flags of the status register, cleared by writing 1
+const SR_WRPERR: u32 = 1 << 4;
+const SR_PGAERR: u32 = 1 << 5;
+const SR_PGPERR: u32 = 1 << 6;
+const SR_PGSERR: u32 = 1 << 7;
+const SR_ERRORS: u32 = SR_WRPERR | SR_PGAERR | SR_PGPERR | SR_PGSERR;
+
+// Why an erase or program operation failed, the flash may be partly written
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    WriteProtection,
+    Alignment,
+    Parallelism,
+    Sequence,
+}
+
+// Sectors at the end of the flash are kept out of the firmware area in memory.x
+#[derive(Clone, Copy)]
+pub struct Sector {
+    pub number: u8,
+    pub address: u32,
+    pub size: usize,
+}
+
+// Patches are written alternately to these two sectors
+pub const PATCH_SECTORS: [Sector; 2] = [
+    Sector {
+        number: 10,
+        address: 0x080C_0000,
+        size: 128 * 1024,
+    },
+    Sector {
+        number: 11,
+        address: 0x080E_0000,
+        size: 128 * 1024,
+    },
+];
+
+// User strike samples, rewritten as a whole
+pub const SAMPLE_SECTOR: Sector = Sector {
+    number: 8,
+    address: 0x0808_0000,
+    size: 128 * 1024,
+};
+
+// Tuning tables, rewritten as a whole
+pub const TUNING_SECTOR: Sector = Sector {
+    number: 9,
+    address: 0x080A_0000,
+    size: 128 * 1024,
+};
+
+// The stores of the different sectors share the flash interface
+pub type SharedFlash = Rc<RefCell<Flash>>;
+
+pub struct Flash {
+    flash: FLASH,
+}
+
+impl Flash {
+    pub fn new(flash: FLASH) -> Self {
+        Flash { flash }
+    }
+
+    pub fn read(&self, sector: Sector) -> &'static [u8] {
+        unsafe { core::slice::from_raw_parts(sector.address as *const u8, sector.size) }
+    }
+
+    // The CPU stalls on flash access during the erase, so pause the synth around this.
+    pub fn erase(&mut self, sector: Sector) -> Result<(), Error> {
+        self.unlock();
+        let result = self.erase_sector(sector.number);
+        self.lock();
+        result
+    }
+
+    // Programs erased flash at the word aligned offset, padding data with 0xFF to a whole word
+    pub fn program(&mut self, sector: Sector, offset: usize, data: &[u8]) -> Result<(), Error> {
+        assert!(offset % 4 == 0 && offset + data.len() <= sector.size);
+
+        self.unlock();
+        self.flash
+            .cr
+            .modify(|_, w| unsafe { w.psize().bits(PSIZE_X32).pg().set_bit() });
+        let result = self.program_words(sector.address as usize + offset, data);
+        self.flash.cr.modify(|_, w| w.pg().clear_bit());
+        self.lock();
+        result
+    }
+
+    // Stops at the first word that fails
+    fn program_words(&mut self, address: usize, data: &[u8]) -> Result<(), Error> {
+        for (index, chunk) in data.chunks(4).enumerate() {
+            let mut word = [0xFFu8; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            unsafe {
+                core::ptr::write_volatile(
+                    (address + index * 4) as *mut u32,
+                    u32::from_le_bytes(word),
+                );
+            }
+            self.wait()?;
+        }
+        Ok(())
+    }
+
+    fn erase_sector(&mut self, sector_number: u8) -> Result<(), Error> {
+        self.wait()?;
+        self.flash.cr.modify(|_, w| unsafe {
+            w.psize()
+                .bits(PSIZE_X32)
+                .ser()
+                .set_bit()
+                .snb()
+                .bits(sector_number)
+        });
+        self.flash.cr.modify(|_, w| w.strt().set_bit());
+        let result = self.wait();
+        self.flash.cr.modify(|_, w| w.ser().clear_bit());
+        result
+    }
+
+    fn unlock(&mut self) {
+        // errors left by an earlier operation would block this one
+        self.flash.sr.write(|w| unsafe { w.bits(SR_ERRORS) });
+        if self.flash.cr.read().lock().bit_is_set() {
+            self.flash.keyr.write(|w| unsafe { w.bits(KEY1) });
+            self.flash.keyr.write(|w| unsafe { w.bits(KEY2) });
+        }
+    }
+
+    fn lock(&mut self) {
+        self.flash.cr.modify(|_, w| w.lock().set_bit());
+    }
+
+    // Waits for the operation to end and reports its errors, their flags are cleared
+    fn wait(&self) -> Result<(), Error> {
+        while self.flash.sr.read().bsy().bit_is_set() {}
+
+        let status = self.flash.sr.read().bits() & SR_ERRORS;
+        if status == 0 {
+            return Ok(());
+        }
+        self.flash.sr.write(|w| unsafe { w.bits(status) });
+        Err(if status & SR_WRPERR != 0 {
+            Error::WriteProtection
+        } else if status & SR_PGAERR != 0 {
+            Error::Alignment
+        } else if status & SR_PGPERR != 0 {
+            Error::Parallelism
+        } else {
+            Error::Sequence
+        })
+    }
+}
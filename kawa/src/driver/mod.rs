@@ -18,3 +18,6 @@
 // along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
 
 pub mod encoder;
+pub mod flash;
+pub mod ticker;
+pub mod usb_midi;
@@ -0,0 +1,67 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use hal::rcc::Clocks;
+use hal::stm32;
+use stm32::{RCC, TIM7};
+use stm32f4xx_hal as hal;
+
+pub const TICKS_PER_SECOND: u32 = 1000;
+
+static TICKS: AtomicU32 = AtomicU32::new(0);
+
+// Milliseconds since start, wraps after ~49 days so compare with wrapping_sub
+pub fn now() -> u32 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+pub struct Ticker {
+    tim: TIM7,
+}
+
+impl Ticker {
+    pub fn new(tim: TIM7, clocks: Clocks) -> Self {
+        let rcc = unsafe { &(*RCC::ptr()) };
+        rcc.apb1enr.modify(|_, w| w.tim7en().set_bit());
+
+        let timer_clock = if clocks.ppre1() == 1 {
+            clocks.pclk1().0
+        } else {
+            clocks.pclk1().0 * 2
+        };
+        let prescaler = timer_clock / 1_000_000 - 1;
+        let reload = 1_000_000 / TICKS_PER_SECOND - 1;
+
+        tim.cr1.modify(|_, w| w.cen().clear_bit());
+        tim.psc.write(|w| unsafe { w.bits(prescaler) });
+        tim.arr.write(|w| unsafe { w.bits(reload) });
+        tim.egr.write(|w| w.ug().set_bit());
+        tim.sr.modify(|_, w| w.uif().clear_bit());
+        tim.dier.write(|w| w.uie().set_bit());
+        tim.cr1.modify(|_, w| w.cen().set_bit());
+
+        Ticker { tim }
+    }
+
+    pub fn handle_irq(&mut self) {
+        self.tim.sr.modify(|_, w| w.uif().clear_bit());
+        TICKS.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,241 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// Class compliant USB MIDI device on the OTG FS port, with one cable in each direction.
+
+use crate::midi_output::OutputQueue;
+use crate::midi_packet::{self, Packet, PacketEncoder};
+use alloc::vec::Vec;
+use hal::otg_fs::{UsbBus, USB};
+use stm32f4xx_hal as hal;
+use usb_device::class_prelude::*;
+use usb_device::prelude::*;
+
+type UsbBusType = UsbBus<USB>;
+
+// shared pair for MIDI devices from obdev.at
+const VID_PID: UsbVidPid = UsbVidPid(0x16C0, 0x05E4);
+const MAX_PACKET_SIZE: u16 = 64;
+const PACKET_SIZE: usize = 4;
+const CABLE: u8 = 0;
+const CONTROL_CHANGE: u8 = 0xB0;
+
+const USB_CLASS_AUDIO: u8 = 0x01;
+const SUBCLASS_AUDIO_CONTROL: u8 = 0x01;
+const SUBCLASS_MIDI_STREAMING: u8 = 0x03;
+const CS_INTERFACE: u8 = 0x24;
+const CS_ENDPOINT: u8 = 0x25;
+const HEADER: u8 = 0x01;
+const MIDI_IN_JACK: u8 = 0x02;
+const MIDI_OUT_JACK: u8 = 0x03;
+const MS_GENERAL: u8 = 0x01;
+const JACK_EMBEDDED: u8 = 0x01;
+const JACK_EXTERNAL: u8 = 0x02;
+
+const EMBEDDED_IN_JACK: u8 = 1;
+const EXTERNAL_IN_JACK: u8 = 2;
+const EMBEDDED_OUT_JACK: u8 = 3;
+const EXTERNAL_OUT_JACK: u8 = 4;
+// the MIDI streaming header and the four jacks
+const MS_TOTAL_LENGTH: u16 = 7 + 6 + 6 + 9 + 9;
+
+static mut EP_MEMORY: [u32; 1024] = [0; 1024];
+static mut USB_BUS: Option<UsbBusAllocator<UsbBusType>> = None;
+
+pub struct MidiClass<'a, B: UsbBus> {
+    audio_control: InterfaceNumber,
+    midi_streaming: InterfaceNumber,
+    ep_out: EndpointOut<'a, B>,
+    ep_in: EndpointIn<'a, B>,
+}
+
+impl<'a, B: UsbBus> MidiClass<'a, B> {
+    pub fn new(alloc: &'a UsbBusAllocator<B>) -> Self {
+        MidiClass {
+            audio_control: alloc.interface(),
+            midi_streaming: alloc.interface(),
+            ep_out: alloc.bulk(MAX_PACKET_SIZE),
+            ep_in: alloc.bulk(MAX_PACKET_SIZE),
+        }
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for MidiClass<'_, B> {
+    fn get_configuration_descriptors(
+        &self,
+        writer: &mut DescriptorWriter,
+    ) -> usb_device::Result<()> {
+        writer.interface(
+            self.audio_control,
+            USB_CLASS_AUDIO,
+            SUBCLASS_AUDIO_CONTROL,
+            0,
+        )?;
+        writer.write(
+            CS_INTERFACE,
+            &[
+                HEADER,
+                0x00,
+                0x01,
+                0x09,
+                0x00,
+                0x01,
+                self.midi_streaming.into(),
+            ],
+        )?;
+
+        writer.interface(
+            self.midi_streaming,
+            USB_CLASS_AUDIO,
+            SUBCLASS_MIDI_STREAMING,
+            0,
+        )?;
+        writer.write(
+            CS_INTERFACE,
+            &[
+                HEADER,
+                0x00,
+                0x01,
+                MS_TOTAL_LENGTH as u8,
+                (MS_TOTAL_LENGTH >> 8) as u8,
+            ],
+        )?;
+        writer.write(
+            CS_INTERFACE,
+            &[MIDI_IN_JACK, JACK_EMBEDDED, EMBEDDED_IN_JACK, 0],
+        )?;
+        writer.write(
+            CS_INTERFACE,
+            &[MIDI_IN_JACK, JACK_EXTERNAL, EXTERNAL_IN_JACK, 0],
+        )?;
+        writer.write(
+            CS_INTERFACE,
+            &[
+                MIDI_OUT_JACK,
+                JACK_EMBEDDED,
+                EMBEDDED_OUT_JACK,
+                1,
+                EXTERNAL_IN_JACK,
+                1,
+                0,
+            ],
+        )?;
+        writer.write(
+            CS_INTERFACE,
+            &[
+                MIDI_OUT_JACK,
+                JACK_EXTERNAL,
+                EXTERNAL_OUT_JACK,
+                1,
+                EMBEDDED_IN_JACK,
+                1,
+                0,
+            ],
+        )?;
+
+        writer.endpoint(&self.ep_out)?;
+        writer.write(CS_ENDPOINT, &[MS_GENERAL, 1, EMBEDDED_IN_JACK])?;
+        writer.endpoint(&self.ep_in)?;
+        writer.write(CS_ENDPOINT, &[MS_GENERAL, 1, EMBEDDED_OUT_JACK])?;
+        Ok(())
+    }
+}
+
+pub struct UsbMidi {
+    device: UsbDevice<'static, UsbBusType>,
+    class: MidiClass<'static, UsbBusType>,
+    output: OutputQueue,
+    encoder: PacketEncoder,
+    // packets the endpoint didn't take yet
+    pending: Vec<u8>,
+}
+
+impl UsbMidi {
+    // Only one can be made, it owns the USB peripheral
+    pub fn new(usb: USB, output: OutputQueue) -> Self {
+        let bus = unsafe {
+            USB_BUS = Some(UsbBus::new(usb, &mut EP_MEMORY));
+            USB_BUS.as_ref().unwrap()
+        };
+        let class = MidiClass::new(bus);
+        let device = UsbDeviceBuilder::new(bus, VID_PID)
+            .manufacturer("Kawa")
+            .product("Kawa Synth")
+            .serial_number("1")
+            .build();
+        UsbMidi {
+            device,
+            class,
+            output,
+            encoder: PacketEncoder::new(CABLE),
+            pending: Vec::with_capacity(MAX_PACKET_SIZE as usize),
+        }
+    }
+
+    // Handles the USB events, returns the MIDI bytes received
+    pub fn poll(&mut self) -> Vec<u8> {
+        let mut received = Vec::new();
+        if !self.device.poll(&mut [&mut self.class]) {
+            return received;
+        }
+
+        let mut buffer = [0; MAX_PACKET_SIZE as usize];
+        if let Ok(size) = self.class.ep_out.read(&mut buffer) {
+            for chunk in buffer[..size].chunks_exact(PACKET_SIZE) {
+                let packet: Packet = [chunk[0], chunk[1], chunk[2], chunk[3]];
+                if midi_packet::cable(&packet) == CABLE {
+                    received.extend_from_slice(midi_packet::decode(&packet));
+                }
+            }
+        }
+        received
+    }
+
+    pub fn send_control_change(&mut self, channel: u8, control: u8, value: u8) {
+        self.output
+            .send(&[CONTROL_CHANGE | channel, control, value]);
+    }
+
+    // Sends what was queued, called after anything may have been queued and after the
+    // endpoint took the previous packets
+    pub fn flush(&mut self) {
+        if self.device.state() != UsbDeviceState::Configured {
+            // nobody listens, the output would only pile up
+            while self.output.pop().is_some() {}
+            self.pending.clear();
+            return;
+        }
+
+        while self.pending.len() < MAX_PACKET_SIZE as usize {
+            match self.output.pop() {
+                Some(byte) => {
+                    if let Some(packet) = self.encoder.push(byte) {
+                        self.pending.extend_from_slice(&packet);
+                    }
+                }
+                None => break,
+            }
+        }
+        if !self.pending.is_empty() {
+            if let Ok(size) = self.class.ep_in.write(&self.pending) {
+                self.pending.drain(..size);
+            }
+        }
+    }
+}
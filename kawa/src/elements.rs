@@ -17,8 +17,10 @@
 // You should have received a copy of the GNU General Public License
 // along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
 
+// Records shared with the Elements engine, laid out as in elements/elements.h
+
 #[repr(C)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Patch {
     pub exciter_envelope_shape: f32,
     pub exciter_bow_level: f32,
@@ -42,14 +44,59 @@ pub struct Patch {
     pub modulation_frequency: f32,
 }
 
-#[link(name = "elements")]
-extern "C" {
-    pub fn Elements_Init(application: bool);
-    pub fn Elements_GetPatch() -> *mut Patch;
-    pub fn Elements_SetGate(newGate: bool);
-    pub fn Elements_SetNote(newNote: f32);
-    pub fn Elements_SetStrength(newStrength: f32);
-    pub fn Elements_SetModulation(newModulation: f32);
-    pub fn Elements_Pause(pause: bool);
-    pub fn Elements_DMA1_Stream5_IRQHandler();
+pub const NUM_PATCH_PARAMETERS: usize = 20;
+
+pub const NUM_LFOS: usize = 2;
+pub const NUM_MODULATION_SLOTS: usize = 8;
+pub const NUM_VELOCITY_SLOTS: usize = 2;
+
+pub const MODULATION_SOURCE_LAST: u32 = 9;
+pub const LFO_SHAPE_LAST: u32 = 5;
+pub const LFO_DIVISION_LAST: u32 = 15;
+
+pub const CONTROLLER_SLIDE: u32 = 0;
+pub const CONTROLLER_PRESSURE: u32 = 1;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LfoSettings {
+    pub rate: f32,
+    pub shape: u32,
+    pub division: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ModulationSlot {
+    pub source: u32,
+    pub destination: u32,
+    pub amount: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VelocitySlot {
+    pub destination: u32,
+    pub amount: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ModulationSettings {
+    pub lfo: [LfoSettings; NUM_LFOS],
+    pub envelope_attack: f32,
+    pub envelope_decay: f32,
+    pub envelope_sustain: f32,
+    pub envelope_release: f32,
+    pub slot: [ModulationSlot; NUM_MODULATION_SLOTS],
+    pub velocity: [VelocitySlot; NUM_VELOCITY_SLOTS],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PerformanceState {
+    pub gate: bool,
+    pub note: f32,
+    pub modulation: f32,
+    pub strength: f32,
 }
@@ -19,8 +19,64 @@
 
 use alloc::boxed::Box;
 
-include!("elements.rs");
+use crate::audio_input::{AudioInput, NUM_CHANNELS};
+pub use crate::elements::*;
 
+#[link(name = "elements")]
+extern "C" {
+    pub fn Elements_Init(application: bool);
+    pub fn Elements_GetPatch() -> *mut Patch;
+    pub fn Elements_GetInitPatch() -> *const Patch;
+    pub fn Elements_GetModulation() -> *mut ModulationSettings;
+    pub fn Elements_GetNumVoices() -> u32;
+    pub fn Elements_GetVoiceState(voice: u32) -> *mut PerformanceState;
+    pub fn Elements_SetUnison(unison: bool, detune: f32, spread: f32);
+    pub fn Elements_SetSamples(data: *const i16, boundaries: *const usize, count: u32);
+    pub fn Elements_SetInputRouting(routing: u32);
+    pub fn Elements_SetInputChannel(channel: u32, gain: f32, threshold: f32);
+    pub fn Elements_GetInputLevel(channel: u32) -> f32;
+    pub fn Elements_GetInputEnvelope() -> f32;
+    pub fn Elements_SetModulation(newModulation: f32);
+    pub fn Elements_SetAftertouch(newAftertouch: f32);
+    pub fn Elements_SetModWheel(newModWheel: f32);
+    pub fn Elements_SetController(controller: u32, parameter: u32, amount: f32);
+    pub fn Elements_SetTempo(newTempo: f32);
+    pub fn Elements_ClockStart(reset: bool);
+    pub fn Elements_ClockStop();
+    pub fn Elements_ClockTick();
+    pub fn Elements_Lock(parameter: u32, value: f32);
+    pub fn Elements_ClearLocks();
+    pub fn Elements_Pause(pause: bool);
+    pub fn Elements_DMA1_Stream5_IRQHandler();
+}
+
+// The codec input processing of the engine, set up from the AudioInput settings
+pub trait InputProcessing {
+    fn apply(&self);
+    // Peak level after the gain, in dBFS
+    fn level(&self, channel: usize) -> f32;
+}
+
+impl InputProcessing for AudioInput {
+    fn apply(&self) {
+        unsafe {
+            Elements_SetInputRouting(self.routing);
+            for channel in 0..NUM_CHANNELS {
+                Elements_SetInputChannel(
+                    channel as u32,
+                    self.gain[channel] as f32,
+                    self.threshold_level(channel) as f32,
+                );
+            }
+        }
+    }
+
+    fn level(&self, channel: usize) -> f32 {
+        unsafe { Elements_GetInputLevel(channel as u32) }
+    }
+}
+
+#[derive(Clone, Copy)]
 pub enum Param {
     ExcEnvShape,
     ExcBowLevel,
@@ -35,12 +91,15 @@ pub enum Param {
     ResBrightness,
     ResDamping,
     ResPosition,
+    ReverbDiffusion,
+    ReverbLp,
     Space,
 }
 
 const KNOB_SCALER: f32 = 20f32;
-const PARAM_MIN: f32 = 0.0;
-const PARAM_MAX: f32 = 0.9995;
+const KNOB_FINE_SCALER: f32 = 100f32;
+pub const PARAM_MIN: f32 = 0.0;
+pub const PARAM_MAX: f32 = 0.9995;
 
 pub fn clamp(value: f32, min: f32, max: f32) -> f32 {
     if value < min {
@@ -54,18 +113,41 @@ pub fn clamp(value: f32, min: f32, max: f32) -> f32 {
     }
 }
 
+// Steps the value by the knob delta and returns it as displayed by the knob
+pub fn adjust_value(value: &mut f32, delta: i8, fine: bool, min: f32, max: f32) -> i16 {
+    *value += (delta as f32) / if fine { KNOB_FINE_SCALER } else { KNOB_SCALER };
+    *value = clamp(*value, min, max);
+    (*value * KNOB_SCALER) as i16
+}
+
+// Steps the value by the knob delta, one step per detent
+pub fn adjust_range(value: &mut u32, delta: i8, min: u32, max: u32) -> i16 {
+    let adjusted = *value as i32 + delta as i32;
+    *value = if adjusted < min as i32 {
+        min
+    } else if adjusted > max as i32 {
+        max
+    } else {
+        adjusted as u32
+    };
+    *value as i16
+}
+
+// Steps the index by the knob delta, for knobs choosing one of `count` options
+pub fn adjust_index(index: &mut u32, delta: i8, count: u32) -> i16 {
+    adjust_range(index, delta, 0, count - 1)
+}
+
 macro_rules! param_bind {
     ($PARAM:ident) => {
-        Box::new(|delta: i8| unsafe {
+        Box::new(|delta: i8, fine: bool| unsafe {
             let patch = &mut *Elements_GetPatch();
-            patch.$PARAM += (delta as f32) / KNOB_SCALER;
-            patch.$PARAM = clamp(patch.$PARAM, PARAM_MIN, PARAM_MAX);
-            (patch.$PARAM * KNOB_SCALER) as u8
+            adjust_value(&mut patch.$PARAM, delta, fine, PARAM_MIN, PARAM_MAX)
         })
     };
 }
 
-pub fn create_knob_handler(param: Param) -> Box<dyn FnMut(i8) -> u8> {
+pub fn create_knob_handler(param: Param) -> Box<dyn FnMut(i8, bool) -> i16> {
     match param {
         Param::ExcEnvShape => param_bind!(exciter_envelope_shape),
         Param::ExcBowLevel => param_bind!(exciter_bow_level),
@@ -80,6 +162,46 @@ pub fn create_knob_handler(param: Param) -> Box<dyn FnMut(i8) -> u8> {
         Param::ResBrightness => param_bind!(resonator_brightness),
         Param::ResDamping => param_bind!(resonator_damping),
         Param::ResPosition => param_bind!(resonator_position),
+        Param::ReverbDiffusion => param_bind!(reverb_diffusion),
+        Param::ReverbLp => param_bind!(reverb_lp),
         Param::Space => param_bind!(space),
     }
 }
+
+macro_rules! param_reset {
+    ($PARAM:ident, $DEFAULT:expr) => {
+        Box::new(|| unsafe {
+            let patch = &mut *Elements_GetPatch();
+            patch.$PARAM = $DEFAULT;
+            (patch.$PARAM * KNOB_SCALER) as i16
+        })
+    };
+}
+
+// Defaults follow Part::Init in elements/dsp/part.cc, the reverb ones are seeded per unit
+// by Part::Seed
+pub fn create_reset_handler(param: Param) -> Box<dyn FnMut() -> i16> {
+    match param {
+        Param::ExcEnvShape => param_reset!(exciter_envelope_shape, PARAM_MAX),
+        Param::ExcBowLevel => param_reset!(exciter_bow_level, 0.0),
+        Param::ExcBowTimbre => param_reset!(exciter_bow_timbre, 0.5),
+        Param::ExcBlowLevel => param_reset!(exciter_blow_level, 0.0),
+        Param::ExcBlowMeta => param_reset!(exciter_blow_meta, 0.5),
+        Param::ExcBlowTimbre => param_reset!(exciter_blow_timbre, 0.5),
+        Param::ExcStrikeLevel => param_reset!(exciter_strike_level, 0.8),
+        Param::ExcStrikeMeta => param_reset!(exciter_strike_meta, 0.5),
+        Param::ExcStrikeTimbre => param_reset!(exciter_strike_timbre, 0.5),
+        Param::ResGeometry => param_reset!(resonator_geometry, 0.2),
+        Param::ResBrightness => param_reset!(resonator_brightness, 0.5),
+        Param::ResDamping => param_reset!(resonator_damping, 0.25),
+        Param::ResPosition => param_reset!(resonator_position, 0.3),
+        Param::ReverbDiffusion => {
+            param_reset!(
+                reverb_diffusion,
+                (*Elements_GetInitPatch()).reverb_diffusion
+            )
+        }
+        Param::ReverbLp => param_reset!(reverb_lp, (*Elements_GetInitPatch()).reverb_lp),
+        Param::Space => param_reset!(space, 0.5),
+    }
+}
@@ -0,0 +1,124 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// Plays a note while the envelope of the audio input is above a threshold, e.g. from
+// a drum mic. The gate closes once the level falls below the threshold minus the
+// hysteresis, so a decaying hit doesn't retrigger.
+
+pub const MAX_NOTE: u32 = 127;
+pub const DEFAULT_NOTE: u32 = 60;
+// The threshold is set in dB above this level in dBFS
+pub const THRESHOLD_FLOOR: i32 = -80;
+pub const MAX_THRESHOLD: u32 = 80;
+pub const DEFAULT_THRESHOLD: u32 = 50;
+// in dB
+pub const MAX_HYSTERESIS: u32 = 24;
+pub const DEFAULT_HYSTERESIS: u32 = 6;
+
+// With dynamics, the peak during the first milliseconds sets the velocity, from 1 at
+// the threshold to 127 at full scale
+const DYNAMICS_WINDOW: u32 = 5;
+const MAX_VELOCITY: u8 = 127;
+
+#[derive(Debug, PartialEq)]
+pub enum GateEvent {
+    Open { note: u8, velocity: u8 },
+    Velocity { note: u8, velocity: u8 },
+    Close { note: u8 },
+}
+
+pub struct InputGate {
+    pub enabled: bool,
+    pub note: u32,
+    pub threshold: u32,
+    pub hysteresis: u32,
+    pub dynamics: bool,
+    // the note played while open
+    playing: Option<u8>,
+    window: u32,
+    velocity: u8,
+}
+
+impl Default for InputGate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputGate {
+    pub fn new() -> Self {
+        InputGate {
+            enabled: false,
+            note: DEFAULT_NOTE,
+            threshold: DEFAULT_THRESHOLD,
+            hysteresis: DEFAULT_HYSTERESIS,
+            dynamics: true,
+            playing: None,
+            window: 0,
+            velocity: 0,
+        }
+    }
+
+    // in dBFS
+    pub fn threshold_level(&self) -> i32 {
+        THRESHOLD_FLOOR + self.threshold as i32
+    }
+
+    // Called every millisecond with the envelope in dBFS
+    pub fn update(&mut self, level: f32) -> Option<GateEvent> {
+        let threshold = self.threshold_level() as f32;
+        match self.playing {
+            Some(note) => {
+                if !self.enabled || level < threshold - self.hysteresis as f32 {
+                    self.playing = None;
+                    return Some(GateEvent::Close { note });
+                }
+                if self.window > 0 {
+                    self.window -= 1;
+                    let velocity = self.velocity_of(level);
+                    if velocity > self.velocity {
+                        self.velocity = velocity;
+                        return Some(GateEvent::Velocity { note, velocity });
+                    }
+                }
+                None
+            }
+            None if self.enabled && level >= threshold => {
+                let note = self.note as u8;
+                self.playing = Some(note);
+                self.window = DYNAMICS_WINDOW;
+                self.velocity = self.velocity_of(level);
+                Some(GateEvent::Open {
+                    note,
+                    velocity: self.velocity,
+                })
+            }
+            None => None,
+        }
+    }
+
+    fn velocity_of(&self, level: f32) -> u8 {
+        let threshold = self.threshold_level() as f32;
+        if !self.dynamics || threshold >= 0.0 {
+            return MAX_VELOCITY;
+        }
+        let position = ((level - threshold) / -threshold).clamp(0.0, 1.0);
+        1 + (position * (MAX_VELOCITY - 1) as f32) as u8
+    }
+}
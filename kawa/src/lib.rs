@@ -0,0 +1,46 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// The modules that don't touch the hardware or the Elements engine. They build for the
+// host too, where their tests run with `cargo test --lib --target <host triple>`.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+#[path = "ui/framework/mod.rs"]
+pub mod framework;
+
+pub mod arpeggiator;
+pub mod audio_input;
+pub mod elements;
+pub mod input_gate;
+pub mod library;
+pub mod midi_clock;
+pub mod midi_packet;
+pub mod midi_source;
+pub mod mpe;
+pub mod pedals;
+pub mod pressure;
+pub mod settings;
+pub mod sysex;
+pub mod trigger_input;
+pub mod tuning;
+pub mod velocity;
+pub mod voice_allocator;
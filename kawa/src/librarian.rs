@@ -0,0 +1,183 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::driver::flash::Error;
+use crate::elements_handlers::*;
+use crate::patch_store::*;
+use crate::program::Program;
+
+// Keeps track of the slot the edit buffer was loaded from and of the slot picked in the
+// browser, previewed patches replace the edit buffer until loaded or left.
+pub struct Librarian {
+    store: PatchStore,
+    selected: usize,
+    loaded: Option<usize>,
+    backup: Option<Program>,
+    copy_source: Option<usize>,
+    name: PatchName,
+    renaming: bool,
+}
+
+impl Librarian {
+    pub fn new(store: PatchStore) -> Self {
+        Librarian {
+            store,
+            selected: 0,
+            loaded: None,
+            backup: None,
+            copy_source: None,
+            name: DEFAULT_NAME,
+            renaming: false,
+        }
+    }
+
+    pub fn select(&mut self, slot: usize) {
+        self.selected = slot;
+    }
+
+    pub fn preview(&mut self, slot: usize) {
+        self.selected = slot;
+        if let Some(program) = self.store.load(slot) {
+            if self.backup.is_none() {
+                self.backup = Some(Program::edited());
+            }
+            program.apply();
+        }
+    }
+
+    pub fn end_preview(&mut self) {
+        if let Some(backup) = self.backup.take() {
+            backup.apply();
+        }
+    }
+
+    pub fn load(&mut self) {
+        if let Some(program) = self.store.load(self.selected) {
+            program.apply();
+            self.backup = None;
+            self.loaded = Some(self.selected);
+        }
+    }
+
+    pub fn has_unsaved_changes(&self) -> bool {
+        let edited = match &self.backup {
+            Some(backup) => *backup,
+            None => Program::edited(),
+        };
+        self.loaded
+            .map_or(true, |slot| self.store.load(slot) != Some(edited))
+    }
+
+    // Saves the edit buffer or renames the stored patch when `renaming`
+    pub fn begin_naming(&mut self, renaming: bool) {
+        self.renaming = renaming;
+        self.name = self.store.name(self.selected).unwrap_or(DEFAULT_NAME);
+    }
+
+    pub fn name(&self) -> &PatchName {
+        &self.name
+    }
+
+    pub fn set_name(&mut self, name: &[u8]) {
+        self.name.copy_from_slice(name);
+    }
+
+    pub fn finish_naming(&mut self) {
+        let (slot, name) = (self.selected, self.name);
+        if self.renaming {
+            self.write(|store| store.rename(slot, &name));
+        } else {
+            self.end_preview();
+            let program = Program::edited();
+            if self.write(|store| store.save(slot, &program, &name)) {
+                self.loaded = Some(slot);
+            }
+        }
+    }
+
+    pub fn cancel_naming(&mut self) {
+        self.end_preview();
+    }
+
+    // The first copy marks the source, the next one copies it to the selected slot
+    pub fn copy(&mut self) {
+        let slot = self.selected;
+        match self.copy_source.take() {
+            Some(source) => {
+                if self.write(|store| store.copy(source, slot)) && self.loaded == Some(slot) {
+                    self.loaded = None;
+                }
+            }
+            None => self.copy_source = Some(slot),
+        }
+    }
+
+    // Swaps the slot marked by copy with the selected one
+    pub fn swap(&mut self) {
+        let slot = self.selected;
+        if let Some(source) = self.copy_source.take() {
+            if !self.write(|store| store.swap(source, slot)) {
+                return;
+            }
+            if self.loaded == Some(slot) {
+                self.loaded = Some(source);
+            } else if self.loaded == Some(source) {
+                self.loaded = Some(slot);
+            }
+        }
+    }
+
+    pub fn delete(&mut self) {
+        let slot = self.selected;
+        if !self.write(|store| store.delete(slot)) {
+            return;
+        }
+        if self.loaded == Some(slot) {
+            self.loaded = None;
+        }
+        if self.copy_source == Some(slot) {
+            self.copy_source = None;
+        }
+    }
+
+    // Slot label with a marker after the slot number: '+' copy source,
+    // '>' loaded slot, '*' loaded slot with unsaved changes
+    pub fn slot_label(&self, slot: usize, label: &mut [u8]) {
+        self.store.slot_label(slot, label);
+        if self.copy_source == Some(slot) {
+            label[3] = b'+';
+        } else if self.loaded == Some(slot) {
+            label[3] = if self.has_unsaved_changes() { b'*' } else { b'>' };
+        }
+    }
+
+    // Returns whether the change was written, a failed one leaves the stored patches as
+    // they were
+    fn write(&mut self, f: impl FnOnce(&mut PatchStore) -> Result<(), Error>) -> bool {
+        // flash erase stalls the CPU for too long to keep the audio running
+        unsafe {
+            Elements_Pause(true);
+        }
+        let written = f(&mut self.store).is_ok();
+        unsafe {
+            Elements_Pause(false);
+        }
+        written
+    }
+}
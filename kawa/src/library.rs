@@ -0,0 +1,32 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// The patch library: banks of slots, each holding a named patch
+
+pub const NUM_BANKS: usize = 4;
+pub const SLOTS_PER_BANK: usize = 16;
+pub const NUM_SLOTS: usize = NUM_BANKS * SLOTS_PER_BANK;
+pub const NAME_LENGTH: usize = 12;
+// "A01 " followed by the name
+pub const SLOT_LABEL_LENGTH: usize = 4 + NAME_LENGTH;
+
+pub type PatchName = [u8; NAME_LENGTH];
+
+pub const EMPTY_NAME: PatchName = *b"------------";
+pub const DEFAULT_NAME: PatchName = *b"Init        ";
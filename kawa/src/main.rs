@@ -33,11 +33,12 @@ use cortex_m::peripheral::Peripherals;
 use cortex_m_rt::entry;
 use hal::delay::Delay;
 use hal::gpio::*;
+use hal::otg_fs::USB;
 use hal::serial::config::*;
 use hal::serial::*;
 use hal::spi::*;
 use hal::stm32;
-use hal::stm32::UART4;
+use hal::stm32::{UART4, USART2};
 use stm32f4::stm32f407::{interrupt, SPI2, TIM1, TIM2, TIM3, TIM5};
 use stm32f4xx_hal as hal;
 use stm32f4xx_hal::rcc::RccExt;
@@ -46,11 +47,12 @@ extern crate cty;
 
 mod driver;
 use driver::encoder::RotaryEncoder;
+use driver::flash::{self, Flash};
+use driver::ticker::{self, Ticker};
+use driver::usb_midi::UsbMidi;
 
 use st7920::ST7920;
 
-use midi_port::*;
-
 use embedded_hal::digital::v2::InputPin;
 
 mod ui;
@@ -62,16 +64,43 @@ mod elements_handlers;
 use elements_handlers::*;
 
 mod midi_input;
+mod midi_output;
+mod parameter_cc;
+mod sequencer;
+use audio_input::AudioInput;
+use kawa::{
+    arpeggiator, audio_input, elements, input_gate, library, midi_clock, midi_packet,
+    midi_source, mpe, pedals, pressure, settings, sysex, trigger_input, tuning, velocity,
+    voice_allocator,
+};
 use midi_input::MidiInput;
+use midi_output::{MidiOutput, OutputQueue, TxInterrupt};
+use settings::{Settings, SettingsCommand};
+
+mod librarian;
+use librarian::Librarian;
+
+mod patch_store;
+mod program;
+mod sample_store;
+mod settings_store;
+mod tuning_store;
+use patch_store::PatchStore;
+use sample_store::{SampleCommand, SampleStore};
+use settings_store::SettingsStore;
+use tuning_store::TuningStore;
 
 use embedded_graphics::prelude::*;
-use embedded_graphics::primitives::Rectangle;
 
 use alloc::boxed::Box;
+use alloc::rc::Rc;
+use core::cell::RefCell;
 
 #[global_allocator]
 static ALLOCATOR: CortexMHeap = CortexMHeap::empty();
-const HEAP_SIZE: usize = 4 * 1024; // in bytes
+// The App with its framebuffers and the widgets of all panels take about 17K, the rest
+// holds SysEx messages and the tables copied while a tuning is saved
+const HEAP_SIZE: usize = 32 * 1024; // in bytes
 
 #[derive(Clone, Copy)]
 enum InputDeviceId {
@@ -84,17 +113,50 @@ enum InputDeviceId {
     Knob2,
     Knob3,
     Knob4,
+    Knob1Switch,
+    Knob2Switch,
+    Knob3Switch,
+    Knob4Switch,
 }
 
+#[derive(Clone, Copy)]
 enum PanelId {
     PanelBow,
     PanelBlow,
     PanelStrike,
     PanelRes,
     PanelOutput,
+    PanelSave,
+    PanelName,
+    PanelLibrary,
+    PanelModMatrix,
+    PanelModLfo,
+    PanelModEnv,
+    PanelArp,
+    PanelSeq,
+    PanelSeqLock,
+    PanelVoice,
+    PanelVelocity,
+    PanelInput,
+    PanelTrigger,
+    PanelExt,
+    PanelMidi,
+    PanelSettings,
 }
 
 type MidiUart = Serial<UART4, (NoTx, gpioc::PC11<Alternate<AF8>>)>;
+// UART4 can't transmit, its TX pins are taken by the codec clock and an encoder
+type MidiOutUart = Serial<USART2, (gpioa::PA2<Alternate<AF7>>, NoRx)>;
+
+impl TxInterrupt for MidiOutUart {
+    fn listen_tx(&mut self, listen: bool) {
+        if listen {
+            self.listen(hal::serial::Event::Txe);
+        } else {
+            self.unlisten(hal::serial::Event::Txe);
+        }
+    }
+}
 
 struct App<'a> {
     button_pins: (
@@ -104,8 +166,14 @@ struct App<'a> {
         gpiod::PD11<Input<PullUp>>,
         gpiob::PB11<Input<PullUp>>,
     ),
-    _trigger_pin: gpioe::PE9<Input<PullUp>>,
-    button_states: [bool; 5],
+    knob_switch_pins: (
+        gpioe::PE8<Input<PullUp>>,
+        gpioe::PE10<Input<PullUp>>,
+        gpioe::PE12<Input<PullUp>>,
+        gpioe::PE14<Input<PullUp>>,
+    ),
+    trigger_pin: gpioe::PE9<Input<PullUp>>,
+    gestures: GestureRecognizer,
     display: st7920::ST7920<
         Spi<
             SPI2,
@@ -118,11 +186,30 @@ struct App<'a> {
         gpioe::PE13<Output<PushPull>>,
         gpioe::PE13<Output<PushPull>>,
     >,
+    framebuffer: FrameBuffer,
     encoders: (TIM2, TIM3, TIM5, TIM1),
+    knob_positions: [i32; 4],
+    ticker: Ticker,
     delay: Delay,
-    panels: Option<[Panel<'a>; 5]>,
+    panels: Option<[Panel<'a>; 21]>,
     current_panel: Option<&'a mut Panel<'a>>,
+    current_panel_id: PanelId,
+    return_panel: PanelId,
     midi_input: MidiInput<MidiUart>,
+    midi_output: MidiOutput<MidiOutUart>,
+    usb_midi: UsbMidi,
+    audio_input: AudioInput,
+    librarian: Librarian,
+    tunings: TuningStore,
+    samples: SampleStore,
+    settings_store: SettingsStore,
+    // loaded after power on, a patch slot + 1 or settings::INIT_PATCH
+    startup_patch: u32,
+    modulation_slot: u32,
+    sequencer_step: u32,
+    sequencer_lock: u32,
+    // channel of the thru filter edited on the MIDI page
+    thru_channel: u32,
 }
 
 impl<'a> App<'a> {
@@ -130,11 +217,16 @@ impl<'a> App<'a> {
         //TODO split this to smaller functions or if not possible (partial borrows), at least to macros
         let p = stm32::Peripherals::take().unwrap();
         let mut cp = Peripherals::take().unwrap();
+        // SYSCFG routes the trigger pin to its external interrupt
+        p.RCC.apb2enr.modify(|_, w| w.syscfgen().set_bit());
         let rcc = p.RCC.constrain();
 
         let clocks = rcc
             .cfgr
+            // USB needs the crystal and the 48 MHz PLL output
+            .use_hse(stm32f4xx_hal::time::MegaHertz(8))
             .sysclk(stm32f4xx_hal::time::MegaHertz(168))
+            .require_pll48clk()
             .freeze();
         let mut delay = Delay::new(cp.SYST, clocks);
 
@@ -181,7 +273,23 @@ impl<'a> App<'a> {
             gpiod.pd11.into_pull_up_input(),
             gpiob.pb11.into_pull_up_input(),
         );
-        let _trigger_pin = gpioe.pe9.into_pull_up_input();
+        let knob_switch_pins = (
+            gpioe.pe8.into_pull_up_input(),
+            gpioe.pe10.into_pull_up_input(),
+            gpioe.pe12.into_pull_up_input(),
+            gpioe.pe14.into_pull_up_input(),
+        );
+        let mut trigger_pin = gpioe.pe9.into_pull_up_input();
+        let mut syscfg = p.SYSCFG;
+        let mut exti = p.EXTI;
+        trigger_pin.make_interrupt_source(&mut syscfg);
+        trigger_pin.trigger_on_edge(&mut exti, Edge::RISING_FALLING);
+        trigger_pin.enable_interrupt(&mut exti);
+        unsafe {
+            // shares the note handling with MIDI input, so neither preempts the other
+            cp.NVIC
+                .set_priority(stm32f4::stm32f407::Interrupt::EXTI9_5, 0);
+        }
 
         let mut display = ST7920::new(
             spi,
@@ -211,24 +319,101 @@ impl<'a> App<'a> {
             cp.NVIC
                 .set_priority(stm32f4::stm32f407::Interrupt::UART4, 0);
         }
-        let midi_input = MidiInput::new(MidiInPort::new(midi_uart));
+        let midi_out_uart = Serial::usart2(
+            p.USART2,
+            (gpioa.pa2.into_alternate_af7(), NoRx),
+            Config {
+                baudrate: stm32f4xx_hal::time::Bps(31250),
+                wordlength: WordLength::DataBits8,
+                parity: Parity::ParityNone,
+                stopbits: StopBits::STOP1,
+            },
+            clocks,
+        )
+        .unwrap();
+        unsafe {
+            // shares the output queue with MIDI input
+            cp.NVIC
+                .set_priority(stm32f4::stm32f407::Interrupt::USART2, 0);
+        }
+        let output = OutputQueue::new();
+        let usb_output = OutputQueue::new();
+        let midi_input = MidiInput::new(midi_uart, output.clone(), usb_output.clone());
+        let midi_output = MidiOutput::new(midi_out_uart, output);
+
+        let usb_midi = UsbMidi::new(
+            USB {
+                usb_global: p.OTG_FS_GLOBAL,
+                usb_device: p.OTG_FS_DEVICE,
+                usb_pwrclk: p.OTG_FS_PWRCLK,
+                pin_dm: gpioa.pa11.into_alternate_af10(),
+                pin_dp: gpioa.pa12.into_alternate_af10(),
+            },
+            usb_output,
+        );
+        unsafe {
+            // shares the note handling with MIDI input
+            cp.NVIC
+                .set_priority(stm32f4::stm32f407::Interrupt::OTG_FS, 0);
+        }
+        let audio_input = AudioInput::new();
+
+        let flash = Rc::new(RefCell::new(Flash::new(p.FLASH)));
+
+        let ticker = Ticker::new(p.TIM7, clocks);
+        unsafe {
+            // the tick plays notes too, at the MIDI input priority neither preempts the
+            // other and the audio isn't masked
+            cp.NVIC
+                .set_priority(stm32f4::stm32f407::Interrupt::TIM7, 0);
+        }
 
         unsafe {
             Elements_Init(false);
+            midi_input.apply_voicing();
+            audio_input.apply();
             cp.NVIC
                 .set_priority(stm32f4::stm32f407::Interrupt::DMA1_STREAM5, 16);
         }
 
         App {
             button_pins,
-            _trigger_pin,
-            button_states: [false; 5],
+            knob_switch_pins,
+            trigger_pin,
+            gestures: GestureRecognizer::new(&[
+                InputDeviceId::Button1 as InputId,
+                InputDeviceId::Button2 as InputId,
+                InputDeviceId::Button3 as InputId,
+                InputDeviceId::Button4 as InputId,
+                InputDeviceId::Button5 as InputId,
+                InputDeviceId::Knob1Switch as InputId,
+                InputDeviceId::Knob2Switch as InputId,
+                InputDeviceId::Knob3Switch as InputId,
+                InputDeviceId::Knob4Switch as InputId,
+            ]),
             display,
+            framebuffer: FrameBuffer::new(),
             midi_input,
+            midi_output,
+            usb_midi,
+            audio_input,
             encoders: (p.TIM2, p.TIM3, p.TIM5, p.TIM1),
+            knob_positions: [0; 4],
+            ticker,
             delay,
             panels: None,
             current_panel: None,
+            current_panel_id: PanelId::PanelBow,
+            return_panel: PanelId::PanelBow,
+            librarian: Librarian::new(PatchStore::new(flash.clone())),
+            tunings: TuningStore::new(flash.clone()),
+            samples: SampleStore::new(flash.clone()),
+            settings_store: SettingsStore::new(flash),
+            startup_patch: settings::INIT_PATCH,
+            modulation_slot: 0,
+            sequencer_step: 0,
+            sequencer_lock: 0,
+            thru_channel: 0,
         }
     }
 
@@ -239,6 +424,22 @@ impl<'a> App<'a> {
             Panel::new(panel_strike::setup()),
             Panel::new(panel_res::setup()),
             Panel::new(panel_out::setup()),
+            panel_save::setup(),
+            panel_name::setup(),
+            panel_library::setup(),
+            Panel::new(panel_mod::setup_matrix()),
+            Panel::new(panel_mod::setup_lfo()),
+            Panel::new(panel_mod::setup_envelope()),
+            Panel::new(panel_arp::setup()),
+            panel_seq::setup_steps(),
+            panel_seq::setup_locks(),
+            Panel::new(panel_voice::setup_voice()),
+            Panel::new(panel_voice::setup_velocity()),
+            panel_input::setup_input(),
+            panel_input::setup_trigger(),
+            Panel::new(panel_ext::setup()),
+            panel_midi::setup(),
+            Panel::new(panel_settings::setup()),
         ])
     }
 
@@ -252,6 +453,7 @@ impl<'a> App<'a> {
         if let Some(panels) = &mut self2.panels {
             self.current_panel = Some(&mut panels[panel as usize]);
         }
+        self.current_panel_id = panel;
 
         if let Some(panel) = &mut self.current_panel {
             panel.input_reset();
@@ -260,52 +462,63 @@ impl<'a> App<'a> {
         self.update_knobs();
         App::pause_synth(true);
 
-        self.display.draw(
-            Rectangle::new(
-                Point::new(0, 0),
-                Point::new(st7920::WIDTH - 1, st7920::HEIGHT - 1),
-            )
-            .fill(Some(BinaryColor::Off)),
-        );
-
+        self.framebuffer.clear();
         if let Some(panel) = &mut self.current_panel {
-            panel.render(&mut self.display);
+            panel.render(&mut self.framebuffer);
+        }
+        self.flush_framebuffer();
+
+        App::pause_synth(false);
+    }
+
+    // Changes the panel, the new one returns to the current one when done
+    pub fn open_panel(&mut self, self2: &'a mut App<'a>, panel: PanelId) {
+        self.return_panel = self.current_panel_id;
+        self.change_panel(self2, panel);
+    }
+
+    fn flush_framebuffer(&mut self) {
+        for span in self.framebuffer.diff() {
+            self.display.draw(self.framebuffer.span_pixels(span));
             self.display
-                .flush(&mut self.delay)
+                .flush_region_graphics(span.region(), &mut self.delay)
                 .expect("could not flush display");
         }
+        self.framebuffer.commit();
+    }
 
-        App::pause_synth(false);
+    // Returns whether any knob turned
+    fn update_knobs(&mut self) -> bool {
+        let turned = self.update_knob(InputDeviceId::Knob1, self.encoders.0.read_enc() as i32);
+        let turned =
+            self.update_knob(InputDeviceId::Knob2, self.encoders.1.read_enc() as i32) || turned;
+        let turned =
+            self.update_knob(InputDeviceId::Knob3, self.encoders.2.read_enc() as i32) || turned;
+        self.update_knob(InputDeviceId::Knob4, self.encoders.3.read_enc() as i32) || turned
     }
 
-    fn update_knobs(&mut self) {
+    fn update_knob(&mut self, id: InputDeviceId, position: i32) -> bool {
+        let index = id as usize - InputDeviceId::Knob1 as usize;
+        let turned = position != self.knob_positions[index];
+        let value = if turned && self.gestures.consume_held() {
+            Value::Fine(position)
+        } else {
+            Value::Int(position)
+        };
+        self.knob_positions[index] = position;
+
         if let Some(panel) = &mut self.current_panel {
-            panel.input_update(
-                InputDeviceId::Knob1 as InputId,
-                Value::Int(self.encoders.0.read_enc() as i32),
-            );
-            panel.input_update(
-                InputDeviceId::Knob2 as InputId,
-                Value::Int(self.encoders.1.read_enc() as i32),
-            );
-            panel.input_update(
-                InputDeviceId::Knob3 as InputId,
-                Value::Int(self.encoders.2.read_enc() as i32),
-            );
-            panel.input_update(
-                InputDeviceId::Knob4 as InputId,
-                Value::Int(self.encoders.3.read_enc() as i32),
-            );
+            panel.input_update(id as InputId, value);
         };
+        turned
     }
 
-    fn update_button(&mut self, id: InputDeviceId, value: bool) {
-        if value && value != self.button_states[id as usize] {
+    fn update_button(&mut self, id: InputDeviceId, pressed: bool) {
+        if let Some(gesture) = self.gestures.update(id as InputId, pressed, ticker::now()) {
             if let Some(panel) = &mut self.current_panel {
-                panel.input_update(id as InputId, Value::Bool(value));
+                panel.input_update(id as InputId, Value::Gesture(gesture));
             };
         }
-        self.button_states[id as usize] = value;
     }
 
     fn update_buttons(&mut self) {
@@ -329,20 +542,206 @@ impl<'a> App<'a> {
             InputDeviceId::Button5,
             !self.button_pins.4.is_high().unwrap(),
         );
+        self.update_button(
+            InputDeviceId::Knob1Switch,
+            !self.knob_switch_pins.0.is_high().unwrap(),
+        );
+        self.update_button(
+            InputDeviceId::Knob2Switch,
+            !self.knob_switch_pins.1.is_high().unwrap(),
+        );
+        self.update_button(
+            InputDeviceId::Knob3Switch,
+            !self.knob_switch_pins.2.is_high().unwrap(),
+        );
+        self.update_button(
+            InputDeviceId::Knob4Switch,
+            !self.knob_switch_pins.3.is_high().unwrap(),
+        );
+    }
+
+    // Tunings arrive in the MIDI interrupt but the flash is written here
+    fn save_received_tuning(&mut self) {
+        let received = cortex_m::interrupt::free(|_| self.midi_input.take_pending_tuning());
+        if let Some((program, name, table)) = received {
+            App::pause_synth(true);
+            let saved = self.tunings.save(program as usize, &name, &table);
+            App::pause_synth(false);
+
+            // retune right away when the current patch uses the slot
+            if saved.is_ok() && self.midi_input.tuning_index == program as u32 + 1 {
+                cortex_m::interrupt::free(|_| {
+                    self.midi_input.set_tuning(program as u32 + 1, &table)
+                });
+            }
+        }
+    }
+
+    fn handle_sysex_commands(&mut self) {
+        while let Some((command, payload, usb)) =
+            cortex_m::interrupt::free(|_| self.midi_input.take_pending_command())
+        {
+            if let Some(command) = SampleCommand::parse(command, &payload) {
+                self.samples.handle(command);
+            } else if let Some(command) = SettingsCommand::parse(command, &payload) {
+                match command {
+                    SettingsCommand::Request => {
+                        let dump = settings::dump(&self.settings());
+                        let midi_input = &self.midi_input;
+                        cortex_m::interrupt::free(|_| midi_input.send_reply(usb, &dump));
+                    }
+                    SettingsCommand::Dump(settings) => {
+                        self.apply_settings(&settings);
+                        // a dump isn't acknowledged, the settings apply until power off
+                        self.save_settings().ok();
+                    }
+                }
+            }
+        }
+    }
+
+    // The global settings as currently set
+    fn settings(&self) -> Settings {
+        let midi_input = &self.midi_input;
+        let audio_input = &self.audio_input;
+        let startup_patch = self.startup_patch;
+        // the thru filter is shared with the MIDI interrupt
+        cortex_m::interrupt::free(|_| {
+            let thru = midi_input.thru.borrow();
+            Settings {
+                midi_channel: midi_input.channel,
+                velocity_curve: midi_input.velocity.curve,
+                velocity_sensitivity: midi_input.velocity.sensitivity,
+                bend_range: midi_input.mpe.default_bend_range() as u32,
+                fine_tune: midi_input.master_tuning.fine_tune,
+                octave: midi_input.master_tuning.octave,
+                semitone: midi_input.master_tuning.semitone,
+                steal_mode: midi_input.voices.mode,
+                unison: midi_input.voices.unison() as u32,
+                detune: midi_input.voices.detune,
+                spread: midi_input.voices.spread,
+                pressure_destination: midi_input.pressure.destination,
+                slide_target: midi_input.mpe.slide_target,
+                input_routing: audio_input.routing,
+                input_gain: audio_input.gain,
+                input_threshold: audio_input.threshold,
+                gate_enabled: midi_input.input_gate.enabled as u32,
+                gate_note: midi_input.input_gate.note,
+                gate_threshold: midi_input.input_gate.threshold,
+                gate_hysteresis: midi_input.input_gate.hysteresis,
+                gate_dynamics: midi_input.input_gate.dynamics as u32,
+                trigger_mode: midi_input.trigger.mode,
+                trigger_polarity: midi_input.trigger.polarity,
+                trigger_note: midi_input.trigger.note,
+                trigger_resolution: midi_input.trigger.clock.resolution,
+                thru: thru.enabled as u32,
+                thru_channels: thru.channels as u32,
+                echo: midi_input.parameter_echo.enabled as u32,
+                echo_channel: midi_input.parameter_echo.channel,
+                startup_patch,
+            }
+        })
+    }
+
+    fn apply_settings(&mut self, settings: &Settings) {
+        let midi_input = &mut self.midi_input;
+        cortex_m::interrupt::free(|_| {
+            midi_input.channel = settings.midi_channel;
+            midi_input.velocity.curve = settings.velocity_curve;
+            midi_input.velocity.sensitivity = settings.velocity_sensitivity;
+            midi_input
+                .mpe
+                .set_default_bend_range(settings.bend_range as u8);
+            midi_input.master_tuning.fine_tune = settings.fine_tune;
+            midi_input.master_tuning.octave = settings.octave;
+            midi_input.master_tuning.semitone = settings.semitone;
+            midi_input.voices.mode = settings.steal_mode;
+            if midi_input.voices.unison() != (settings.unison != 0) {
+                midi_input.set_unison(settings.unison != 0);
+            }
+            midi_input.voices.detune = settings.detune;
+            midi_input.voices.spread = settings.spread;
+            midi_input.apply_voicing();
+            midi_input.set_pressure_destination(settings.pressure_destination);
+            midi_input.mpe.slide_target = settings.slide_target;
+
+            let gate = &mut midi_input.input_gate;
+            gate.enabled = settings.gate_enabled != 0;
+            gate.note = settings.gate_note;
+            gate.threshold = settings.gate_threshold;
+            gate.hysteresis = settings.gate_hysteresis;
+            gate.dynamics = settings.gate_dynamics != 0;
+
+            midi_input.release_trigger();
+            let trigger = &mut midi_input.trigger;
+            trigger.mode = settings.trigger_mode;
+            trigger.polarity = settings.trigger_polarity;
+            trigger.note = settings.trigger_note;
+            trigger.clock.resolution = settings.trigger_resolution;
+
+            let mut thru = midi_input.thru.borrow_mut();
+            thru.enabled = settings.thru != 0;
+            thru.channels = settings.thru_channels as u16;
+            midi_input.parameter_echo.enabled = settings.echo != 0;
+            midi_input.parameter_echo.channel = settings.echo_channel;
+        });
+
+        self.audio_input.routing = settings.input_routing;
+        self.audio_input.gain = settings.input_gain;
+        self.audio_input.threshold = settings.input_threshold;
+        self.audio_input.apply();
+        self.startup_patch = settings.startup_patch;
+    }
+
+    fn save_settings(&mut self) -> Result<(), flash::Error> {
+        let settings = self.settings();
+        App::pause_synth(true);
+        let saved = self.settings_store.save(&settings);
+        App::pause_synth(false);
+        saved
+    }
+
+    // Applies the stored settings and loads the startup patch
+    fn load_settings(&mut self) {
+        let settings = self.settings_store.load();
+        self.apply_settings(&settings);
+        if let Some(slot) = settings.startup_patch.checked_sub(1) {
+            self.librarian.select(slot as usize);
+            self.librarian.load();
+        }
+    }
+
+    // Sends the patch parameters moved by a knob as CCs
+    fn send_parameter_changes(&mut self, turned: bool) {
+        let patch = unsafe { &*Elements_GetPatch() };
+        let values = unsafe {
+            core::slice::from_raw_parts(patch as *const Patch as *const f32, NUM_PATCH_PARAMETERS)
+        };
+        let midi_input = &mut self.midi_input;
+        let midi_output = &mut self.midi_output;
+        let usb_midi = &mut self.usb_midi;
+        cortex_m::interrupt::free(|_| {
+            let echo = &mut midi_input.parameter_echo;
+            for (control, value) in echo.update(values, turned) {
+                midi_output.send_control_change(echo.channel as u8, control, value);
+                usb_midi.send_control_change(echo.channel as u8, control, value);
+            }
+            midi_output.flush();
+            usb_midi.flush();
+        });
     }
 
     fn update(&mut self) {
-        self.update_knobs();
+        let turned = self.update_knobs();
+        self.send_parameter_changes(turned);
         self.update_buttons();
+        self.save_received_tuning();
+        self.handle_sysex_commands();
 
         if let Some(panel) = &mut self.current_panel {
-            let invalidate = panel.render(&mut self.display);
-            if invalidate.1.width != 0 && invalidate.1.height != 0 {
-                self.display
-                    .flush_region_graphics(invalidate, &mut self.delay)
-                    .expect("could not flush display");
-            }
+            panel.render(&mut self.framebuffer);
         }
+        self.flush_framebuffer();
     }
 }
 
@@ -355,6 +754,13 @@ fn main() -> ! {
     let mut app = Box::new(App::new());
     unsafe {
         APP = &mut *app as *mut App;
+        // patches are applied through APP
+        (*APP).load_settings();
+        // the tick handler goes through APP, so it can only start now
+        cortex_m::peripheral::NVIC::unmask(stm32f4::stm32f407::Interrupt::TIM7);
+        cortex_m::peripheral::NVIC::unmask(stm32f4::stm32f407::Interrupt::EXTI9_5);
+        cortex_m::peripheral::NVIC::unmask(stm32f4::stm32f407::Interrupt::USART2);
+        cortex_m::peripheral::NVIC::unmask(stm32f4::stm32f407::Interrupt::OTG_FS);
     }
 
     app.setup_ui();
@@ -371,6 +777,47 @@ fn main() -> ! {
 fn UART4() {
     unsafe {
         (*APP).midi_input.handle_midi_irq();
+        (*APP).midi_output.flush();
+    }
+}
+
+#[interrupt]
+fn OTG_FS() {
+    unsafe {
+        let received = (*APP).usb_midi.poll();
+        if !received.is_empty() {
+            (*APP).midi_input.handle_usb(&received);
+            (*APP).midi_output.flush();
+        }
+        (*APP).usb_midi.flush();
+    }
+}
+
+#[interrupt]
+fn USART2() {
+    unsafe {
+        (*APP).midi_output.handle_irq();
+    }
+}
+
+#[interrupt]
+fn TIM7() {
+    unsafe {
+        (*APP).ticker.handle_irq();
+        let now = ticker::now();
+        let high = (*APP).trigger_pin.is_high().unwrap();
+        (*APP).midi_input.handle_trigger(high, now);
+        (*APP).midi_input.handle_tick(now);
+    }
+}
+
+#[interrupt]
+fn EXTI9_5() {
+    unsafe {
+        // only the trigger pin is an interrupt source on these lines
+        (*stm32::EXTI::ptr()).pr.write(|w| w.pr9().set_bit());
+        let high = (*APP).trigger_pin.is_high().unwrap();
+        (*APP).midi_input.handle_trigger(high, ticker::now());
     }
 }
 
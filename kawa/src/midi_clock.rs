@@ -0,0 +1,158 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// Follows the tempo of an external MIDI clock. The tempo is measured over the last beat,
+// which averages out the jitter of single ticks, and then smoothed unless it changes a lot.
+
+pub const TICKS_PER_BEAT: usize = 24;
+// Step lengths of note divisions in ticks:
+// 1/1, 1/2, 1/2T, 1/4., 1/4, 1/4T, 1/8., 1/8, 1/8T, 1/16., 1/16, 1/16T, 1/32
+pub const DIVISION_TICKS: [u32; 13] = [96, 48, 32, 36, 24, 16, 18, 12, 8, 9, 6, 4, 3];
+pub const DEFAULT_BPM: f32 = 120.0;
+pub const MIN_BPM: f32 = 20.0;
+pub const MAX_BPM: f32 = 300.0;
+// relative difference from the current tempo treated as a tempo change instead of jitter
+const TEMPO_CHANGE: f32 = 0.05;
+const SMOOTHING: f32 = 0.2;
+// a longer gap between ticks restarts the measurement
+const TIMEOUT_MS: u32 = 500;
+const MS_PER_MINUTE: f32 = 60_000.0;
+
+pub struct MidiClock {
+    timestamps: [u32; TICKS_PER_BEAT],
+    index: usize,
+    count: usize,
+    bpm: f32,
+}
+
+impl Default for MidiClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MidiClock {
+    pub fn new() -> Self {
+        MidiClock {
+            timestamps: [0; TICKS_PER_BEAT],
+            index: 0,
+            count: 0,
+            bpm: DEFAULT_BPM,
+        }
+    }
+
+    pub fn bpm(&self) -> f32 {
+        self.bpm
+    }
+
+    // Whether the last tick arrived recently enough to follow the clock
+    pub fn is_running(&self, now: u32) -> bool {
+        self.count > 0 && now.wrapping_sub(self.last_tick()) <= TIMEOUT_MS
+    }
+
+    // Feeds a clock tick received at `now` milliseconds, returns the tempo estimate
+    // once a whole beat has been measured
+    pub fn tick(&mut self, now: u32) -> Option<f32> {
+        if !self.is_running(now) {
+            self.count = 0;
+        }
+
+        let beat_ago = self.timestamps[self.index];
+        self.timestamps[self.index] = now;
+        self.index = (self.index + 1) % TICKS_PER_BEAT;
+        if self.count < TICKS_PER_BEAT {
+            self.count += 1;
+            return None;
+        }
+
+        let beat_ms = now.wrapping_sub(beat_ago).max(1);
+        let estimate = (MS_PER_MINUTE / beat_ms as f32).clamp(MIN_BPM, MAX_BPM);
+        if (estimate - self.bpm).abs() > self.bpm * TEMPO_CHANGE {
+            self.bpm = estimate;
+        } else {
+            self.bpm += (estimate - self.bpm) * SMOOTHING;
+        }
+        Some(self.bpm)
+    }
+
+    fn last_tick(&self) -> u32 {
+        self.timestamps[(self.index + TICKS_PER_BEAT - 1) % TICKS_PER_BEAT]
+    }
+}
+
+// Length of a clock tick in milliseconds
+pub fn tick_ms(bpm: f32) -> f32 {
+    MS_PER_MINUTE / (bpm * TICKS_PER_BEAT as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Feeds ticks `interval` ms apart from `start` and returns the last estimate
+    fn feed(clock: &mut MidiClock, start: u32, interval: u32, ticks: u32) -> Option<f32> {
+        let mut bpm = None;
+        for tick in 0..ticks {
+            bpm = clock.tick(start + tick * interval).or(bpm);
+        }
+        bpm
+    }
+
+    #[test]
+    fn tempo_is_known_after_a_beat() {
+        let mut clock = MidiClock::new();
+        assert_eq!(feed(&mut clock, 0, 25, TICKS_PER_BEAT as u32), None);
+        // 25ms ticks are 100 BPM
+        assert_eq!(clock.tick(25 * TICKS_PER_BEAT as u32), Some(100.0));
+    }
+
+    #[test]
+    fn jitter_is_smoothed() {
+        let mut clock = MidiClock::new();
+        feed(&mut clock, 0, 25, TICKS_PER_BEAT as u32 + 1);
+        // one late tick is less than the tempo change threshold
+        let bpm = clock.tick(25 * TICKS_PER_BEAT as u32 + 26).unwrap();
+        assert!(bpm < 100.0 && bpm > 99.5);
+    }
+
+    #[test]
+    fn tempo_is_clamped() {
+        let mut clock = MidiClock::new();
+        assert_eq!(
+            feed(&mut clock, 0, 1, 2 * TICKS_PER_BEAT as u32),
+            Some(MAX_BPM)
+        );
+    }
+
+    #[test]
+    fn gap_restarts_the_measurement() {
+        let mut clock = MidiClock::new();
+        feed(&mut clock, 0, 25, TICKS_PER_BEAT as u32 + 1);
+        assert!(clock.is_running(25 * TICKS_PER_BEAT as u32 + TIMEOUT_MS));
+        let start = 25 * TICKS_PER_BEAT as u32 + TIMEOUT_MS + 1;
+        assert!(!clock.is_running(start));
+        assert_eq!(feed(&mut clock, start, 50, TICKS_PER_BEAT as u32), None);
+        assert_eq!(clock.tick(start + 50 * TICKS_PER_BEAT as u32), Some(50.0));
+    }
+
+    #[test]
+    fn tick_length_follows_the_tempo() {
+        assert_eq!(tick_ms(125.0), 20.0);
+    }
+}
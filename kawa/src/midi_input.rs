@@ -1,60 +1,690 @@
+use crate::arpeggiator::{ArpEvent, Arpeggiator};
+use crate::driver::ticker;
 use crate::elements_handlers::*;
+use crate::input_gate::{GateEvent, InputGate};
+use crate::midi_clock::{self, MidiClock};
+use crate::midi_output::{OutputQueue, Thru};
+use crate::midi_source::{MidiSource, Received};
+use crate::mpe::Mpe;
+use crate::parameter_cc::{self, ParameterEcho};
+use crate::pedals::Pedals;
+use crate::pressure::{self, Pressure};
+use crate::sequencer::{SeqEvent, Sequencer};
+use crate::settings::OMNI;
+use crate::sysex;
+use crate::trigger_input::{TriggerEvent, TriggerInput};
+use crate::tuning::{self, MasterTuning, TuningMessage, TuningName, TuningTable};
+use crate::velocity::VelocityCurve;
+use crate::voice_allocator::VoiceAllocator;
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
 use midi_port::*;
 
+const CC_MOD_WHEEL: u8 = 1;
+const CC_SUSTAIN: u8 = 64;
+const CC_SOSTENUTO: u8 = 66;
+// pedal values from here on mean down
+const PEDAL_DOWN: u8 = 64;
+
+const TIMING_CLOCK: u8 = 0xF8;
+const START: u8 = 0xFA;
+const CONTINUE: u8 = 0xFB;
+const STOP: u8 = 0xFC;
+
+const MAX_PENDING_COMMANDS: usize = 4;
+
+pub type ThruFilter = Rc<RefCell<Thru>>;
+
+// The MIDI bytes of USB packets, read like a UART
+#[derive(Clone, Default)]
+pub struct ByteQueue(Rc<RefCell<VecDeque<u8>>>);
+
+impl ByteQueue {
+    fn push(&self, bytes: &[u8]) {
+        self.0.borrow_mut().extend(bytes.iter());
+    }
+}
+
+impl embedded_hal::serial::Read<u8> for ByteQueue {
+    type Error = ();
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        self.0.borrow_mut().pop_front().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+// Passes every byte read from the UART to the software thru as well
+pub struct ThruTap<Uart> {
+    uart: Uart,
+    thru: ThruFilter,
+}
+
+impl<Uart> embedded_hal::serial::Read<u8> for ThruTap<Uart>
+where
+    Uart: embedded_hal::serial::Read<u8>,
+{
+    type Error = Uart::Error;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        let result = self.uart.read();
+        match result {
+            Ok(byte) => self.thru.borrow_mut().forward(byte),
+            Err(nb::Error::Other(_)) => self.thru.borrow_mut().reset(),
+            Err(nb::Error::WouldBlock) => (),
+        }
+        result
+    }
+}
+
 pub struct MidiInput<MidiUart>
 where
     MidiUart: embedded_hal::serial::Read<u8>,
 {
-    port: MidiInPort<MidiUart>,
+    din: MidiSource<ThruTap<MidiUart>>,
+    usb: MidiSource<ByteQueue>,
+    usb_bytes: ByteQueue,
+    pub thru: ThruFilter,
+    // replies and thru messages for the MIDI output, replies to USB go back over USB
+    output: OutputQueue,
+    usb_output: OutputQueue,
+    reply_to_usb: bool,
+    // OMNI or the channel + 1, MPE zones take every channel
+    pub channel: u32,
+    clock: MidiClock,
+    pub arpeggiator: Arpeggiator,
+    pub sequencer: Sequencer,
+    pub voices: VoiceAllocator,
+    pub mpe: Mpe,
+    pub pressure: Pressure,
+    pub velocity: VelocityCurve,
+    pub input_gate: InputGate,
+    pub trigger: TriggerInput,
+    pub parameter_echo: ParameterEcho,
+    pedals: Pedals,
+    // pitch bend outside MPE zones, in semitones
+    bend: f32,
+    // pitch of each MIDI note in semitones, the index selects the stored table (0 is
+    // equal temperament)
+    tuning: TuningTable,
+    pub tuning_index: u32,
+    pub master_tuning: MasterTuning,
+    // a bulk dump waiting to be written to flash by the main loop
+    pending_tuning: Option<(u8, TuningName, TuningTable)>,
+    // Kawa SysEx commands waiting for the main loop, they may write to flash, with
+    // whether they came over USB
+    pending_commands: VecDeque<(u8, Vec<u8>, bool)>,
+    // notes played by the arpeggiator and the sequencer, each plays one at a time
+    arp_note: Option<u8>,
+    seq_note: Option<u8>,
+    // in BPM, used while no MIDI clock arrives
+    pub tempo: u32,
+    internal_clock_phase: f32,
 }
 
 impl<MidiUart> MidiInput<MidiUart>
 where
     MidiUart: embedded_hal::serial::Read<u8>,
 {
-    pub fn new(port: MidiInPort<MidiUart>) -> Self {
-        MidiInput { port }
+    pub fn new(uart: MidiUart, output: OutputQueue, usb_output: OutputQueue) -> Self {
+        let thru = Rc::new(RefCell::new(Thru::new(output.clone())));
+        let usb_bytes = ByteQueue::default();
+        MidiInput {
+            din: MidiSource::new(ThruTap {
+                uart,
+                thru: thru.clone(),
+            }),
+            usb: MidiSource::new(usb_bytes.clone()),
+            usb_bytes,
+            thru,
+            output,
+            usb_output,
+            reply_to_usb: false,
+            channel: OMNI,
+            clock: MidiClock::new(),
+            arpeggiator: Arpeggiator::new(),
+            sequencer: Sequencer::new(),
+            mpe: Mpe::new(),
+            pressure: Pressure::new(),
+            velocity: VelocityCurve::new(),
+            input_gate: InputGate::new(),
+            trigger: TriggerInput::new(),
+            parameter_echo: ParameterEcho::new(),
+            pedals: Pedals::new(),
+            bend: 0.0,
+            tuning: tuning::equal_temperament(),
+            tuning_index: 0,
+            master_tuning: MasterTuning::new(),
+            pending_tuning: None,
+            pending_commands: VecDeque::with_capacity(MAX_PENDING_COMMANDS),
+            voices: VoiceAllocator::new(unsafe { Elements_GetNumVoices() } as usize),
+            arp_note: None,
+            seq_note: None,
+            tempo: midi_clock::DEFAULT_BPM as u32,
+            internal_clock_phase: 0.0,
+        }
     }
 
+    // Handles every byte waiting in the UART
     pub fn handle_midi_irq(&mut self) {
-        self.port.poll_uart();
+        self.drain(false);
+    }
+
+    // Handles the MIDI bytes of USB packets the same way
+    pub fn handle_usb(&mut self, bytes: &[u8]) {
+        self.usb_bytes.push(bytes);
+        self.drain(true);
+    }
+
+    fn drain(&mut self, usb: bool) {
+        self.reply_to_usb = usb;
+        loop {
+            let received = if usb {
+                self.usb.receive()
+            } else {
+                self.din.receive()
+            };
+            match received {
+                Some(Received::Message(message)) => self.handle_message(message),
+                Some(Received::SysEx(data)) => self.handle_sysex(&data),
+                Some(Received::Realtime(byte)) => self.handle_realtime(byte),
+                None => break,
+            }
+        }
+    }
 
-        if let Some(message) = self.port.get_message() {
+    fn reply(&self, message: &[u8]) {
+        self.send_reply(self.reply_to_usb, message);
+    }
+
+    // Replies go back over USB or to the MIDI output
+    pub fn send_reply(&self, usb: bool, message: &[u8]) {
+        if usb {
+            self.usb_output.send(message);
+        } else {
+            self.output.send(message);
+        }
+    }
+
+    fn handle_message(&mut self, message: MidiMessage) {
+        if self.receives(&message) {
             match message {
                 MidiMessage::NoteOn {
-                    channel: _,
+                    channel,
                     note,
                     velocity,
-                } => self.handle_note(true, note, velocity),
+                } => self.handle_note(channel, true, note, velocity),
                 MidiMessage::NoteOff {
-                    channel: _,
+                    channel,
                     note,
                     velocity,
-                } => self.handle_note(false, note, velocity),
+                } => self.handle_note(channel, false, note, velocity),
                 MidiMessage::Aftertouch {
-                    channel: _,
+                    channel,
                     note: None,
                     value,
-                } => self.set_modulation(value),
+                } => {
+                    if self.mpe.pressure(channel, value) {
+                        self.apply_expression(channel);
+                    } else {
+                        self.pressure.set(value);
+                    }
+                }
+                MidiMessage::Aftertouch {
+                    channel: _,
+                    note: Some(note),
+                    value,
+                } => {
+                    // only the latest note is followed, the pressure goes to the whole patch
+                    if self.voices.latest() == Some(note) {
+                        self.pressure.set(value);
+                    }
+                }
+                MidiMessage::PitchBendChange { channel, value } => {
+                    if self.mpe.pitch_bend(channel, value) {
+                        for member in self.mpe.affected(channel) {
+                            self.apply_expression(member);
+                        }
+                    } else {
+                        self.bend = self.mpe.bend(channel);
+                        self.apply_pitch_modulation();
+                    }
+                }
+                MidiMessage::ControlChange {
+                    channel,
+                    control,
+                    value,
+                } => {
+                    if self.mpe.control_change(channel, control, value) {
+                        self.apply_expression(channel);
+                    } else {
+                        self.handle_control_change(control, value);
+                    }
+                }
                 _ => (),
             };
         }
     }
 
-    fn handle_note(&mut self, on: bool, note: NoteNumber, velocity: u8) {
+    fn receives(&self, message: &MidiMessage) -> bool {
+        let channel = match *message {
+            MidiMessage::NoteOn { channel, .. }
+            | MidiMessage::NoteOff { channel, .. }
+            | MidiMessage::Aftertouch { channel, .. }
+            | MidiMessage::PitchBendChange { channel, .. }
+            | MidiMessage::ControlChange { channel, .. } => channel,
+            _ => return true,
+        };
+        self.channel == OMNI || channel as u32 + 1 == self.channel || self.mpe.enabled()
+    }
+
+    // Runs the internal clock and the note gates, called every millisecond
+    pub fn handle_tick(&mut self, now: u32) {
+        if self.trigger.clock.is_running(now) {
+            // the ticks between the pulses of the trigger input
+            if self.trigger.clock.update(now) {
+                self.trigger_tick(now);
+            }
+        } else if !self.clock.is_running(now) {
+            let tempo = self.tempo as f32;
+            self.internal_clock_phase += 1.0 / midi_clock::tick_ms(tempo);
+            if self.internal_clock_phase >= 1.0 {
+                self.internal_clock_phase -= 1.0;
+                unsafe {
+                    Elements_SetTempo(tempo);
+                }
+                self.clock_tick(now, midi_clock::tick_ms(tempo));
+            }
+        }
+
+        if let Some(value) = self.pressure.update() {
+            self.apply_pressure(value);
+        }
+
+        let event = self.arpeggiator.update(now);
+        self.play(event);
+        let event = self.sequencer.update(now);
+        self.play_step(event);
+        let event = self
+            .input_gate
+            .update(unsafe { Elements_GetInputEnvelope() });
+        self.play_input(event);
+    }
+
+    // Called on the edges of the trigger input and every millisecond with its level
+    pub fn handle_trigger(&mut self, high: bool, now: u32) {
+        let event = self.trigger.update(high, now);
+        self.play_trigger(event, now);
+    }
+
+    // Ends a gate or a pedal held by the trigger input before its settings change
+    pub fn release_trigger(&mut self) {
+        let event = self.trigger.release();
+        self.play_trigger(event, ticker::now());
+    }
+
+    fn play_trigger(&mut self, event: Option<TriggerEvent>, now: u32) {
+        match event {
+            Some(TriggerEvent::Gate(on)) => {
+                let note = self.trigger.note as u8;
+                self.play_note(on, note, 127);
+            }
+            Some(TriggerEvent::Clock { ticks, start }) => {
+                if start {
+                    unsafe {
+                        Elements_ClockStart(true);
+                    }
+                    self.arpeggiator.reset();
+                }
+                for _ in 0..ticks {
+                    self.trigger_tick(now);
+                }
+            }
+            Some(TriggerEvent::Sustain(down)) => self.set_sustain(down),
+            None => (),
+        }
+    }
+
+    fn trigger_tick(&mut self, now: u32) {
+        let bpm = self.trigger.clock.bpm();
         unsafe {
-            Elements_SetGate(on);
-            if on {
-                Elements_SetNote(note as f32);
-                Elements_SetStrength((velocity as f32) / 127.0);
-                Elements_SetModulation(0.0);
+            Elements_SetTempo(bpm);
+            Elements_ClockTick();
+        }
+        self.clock_tick(now, midi_clock::tick_ms(bpm));
+    }
+
+    fn set_sustain(&mut self, down: bool) {
+        for note in self.pedals.set_sustain(down) {
+            self.release_note(note);
+        }
+    }
+
+    fn set_sostenuto(&mut self, down: bool) {
+        for note in self.pedals.set_sostenuto(down) {
+            self.release_note(note);
+        }
+    }
+
+    // Ends the arpeggio, the keys held for it are played again in the new mode
+    pub fn set_arp_mode(&mut self, mode: u32) {
+        let event = self.arpeggiator.set_mode(mode);
+        self.play(event);
+    }
+
+    pub fn start_sequencer(&mut self) {
+        self.sequencer.start();
+    }
+
+    pub fn stop_sequencer(&mut self) {
+        let event = self.sequencer.stop();
+        self.play_step(event);
+        unsafe {
+            Elements_ClearLocks();
+        }
+    }
+
+    pub fn set_unison(&mut self, unison: bool) {
+        for voice in 0..self.voices.count() {
+            unsafe {
+                (*Elements_GetVoiceState(voice as u32)).gate = false;
             }
         }
+        self.voices.set_unison(unison);
+        self.apply_voicing();
     }
 
-    fn set_modulation(&mut self, value: u8) {
+    pub fn apply_voicing(&self) {
         unsafe {
-            Elements_SetModulation((value as f32) / 127.0);
+            Elements_SetUnison(
+                self.voices.unison(),
+                self.voices.detune as f32 / 100.0,
+                self.voices.spread as f32 / 100.0,
+            );
+        }
+    }
+
+    fn clock_tick(&mut self, now: u32, tick_ms: f32) {
+        let event = self.arpeggiator.clock(now, tick_ms);
+        self.play(event);
+        let event = self.sequencer.clock(now, tick_ms);
+        self.play_step(event);
+    }
+
+    fn handle_note(&mut self, channel: u8, on: bool, note: NoteNumber, velocity: u8) {
+        if on {
+            self.mpe.note_on(channel, note);
+            self.pedals.note_on(note);
+            if self.arpeggiator.enabled() {
+                self.arpeggiator.note_on(note, velocity);
+            } else {
+                self.play_note(true, note, velocity);
+            }
+        } else if self.pedals.note_off(note) {
+            self.release_note(note);
+        }
+
+        if on {
+            // MPE controllers send the initial expression before the note
+            self.apply_expression(channel);
+        } else {
+            self.mpe.note_off(channel, note);
+        }
+    }
+
+    // Ends a key, held notes of the arpeggiator included
+    fn release_note(&mut self, note: u8) {
+        if self.arpeggiator.holds(note) {
+            let event = self.arpeggiator.note_off(note);
+            self.play(event);
+        } else {
+            self.play_note(false, note, 0);
+        }
+    }
+
+    // Applies the expression of an MPE member channel to the voice playing its note, the
+    // latest note also drives the aftertouch source and the slide target
+    fn apply_expression(&mut self, channel: u8) {
+        let expression = match self.mpe.expression(channel) {
+            Some(expression) if !self.arpeggiator.enabled() => expression,
+            _ => return,
+        };
+        let voice = match self.voices.voice_of(expression.note) {
+            Some(voice) => voice,
+            None => return,
+        };
+
+        unsafe {
+            let state = &mut *Elements_GetVoiceState(voice as u32);
+            state.modulation = expression.bend;
+            if let Some(pressure) = expression.pressure {
+                state.strength = pressure;
+            }
+
+            if self.mpe.latest() == Some(channel) {
+                if let Some(pressure) = expression.pressure {
+                    Elements_SetAftertouch(pressure);
+                }
+                // no target wraps past the patch parameters
+                Elements_SetController(
+                    CONTROLLER_SLIDE,
+                    self.mpe.slide_target.wrapping_sub(1),
+                    expression.slide,
+                );
+            }
+        }
+    }
+
+    pub fn set_pressure_destination(&mut self, destination: u32) {
+        unsafe {
+            Elements_SetController(CONTROLLER_PRESSURE, u32::max_value(), 0.0);
+        }
+        self.pressure.destination = destination;
+        self.apply_pitch_modulation();
+        let value = self.pressure.value();
+        self.apply_pressure(value);
+    }
+
+    fn apply_pressure(&mut self, value: f32) {
+        unsafe {
+            Elements_SetAftertouch(value);
+            if self.pressure.destination == pressure::DESTINATION_PITCH {
+                self.apply_pitch_modulation();
+            } else if let Some(parameter) = self.pressure.parameter() {
+                Elements_SetController(CONTROLLER_PRESSURE, parameter, value);
+            }
+        }
+    }
+
+    // Plain pitch bend and pressure routed to the pitch move all voices, on top of the
+    // bend of each MPE note
+    fn apply_pitch_modulation(&self) {
+        let pressure = if self.pressure.destination == pressure::DESTINATION_PITCH {
+            self.pressure.value()
+        } else {
+            0.0
+        };
+        unsafe {
+            Elements_SetModulation(self.bend + pressure);
+        }
+    }
+
+    fn handle_sysex(&mut self, data: &[u8]) {
+        if sysex::is_identity_request(data) {
+            self.reply(&sysex::IDENTITY_REPLY);
+            return;
+        }
+
+        match tuning::parse(data) {
+            Some(TuningMessage::Bulk {
+                program,
+                name,
+                table,
+            }) => self.pending_tuning = Some((program, name, table)),
+            Some(TuningMessage::NoteChanges(changes)) => {
+                // retunes the playing table until the next patch change
+                for (note, pitch) in changes {
+                    self.tuning[note as usize] = pitch;
+                }
+            }
+            None => {
+                if let Some((command, payload)) = sysex::parse(data) {
+                    // the sender has to wait for the flash writes, drop what doesn't fit
+                    if self.pending_commands.len() < MAX_PENDING_COMMANDS {
+                        self.pending_commands.push_back((
+                            command,
+                            payload.to_vec(),
+                            self.reply_to_usb,
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn take_pending_command(&mut self) -> Option<(u8, Vec<u8>, bool)> {
+        self.pending_commands.pop_front()
+    }
+
+    pub fn take_pending_tuning(&mut self) -> Option<(u8, TuningName, TuningTable)> {
+        self.pending_tuning.take()
+    }
+
+    pub fn set_tuning(&mut self, index: u32, table: &TuningTable) {
+        self.tuning_index = index;
+        self.tuning = *table;
+    }
+
+    fn handle_realtime(&mut self, byte: u8) {
+        unsafe {
+            match byte {
+                TIMING_CLOCK => {
+                    let now = ticker::now();
+                    if let Some(bpm) = self.clock.tick(now) {
+                        Elements_SetTempo(bpm);
+                    }
+                    Elements_ClockTick();
+                    self.clock_tick(now, midi_clock::tick_ms(self.clock.bpm()));
+                }
+                START => {
+                    Elements_ClockStart(true);
+                    self.arpeggiator.reset();
+                    self.start_sequencer();
+                }
+                CONTINUE => {
+                    Elements_ClockStart(false);
+                    self.sequencer.resume();
+                }
+                STOP => {
+                    Elements_ClockStop();
+                    self.stop_sequencer();
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn handle_control_change(&mut self, control: u8, value: u8) {
+        match control {
+            CC_MOD_WHEEL => unsafe {
+                Elements_SetModWheel((value as f32) / 127.0);
+            },
+            CC_SUSTAIN => self.set_sustain(value >= PEDAL_DOWN),
+            CC_SOSTENUTO => self.set_sostenuto(value >= PEDAL_DOWN),
+            _ => {
+                if let Some(parameter) = parameter_cc::parameter(control) {
+                    let patch = unsafe { &mut *Elements_GetPatch() };
+                    let values = unsafe {
+                        core::slice::from_raw_parts_mut(
+                            patch as *mut Patch as *mut f32,
+                            NUM_PATCH_PARAMETERS,
+                        )
+                    };
+                    values[parameter] = clamp(parameter_cc::value(value), PARAM_MIN, PARAM_MAX);
+                    self.parameter_echo.received(parameter, value);
+                }
+            }
+        }
+    }
+
+    fn play(&mut self, event: Option<ArpEvent>) {
+        match event {
+            Some(ArpEvent::NoteOn { note, velocity }) => {
+                self.end_arp_note();
+                self.play_note(true, note, velocity);
+                self.arp_note = Some(note);
+            }
+            Some(ArpEvent::NoteOff) => self.end_arp_note(),
+            None => (),
+        }
+    }
+
+    fn end_arp_note(&mut self) {
+        if let Some(note) = self.arp_note.take() {
+            self.play_note(false, note, 0);
+        }
+    }
+
+    fn play_step(&mut self, event: Option<SeqEvent>) {
+        match event {
+            Some(SeqEvent::Trig {
+                note,
+                velocity,
+                locks,
+            }) => {
+                unsafe {
+                    Elements_ClearLocks();
+                    for lock in locks.iter().filter(|lock| lock.parameter != 0) {
+                        Elements_Lock(lock.parameter - 1, lock.value);
+                    }
+                }
+                self.end_seq_note();
+                self.play_note(true, note, velocity);
+                self.seq_note = Some(note);
+            }
+            Some(SeqEvent::NoteOff) => self.end_seq_note(),
+            None => (),
+        }
+    }
+
+    fn end_seq_note(&mut self) {
+        if let Some(note) = self.seq_note.take() {
+            self.play_note(false, note, 0);
+        }
+    }
+
+    fn play_input(&mut self, event: Option<GateEvent>) {
+        match event {
+            Some(GateEvent::Open { note, velocity }) => self.play_note(true, note, velocity),
+            Some(GateEvent::Velocity { note, velocity }) => {
+                // the hit is still rising, the strength follows it
+                if let Some(voice) = self.voices.voice_of(note) {
+                    let state = unsafe { &mut *Elements_GetVoiceState(voice as u32) };
+                    state.strength = self.velocity.strength(velocity);
+                }
+            }
+            Some(GateEvent::Close { note }) => self.play_note(false, note, 0),
+            None => (),
+        }
+    }
+
+    fn play_note(&mut self, on: bool, note: u8, velocity: u8) {
+        let voice = if on {
+            Some(self.voices.note_on(note))
+        } else {
+            self.voices.note_off(note)
+        };
+
+        if let Some(voice) = voice {
+            let state = unsafe { &mut *Elements_GetVoiceState(voice as u32) };
+            state.gate = on;
+            if on {
+                state.note = self.tuning[note as usize] + self.master_tuning.offset();
+                state.strength = self.velocity.strength(velocity);
+                // MPE notes set their own bend next, the global bend is added when rendered
+                state.modulation = 0.0;
+            }
         }
     }
 }
@@ -0,0 +1,199 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// Buffered MIDI output. Messages are queued whole and sent from the transmit interrupt,
+// realtime bytes jump the queue since they may be sent anywhere in the stream.
+
+use crate::midi_source::data_length;
+pub use crate::midi_source::{ALL_CHANNELS, NUM_CHANNELS};
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+// bytes waiting to be sent, about 80 ms at the MIDI rate
+const MAX_QUEUED: usize = 256;
+
+const REALTIME_FIRST: u8 = 0xF8;
+const TUNE_REQUEST: u8 = 0xF6;
+const STATUS_FIRST: u8 = 0x80;
+const SYSTEM_FIRST: u8 = 0xF0;
+const CONTROL_CHANGE: u8 = 0xB0;
+
+// Turns the transmit interrupt of a UART on while there are bytes to send
+pub trait TxInterrupt {
+    fn listen_tx(&mut self, listen: bool);
+}
+
+#[derive(Clone)]
+pub struct OutputQueue(Rc<RefCell<VecDeque<u8>>>);
+
+impl OutputQueue {
+    pub fn new() -> Self {
+        OutputQueue(Rc::new(RefCell::new(VecDeque::with_capacity(MAX_QUEUED))))
+    }
+
+    // Queues a whole message, or drops it when it doesn't fit
+    pub fn send(&self, message: &[u8]) {
+        let mut queue = self.0.borrow_mut();
+        if queue.len() + message.len() <= MAX_QUEUED {
+            queue.extend(message.iter());
+        }
+    }
+
+    pub fn send_realtime(&self, byte: u8) {
+        let mut queue = self.0.borrow_mut();
+        if queue.len() < MAX_QUEUED {
+            queue.push_front(byte);
+        }
+    }
+
+    pub fn pop(&self) -> Option<u8> {
+        self.0.borrow_mut().pop_front()
+    }
+
+    fn front(&self) -> Option<u8> {
+        self.0.borrow().front().cloned()
+    }
+}
+
+pub struct MidiOutput<Uart> {
+    uart: Uart,
+    queue: OutputQueue,
+}
+
+impl<Uart> MidiOutput<Uart>
+where
+    Uart: embedded_hal::serial::Write<u8> + TxInterrupt,
+{
+    pub fn new(uart: Uart, queue: OutputQueue) -> Self {
+        MidiOutput { uart, queue }
+    }
+
+    pub fn send_control_change(&mut self, channel: u8, control: u8, value: u8) {
+        self.queue.send(&[CONTROL_CHANGE | channel, control, value]);
+    }
+
+    // Starts sending what was queued since, called after anything may have been queued
+    pub fn flush(&mut self) {
+        if self.queue.front().is_some() {
+            self.uart.listen_tx(true);
+        }
+    }
+
+    pub fn handle_irq(&mut self) {
+        while let Some(byte) = self.queue.front() {
+            match self.uart.write(byte) {
+                Ok(()) => {
+                    self.queue.pop();
+                }
+                Err(_) => return,
+            }
+        }
+        self.uart.listen_tx(false);
+    }
+}
+
+// Software MIDI thru: forwards what arrives to the output, channel messages only on the
+// channels that pass the filter. Messages are forwarded whole with their status byte, so
+// they don't mix with Kawa's own output. SysEx is not forwarded.
+pub struct Thru {
+    pub enabled: bool,
+    // bit n passes channel n + 1
+    pub channels: u16,
+    queue: OutputQueue,
+    message: [u8; 3],
+    // bytes of the message so far, 0 while there is no status to add data to
+    length: usize,
+    expected: usize,
+}
+
+impl Thru {
+    pub fn new(queue: OutputQueue) -> Self {
+        Thru {
+            enabled: false,
+            channels: ALL_CHANNELS,
+            queue,
+            message: [0; 3],
+            length: 0,
+            expected: 0,
+        }
+    }
+
+    pub fn passes(&self, channel: u32) -> bool {
+        self.channels & (1 << channel) != 0
+    }
+
+    pub fn set_passes(&mut self, channel: u32, passes: bool) {
+        if passes {
+            self.channels |= 1 << channel;
+        } else {
+            self.channels &= !(1 << channel);
+        }
+    }
+
+    // Drops the unfinished message after a byte was lost
+    pub fn reset(&mut self) {
+        self.length = 0;
+    }
+
+    pub fn forward(&mut self, byte: u8) {
+        if byte >= REALTIME_FIRST {
+            if self.enabled {
+                self.queue.send_realtime(byte);
+            }
+            return;
+        }
+
+        if byte >= STATUS_FIRST {
+            self.message[0] = byte;
+            self.length = 1;
+            self.expected = data_length(byte);
+            if byte >= SYSTEM_FIRST && self.expected == 0 {
+                // SysEx data is dropped up to the next status
+                if byte == TUNE_REQUEST {
+                    self.send();
+                }
+                self.length = 0;
+            }
+            return;
+        }
+
+        if self.length == 0 {
+            return;
+        }
+        self.message[self.length] = byte;
+        self.length += 1;
+        if self.length > self.expected {
+            self.send();
+            // running status: the next data bytes start a message with the same status
+            self.length = if self.message[0] < SYSTEM_FIRST { 1 } else { 0 };
+        }
+    }
+
+    fn send(&mut self) {
+        let status = self.message[0];
+        if !self.enabled {
+            return;
+        }
+        if status < SYSTEM_FIRST && !self.passes((status & 0x0F) as u32) {
+            return;
+        }
+        self.queue.send(&self.message[..self.length]);
+    }
+}
@@ -0,0 +1,314 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// USB-MIDI event packets: a header byte with the cable number and the code index number
+// (CIN), which gives the length of the MIDI message in the other three bytes.
+
+pub type Packet = [u8; 4];
+
+const CIN_TWO_BYTE_SYSTEM: u8 = 0x2;
+const CIN_THREE_BYTE_SYSTEM: u8 = 0x3;
+const CIN_SYSEX: u8 = 0x4;
+// also single byte system common messages
+const CIN_SYSEX_END_1: u8 = 0x5;
+const CIN_SINGLE_BYTE: u8 = 0xF;
+
+// MIDI bytes carried by each CIN, 0 and 1 are reserved
+const CIN_LENGTHS: [usize; 16] = [0, 0, 2, 3, 3, 1, 2, 3, 3, 3, 3, 3, 2, 2, 3, 1];
+
+const REALTIME_FIRST: u8 = 0xF8;
+const SYSEX_START: u8 = 0xF0;
+const SYSEX_END: u8 = 0xF7;
+const STATUS_FIRST: u8 = 0x80;
+const SYSTEM_FIRST: u8 = 0xF0;
+
+pub fn cable(packet: &Packet) -> u8 {
+    packet[0] >> 4
+}
+
+// The MIDI bytes in a packet
+pub fn decode(packet: &Packet) -> &[u8] {
+    &packet[1..1 + CIN_LENGTHS[(packet[0] & 0x0F) as usize]]
+}
+
+// Splits a MIDI byte stream into packets for one cable
+pub struct PacketEncoder {
+    cable: u8,
+    bytes: [u8; 3],
+    length: usize,
+    // message length including the status, 0 while there is no status to add data to
+    expected: usize,
+    sysex: bool,
+}
+
+impl PacketEncoder {
+    pub fn new(cable: u8) -> Self {
+        PacketEncoder {
+            cable,
+            bytes: [0; 3],
+            length: 0,
+            expected: 0,
+            sysex: false,
+        }
+    }
+
+    // Returns a packet once the byte completes one
+    pub fn push(&mut self, byte: u8) -> Option<Packet> {
+        if byte >= REALTIME_FIRST {
+            return Some(self.packet(CIN_SINGLE_BYTE, &[byte]));
+        }
+
+        if self.sysex {
+            if byte == SYSEX_END {
+                self.bytes[self.length] = byte;
+                self.length += 1;
+                self.sysex = false;
+                let cin = CIN_SYSEX_END_1 + self.length as u8 - 1;
+                return Some(self.take(cin));
+            }
+            if byte < STATUS_FIRST {
+                self.bytes[self.length] = byte;
+                self.length += 1;
+                return if self.length == self.bytes.len() {
+                    Some(self.take(CIN_SYSEX))
+                } else {
+                    None
+                };
+            }
+            // any other status ends an unfinished SysEx, which is dropped
+            self.sysex = false;
+        }
+
+        if byte == SYSEX_END {
+            // outside a SysEx it ends nothing
+            self.expected = 0;
+            return None;
+        }
+        if byte >= STATUS_FIRST {
+            self.bytes[0] = byte;
+            self.length = 1;
+            self.expected = message_length(byte);
+            if byte == SYSEX_START {
+                self.sysex = true;
+                self.expected = 0;
+            } else if self.expected == 1 {
+                self.expected = 0;
+                return Some(self.take(CIN_SYSEX_END_1));
+            }
+            return None;
+        }
+
+        if self.expected == 0 {
+            return None;
+        }
+        self.bytes[self.length] = byte;
+        self.length += 1;
+        if self.length < self.expected {
+            return None;
+        }
+        let status = self.bytes[0];
+        let cin = if status < SYSTEM_FIRST {
+            status >> 4
+        } else if self.expected == 2 {
+            CIN_TWO_BYTE_SYSTEM
+        } else {
+            CIN_THREE_BYTE_SYSTEM
+        };
+        let packet = self.packet(cin, &self.bytes[..self.length]);
+        // running status: the next data bytes start a message with the same status
+        self.length = 1;
+        if status >= SYSTEM_FIRST {
+            self.expected = 0;
+        }
+        Some(packet)
+    }
+
+    fn take(&mut self, cin: u8) -> Packet {
+        let packet = self.packet(cin, &self.bytes[..self.length]);
+        self.length = 0;
+        packet
+    }
+
+    fn packet(&self, cin: u8, bytes: &[u8]) -> Packet {
+        let mut packet = [self.cable << 4 | cin, 0, 0, 0];
+        packet[1..1 + bytes.len()].copy_from_slice(bytes);
+        packet
+    }
+}
+
+// Bytes of a message starting with the status byte
+fn message_length(status: u8) -> usize {
+    match status & 0xF0 {
+        0xC0 | 0xD0 => 2,
+        0xF0 => match status {
+            0xF1 | 0xF3 => 2,
+            0xF2 => 3,
+            _ => 1,
+        },
+        _ => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(cable: u8, bytes: &[u8]) -> Vec<Packet> {
+        let mut encoder = PacketEncoder::new(cable);
+        bytes
+            .iter()
+            .filter_map(|&byte| encoder.push(byte))
+            .collect()
+    }
+
+    #[test]
+    fn header_holds_the_cable_and_cin() {
+        let packet = [0x39, 0x90, 60, 100];
+        assert_eq!(cable(&packet), 3);
+        assert_eq!(decode(&packet), [0x90, 60, 100]);
+        assert_eq!(encode(3, &[0x90, 60, 100]), [packet]);
+    }
+
+    #[test]
+    fn channel_messages_take_the_cin_of_their_status() {
+        assert_eq!(
+            encode(
+                0,
+                &[
+                    0x80, 60, 0, 0x91, 60, 100, 0xA2, 60, 20, 0xB3, 7, 90, 0xC4, 5, 0xD5, 30, 0xE6,
+                    0, 64
+                ]
+            ),
+            [
+                [0x08, 0x80, 60, 0],
+                [0x09, 0x91, 60, 100],
+                [0x0A, 0xA2, 60, 20],
+                [0x0B, 0xB3, 7, 90],
+                [0x0C, 0xC4, 5, 0],
+                [0x0D, 0xD5, 30, 0],
+                [0x0E, 0xE6, 0, 64],
+            ]
+        );
+    }
+
+    #[test]
+    fn system_messages_take_the_cin_of_their_length() {
+        assert_eq!(
+            encode(
+                0,
+                &[0xF1, 0x12, 0xF2, 0x01, 0x02, 0xF3, 4, 0xF6, 0xF8, 0xFE]
+            ),
+            [
+                [0x02, 0xF1, 0x12, 0],
+                [0x03, 0xF2, 0x01, 0x02],
+                [0x02, 0xF3, 4, 0],
+                [0x05, 0xF6, 0, 0],
+                [0x0F, 0xF8, 0, 0],
+                [0x0F, 0xFE, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_takes_the_length_of_the_cin() {
+        assert_eq!(decode(&[0x0C, 0xC0, 5, 0]), [0xC0, 5]);
+        assert_eq!(decode(&[0x05, 0xF7, 0, 0]), [0xF7]);
+        assert_eq!(decode(&[0x06, 0x01, 0xF7, 0]), [0x01, 0xF7]);
+        assert_eq!(decode(&[0x0F, 0xF8, 0, 0]), [0xF8]);
+        // reserved
+        assert!(decode(&[0x00, 0x90, 60, 100]).is_empty());
+    }
+
+    #[test]
+    fn running_status_is_sent_in_full() {
+        assert_eq!(
+            encode(1, &[0x90, 60, 100, 62, 100, 0xD0, 10, 11]),
+            [
+                [0x19, 0x90, 60, 100],
+                [0x19, 0x90, 62, 100],
+                [0x1D, 0xD0, 10, 0],
+                [0x1D, 0xD0, 11, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn realtime_bytes_go_out_inside_messages() {
+        assert_eq!(
+            encode(0, &[0x90, 60, 0xF8, 100]),
+            [[0x0F, 0xF8, 0, 0], [0x09, 0x90, 60, 100]]
+        );
+    }
+
+    #[test]
+    fn sysex_is_split_into_three_byte_packets() {
+        assert_eq!(
+            encode(2, &[0xF0, 0x7D, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0xF7]),
+            [
+                [0x24, 0xF0, 0x7D, 0x01],
+                [0x24, 0x02, 0x03, 0x04],
+                [0x27, 0x05, 0x06, 0xF7],
+            ]
+        );
+    }
+
+    #[test]
+    fn sysex_end_packet_takes_one_or_two_bytes() {
+        assert_eq!(
+            encode(0, &[0xF0, 0x01, 0x02, 0xF7]),
+            [[0x04, 0xF0, 0x01, 0x02], [0x05, 0xF7, 0, 0]]
+        );
+        assert_eq!(
+            encode(0, &[0xF0, 0x01, 0x02, 0x03, 0xF7]),
+            [[0x04, 0xF0, 0x01, 0x02], [0x06, 0x03, 0xF7, 0]]
+        );
+        assert_eq!(encode(0, &[0xF0, 0xF7]), [[0x06, 0xF0, 0xF7, 0]]);
+        assert_eq!(encode(0, &[0xF0, 0x01, 0xF7]), [[0x07, 0xF0, 0x01, 0xF7]]);
+    }
+
+    #[test]
+    fn sysex_keeps_realtime_bytes_apart() {
+        assert_eq!(
+            encode(0, &[0xF0, 0x01, 0xF8, 0x02, 0xF7]),
+            [
+                [0x0F, 0xF8, 0, 0],
+                [0x04, 0xF0, 0x01, 0x02],
+                [0x05, 0xF7, 0, 0]
+            ]
+        );
+    }
+
+    #[test]
+    fn status_ends_an_unfinished_sysex() {
+        assert_eq!(
+            encode(0, &[0xF0, 0x01, 0x90, 60, 100, 0xF7]),
+            [[0x09, 0x90, 60, 100]]
+        );
+    }
+
+    #[test]
+    fn decoding_restores_the_stream() {
+        let bytes = [0xF0, 0x7D, 0x01, 0x02, 0x03, 0xF7, 0xB0, 7, 90, 0xF8, 0xF6];
+        let decoded: Vec<u8> = encode(0, &bytes)
+            .iter()
+            .flat_map(|packet| decode(packet).to_vec())
+            .collect();
+        assert_eq!(decoded, bytes);
+    }
+}
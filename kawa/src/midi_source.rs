@@ -0,0 +1,466 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// Parses the MIDI byte stream of a UART, or of anything read like one. Realtime bytes may
+// come anywhere, even inside other messages, and are passed on right away. Channel
+// messages may leave out the status byte while it stays the same (running status). After
+// a lost byte the unfinished message is dropped and parsing picks up at the next status.
+
+use alloc::vec::Vec;
+use embedded_hal::serial::Read;
+use midi_port::MidiMessage;
+
+const STATUS_FIRST: u8 = 0x80;
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const POLY_PRESSURE: u8 = 0xA0;
+const CONTROL_CHANGE: u8 = 0xB0;
+const CHANNEL_PRESSURE: u8 = 0xD0;
+const PITCH_BEND: u8 = 0xE0;
+const SYSTEM_FIRST: u8 = 0xF0;
+const SYSEX_START: u8 = 0xF0;
+const SYSEX_END: u8 = 0xF7;
+const REALTIME_FIRST: u8 = 0xF8;
+
+pub const NUM_CHANNELS: u32 = 16;
+// a bit per channel, for channel filters
+pub const ALL_CHANNELS: u16 = 0xFFFF;
+
+// longer messages are dropped, a tuning dump takes 406 bytes
+pub const MAX_SYSEX_SIZE: usize = 512;
+
+pub enum Received {
+    Message(MidiMessage),
+    // the data bytes, without F0 and F7
+    SysEx(Vec<u8>),
+    Realtime(u8),
+}
+
+pub struct MidiSource<Uart> {
+    uart: Uart,
+    // status of the message being received, kept for running status, 0 while there is
+    // none to add data to
+    status: u8,
+    data: [u8; 2],
+    length: usize,
+    sysex: Vec<u8>,
+    receiving_sysex: bool,
+}
+
+impl<Uart> MidiSource<Uart>
+where
+    Uart: Read<u8>,
+{
+    pub fn new(uart: Uart) -> Self {
+        MidiSource {
+            uart,
+            status: 0,
+            data: [0; 2],
+            length: 0,
+            sysex: Vec::new(),
+            receiving_sysex: false,
+        }
+    }
+
+    // Reads until something is received, returns None once the UART has no more bytes
+    pub fn receive(&mut self) -> Option<Received> {
+        loop {
+            match self.uart.read() {
+                Ok(byte) => {
+                    if let Some(received) = self.parse(byte) {
+                        return Some(received);
+                    }
+                }
+                Err(nb::Error::WouldBlock) => return None,
+                // a byte was lost to an overrun or a framing error
+                Err(nb::Error::Other(_)) => self.drop_message(),
+            }
+        }
+    }
+
+    fn parse(&mut self, byte: u8) -> Option<Received> {
+        if byte >= REALTIME_FIRST {
+            return Some(Received::Realtime(byte));
+        }
+        if byte >= STATUS_FIRST {
+            return self.start(byte);
+        }
+
+        if self.receiving_sysex {
+            if self.sysex.len() < MAX_SYSEX_SIZE {
+                self.sysex.push(byte);
+            } else {
+                self.drop_message();
+            }
+            return None;
+        }
+        if self.status == 0 {
+            return None;
+        }
+        self.data[self.length] = byte;
+        self.length += 1;
+        if self.length < data_length(self.status) {
+            return None;
+        }
+
+        self.length = 0;
+        if self.status >= SYSTEM_FIRST {
+            // system common messages aren't used and don't run on
+            self.status = 0;
+            return None;
+        }
+        channel_message(self.status, self.data).map(Received::Message)
+    }
+
+    // Any status byte ends an unfinished message
+    fn start(&mut self, status: u8) -> Option<Received> {
+        let sysex = if status == SYSEX_END && self.receiving_sysex {
+            Some(Received::SysEx(core::mem::take(&mut self.sysex)))
+        } else {
+            None
+        };
+        self.drop_message();
+        if status == SYSEX_START {
+            self.receiving_sysex = true;
+        } else if data_length(status) > 0 {
+            self.status = status;
+        }
+        sysex
+    }
+
+    fn drop_message(&mut self) {
+        self.status = 0;
+        self.length = 0;
+        self.sysex.clear();
+        self.receiving_sysex = false;
+    }
+}
+
+// Data bytes following a status byte
+pub fn data_length(status: u8) -> usize {
+    match status & 0xF0 {
+        0xC0 | 0xD0 => 1,
+        0xF0 => match status {
+            0xF1 | 0xF3 => 1,
+            0xF2 => 2,
+            _ => 0,
+        },
+        _ => 2,
+    }
+}
+
+// Program changes aren't used
+fn channel_message(status: u8, data: [u8; 2]) -> Option<MidiMessage> {
+    let channel = status & 0x0F;
+    let message = match status & 0xF0 {
+        NOTE_OFF => MidiMessage::NoteOff {
+            channel,
+            note: data[0],
+            velocity: data[1],
+        },
+        // a note on without velocity ends the note
+        NOTE_ON if data[1] == 0 => MidiMessage::NoteOff {
+            channel,
+            note: data[0],
+            velocity: 0,
+        },
+        NOTE_ON => MidiMessage::NoteOn {
+            channel,
+            note: data[0],
+            velocity: data[1],
+        },
+        POLY_PRESSURE => MidiMessage::Aftertouch {
+            channel,
+            note: Some(data[0]),
+            value: data[1],
+        },
+        CONTROL_CHANGE => MidiMessage::ControlChange {
+            channel,
+            control: data[0],
+            value: data[1],
+        },
+        CHANNEL_PRESSURE => MidiMessage::Aftertouch {
+            channel,
+            note: None,
+            value: data[0],
+        },
+        PITCH_BEND => MidiMessage::PitchBendChange {
+            channel,
+            value: data[0] as u16 | (data[1] as u16) << 7,
+        },
+        _ => return None,
+    };
+    Some(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::VecDeque;
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+
+    // Err stands for a byte lost to an overrun
+    #[derive(Clone, Default)]
+    struct MockUart(Rc<RefCell<VecDeque<Result<u8, ()>>>>);
+
+    impl MockUart {
+        fn push(&self, bytes: &[u8]) {
+            self.0
+                .borrow_mut()
+                .extend(bytes.iter().map(|&byte| Ok(byte)));
+        }
+
+        fn lose_byte(&self) {
+            self.0.borrow_mut().push_back(Err(()));
+        }
+    }
+
+    impl Read<u8> for MockUart {
+        type Error = ();
+
+        fn read(&mut self) -> nb::Result<u8, ()> {
+            match self.0.borrow_mut().pop_front() {
+                Some(Ok(byte)) => Ok(byte),
+                Some(Err(())) => Err(nb::Error::Other(())),
+                None => Err(nb::Error::WouldBlock),
+            }
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        NoteOn(u8, u8, u8),
+        NoteOff(u8, u8),
+        Pressure(u8, Option<u8>, u8),
+        Control(u8, u8, u8),
+        Bend(u8, u16),
+        SysEx(Vec<u8>),
+        Realtime(u8),
+        Other,
+    }
+
+    // Receives until the UART is drained, like the interrupt handler
+    fn drain(source: &mut MidiSource<MockUart>) -> Vec<Event> {
+        let mut events = Vec::new();
+        while let Some(received) = source.receive() {
+            events.push(match received {
+                Received::Message(MidiMessage::NoteOn {
+                    channel,
+                    note,
+                    velocity,
+                }) => Event::NoteOn(channel, note, velocity),
+                Received::Message(MidiMessage::NoteOff { channel, note, .. }) => {
+                    Event::NoteOff(channel, note)
+                }
+                Received::Message(MidiMessage::Aftertouch {
+                    channel,
+                    note,
+                    value,
+                }) => Event::Pressure(channel, note, value),
+                Received::Message(MidiMessage::ControlChange {
+                    channel,
+                    control,
+                    value,
+                }) => Event::Control(channel, control, value),
+                Received::Message(MidiMessage::PitchBendChange { channel, value }) => {
+                    Event::Bend(channel, value)
+                }
+                Received::Message(_) => Event::Other,
+                Received::SysEx(data) => Event::SysEx(data),
+                Received::Realtime(byte) => Event::Realtime(byte),
+            });
+        }
+        events
+    }
+
+    fn receive(bytes: &[u8]) -> Vec<Event> {
+        let uart = MockUart::default();
+        let mut source = MidiSource::new(uart.clone());
+        uart.push(bytes);
+        drain(&mut source)
+    }
+
+    #[test]
+    fn channel_messages_are_parsed() {
+        assert_eq!(
+            receive(&[0x91, 60, 100, 0x81, 60, 64, 0xB2, 7, 90, 0xA3, 60, 20, 0xD4, 30]),
+            [
+                Event::NoteOn(1, 60, 100),
+                Event::NoteOff(1, 60),
+                Event::Control(2, 7, 90),
+                Event::Pressure(3, Some(60), 20),
+                Event::Pressure(4, None, 30),
+            ]
+        );
+    }
+
+    #[test]
+    fn pitch_bend_takes_the_low_bits_first() {
+        assert_eq!(
+            receive(&[0xE0, 0x00, 0x40, 0xE5, 0x7F, 0x7F, 0xE0, 0x01, 0x00]),
+            [
+                Event::Bend(0, 8192),
+                Event::Bend(5, 16383),
+                Event::Bend(0, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn note_on_without_velocity_is_a_note_off() {
+        assert_eq!(receive(&[0x90, 60, 0]), [Event::NoteOff(0, 60)]);
+    }
+
+    #[test]
+    fn running_status_repeats_the_last_status() {
+        assert_eq!(
+            receive(&[0x90, 60, 100, 64, 90, 60, 0, 0xD0, 10, 11]),
+            [
+                Event::NoteOn(0, 60, 100),
+                Event::NoteOn(0, 64, 90),
+                Event::NoteOff(0, 60),
+                Event::Pressure(0, None, 10),
+                Event::Pressure(0, None, 11),
+            ]
+        );
+    }
+
+    #[test]
+    fn messages_continue_in_the_next_interrupt() {
+        let uart = MockUart::default();
+        let mut source = MidiSource::new(uart.clone());
+        uart.push(&[0x90, 60]);
+        assert!(drain(&mut source).is_empty());
+        uart.push(&[100, 62]);
+        assert_eq!(drain(&mut source), [Event::NoteOn(0, 60, 100)]);
+        uart.push(&[80]);
+        assert_eq!(drain(&mut source), [Event::NoteOn(0, 62, 80)]);
+    }
+
+    #[test]
+    fn data_without_a_status_is_ignored() {
+        assert_eq!(
+            receive(&[60, 100, 0x90, 60, 100]),
+            [Event::NoteOn(0, 60, 100)]
+        );
+    }
+
+    #[test]
+    fn status_ends_an_unfinished_message() {
+        assert_eq!(
+            receive(&[0x90, 60, 0xB0, 1, 64]),
+            [Event::Control(0, 1, 64)]
+        );
+    }
+
+    #[test]
+    fn realtime_bytes_come_out_inside_messages() {
+        assert_eq!(
+            receive(&[0x90, 0xF8, 60, 0xFA, 100, 0xF8, 62, 0xFC, 90]),
+            [
+                Event::Realtime(0xF8),
+                Event::Realtime(0xFA),
+                Event::NoteOn(0, 60, 100),
+                Event::Realtime(0xF8),
+                Event::Realtime(0xFC),
+                Event::NoteOn(0, 62, 90),
+            ]
+        );
+    }
+
+    #[test]
+    fn sysex_is_framed_with_realtime_bytes_taken_out() {
+        assert_eq!(
+            receive(&[0xF0, 0x7D, 0x01, 0xF8, 0x02, 0xFE, 0xF7]),
+            [
+                Event::Realtime(0xF8),
+                Event::Realtime(0xFE),
+                Event::SysEx(vec![0x7D, 0x01, 0x02]),
+            ]
+        );
+    }
+
+    #[test]
+    fn sysex_ends_running_status() {
+        assert_eq!(
+            receive(&[0x90, 60, 100, 0xF0, 0x01, 0xF7, 62, 100]),
+            [Event::NoteOn(0, 60, 100), Event::SysEx(vec![0x01])]
+        );
+    }
+
+    #[test]
+    fn sysex_cut_by_a_status_is_dropped() {
+        assert_eq!(
+            receive(&[0xF0, 0x01, 0x02, 0x90, 60, 100, 0xF7]),
+            [Event::NoteOn(0, 60, 100)]
+        );
+    }
+
+    #[test]
+    fn sysex_too_long_is_dropped() {
+        let mut bytes = vec![0xF0];
+        bytes.extend_from_slice(&[0x01; MAX_SYSEX_SIZE]);
+        bytes.push(0xF7);
+        assert_eq!(receive(&bytes), [Event::SysEx(vec![0x01; MAX_SYSEX_SIZE])]);
+
+        bytes.insert(1, 0x01);
+        assert!(receive(&bytes).is_empty());
+    }
+
+    #[test]
+    fn system_common_messages_end_running_status() {
+        assert_eq!(
+            receive(&[0x90, 60, 100, 0xF3, 5, 62, 100, 0xF2, 1, 2, 0xF6, 0x90, 64, 100]),
+            [Event::NoteOn(0, 60, 100), Event::NoteOn(0, 64, 100)]
+        );
+    }
+
+    #[test]
+    fn program_changes_are_skipped() {
+        assert_eq!(
+            receive(&[0xC0, 5, 6, 0x90, 60, 100]),
+            [Event::NoteOn(0, 60, 100)]
+        );
+    }
+
+    #[test]
+    fn lost_byte_drops_the_message() {
+        let uart = MockUart::default();
+        let mut source = MidiSource::new(uart.clone());
+        uart.push(&[0x90, 60, 100, 62]);
+        uart.lose_byte();
+        // the rest of the message and the data after it, until the next status
+        uart.push(&[100, 64, 100, 0x90, 67, 100]);
+        assert_eq!(
+            drain(&mut source),
+            [Event::NoteOn(0, 60, 100), Event::NoteOn(0, 67, 100)]
+        );
+    }
+
+    #[test]
+    fn lost_byte_drops_the_sysex() {
+        let uart = MockUart::default();
+        let mut source = MidiSource::new(uart.clone());
+        uart.push(&[0xF0, 0x01]);
+        uart.lose_byte();
+        uart.push(&[0x02, 0xF7, 0xF0, 0x03, 0xF7]);
+        assert_eq!(drain(&mut source), [Event::SysEx(vec![0x03])]);
+    }
+}
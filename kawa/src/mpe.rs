@@ -0,0 +1,540 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// MPE receiver. The MPE Configuration Message sets up a lower zone managed on channel 1
+// and/or an upper zone managed on channel 16, each note of a zone then plays on its own
+// member channel with its own pitch bend, pressure and slide (CC74). The MIDI input
+// applies the expression it keeps to the voices.
+
+use core::ops::RangeInclusive;
+
+pub const NUM_CHANNELS: usize = 16;
+
+const LOWER_MANAGER: u8 = 0;
+const UPPER_MANAGER: u8 = 15;
+
+const CC_DATA_ENTRY: u8 = 6;
+const CC_SLIDE: u8 = 74;
+const CC_RPN_LSB: u8 = 100;
+const CC_RPN_MSB: u8 = 101;
+
+const RPN_PITCH_BEND_RANGE: (u8, u8) = (0, 0);
+const RPN_MPE_CONFIGURATION: (u8, u8) = (0, 6);
+const RPN_NULL: (u8, u8) = (127, 127);
+
+// in semitones, defaults of the MPE specification, the manager range also applies to
+// the channels outside the zones
+const MEMBER_BEND_RANGE: u8 = 48;
+pub const MANAGER_BEND_RANGE: u8 = 2;
+
+const PITCH_BEND_CENTER: u16 = 8192;
+const CONTROL_CENTER: u8 = 64;
+
+// Per-note expression of a member channel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Expression {
+    pub note: u8,
+    // in semitones, including the bend of the manager channel
+    pub bend: f32,
+    // 0.0 to 1.0, None until the note got pressure
+    pub pressure: Option<f32>,
+    // -1.0 to 1.0
+    pub slide: f32,
+}
+
+#[derive(Clone, Copy)]
+struct Channel {
+    rpn: (u8, u8),
+    bend_range: u8,
+    // -1.0 to 1.0
+    bend: f32,
+    pressure: Option<f32>,
+    slide: f32,
+    note: Option<u8>,
+    // orders the notes of the member channels
+    started: u32,
+}
+
+impl Channel {
+    fn new(bend_range: u8) -> Self {
+        Channel {
+            rpn: RPN_NULL,
+            bend_range,
+            bend: 0.0,
+            pressure: None,
+            slide: 0.0,
+            note: None,
+            started: 0,
+        }
+    }
+}
+
+pub struct Mpe {
+    // parameter moved by the slide of the latest note, index + 1 in the patch, 0 for none
+    pub slide_target: u32,
+    // of the manager channels and the channels outside the zones, until changed by RPN
+    default_bend_range: u8,
+    channels: [Channel; NUM_CHANNELS],
+    // number of member channels of the lower and upper zones, 0 when not in use
+    lower_members: u8,
+    upper_members: u8,
+    counter: u32,
+}
+
+impl Default for Mpe {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mpe {
+    pub fn new() -> Self {
+        Mpe {
+            slide_target: 0,
+            default_bend_range: MANAGER_BEND_RANGE,
+            channels: [Channel::new(MANAGER_BEND_RANGE); NUM_CHANNELS],
+            lower_members: 0,
+            upper_members: 0,
+            counter: 0,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.lower_members > 0 || self.upper_members > 0
+    }
+
+    pub fn default_bend_range(&self) -> u8 {
+        self.default_bend_range
+    }
+
+    pub fn set_default_bend_range(&mut self, range: u8) {
+        self.default_bend_range = range;
+        for channel in 0..NUM_CHANNELS as u8 {
+            if !self.is_member(channel) {
+                self.channels[channel as usize].bend_range = range;
+            }
+        }
+    }
+
+    pub fn is_member(&self, channel: u8) -> bool {
+        self.lower().contains(&channel) || self.upper().contains(&channel)
+    }
+
+    // Channels whose notes change with a message on the channel: the members of the zone
+    // for a manager channel, the channel itself for a member channel, none otherwise
+    pub fn affected(&self, channel: u8) -> RangeInclusive<u8> {
+        if channel == LOWER_MANAGER && self.lower_members > 0 {
+            self.lower()
+        } else if channel == UPPER_MANAGER && self.upper_members > 0 {
+            self.upper()
+        } else if self.is_member(channel) {
+            channel..=channel
+        } else {
+            empty()
+        }
+    }
+
+    pub fn note_on(&mut self, channel: u8, note: u8) {
+        self.counter = self.counter.wrapping_add(1);
+        let counter = self.counter;
+        if let Some(state) = self.member(channel) {
+            state.note = Some(note);
+            state.started = counter;
+        }
+    }
+
+    // The member channel of the last started note that still plays, the mono expression
+    // follows it
+    pub fn latest(&self) -> Option<u8> {
+        (0..NUM_CHANNELS as u8)
+            .filter(|channel| self.is_member(*channel))
+            .filter(|channel| self.channels[*channel as usize].note.is_some())
+            .max_by_key(|channel| self.channels[*channel as usize].started)
+    }
+
+    // The expression of the channel starts over with its next note
+    pub fn note_off(&mut self, channel: u8, note: u8) {
+        if let Some(state) = self.member(channel) {
+            if state.note == Some(note) {
+                state.note = None;
+                state.pressure = None;
+            }
+        }
+    }
+
+    // Returns true when the message belongs to a zone
+    pub fn control_change(&mut self, channel: u8, control: u8, value: u8) -> bool {
+        let state = &mut self.channels[channel as usize % NUM_CHANNELS];
+        match control {
+            CC_RPN_MSB => state.rpn.0 = value,
+            CC_RPN_LSB => state.rpn.1 = value,
+            CC_DATA_ENTRY => match state.rpn {
+                RPN_MPE_CONFIGURATION => self.configure(channel, value),
+                RPN_PITCH_BEND_RANGE => self.set_bend_range(channel, value),
+                _ => (),
+            },
+            CC_SLIDE => {
+                state.slide = (value as f32 - CONTROL_CENTER as f32) / 63.0;
+                return self.is_member(channel);
+            }
+            _ => return false,
+        }
+        !self.affected(channel).is_empty()
+    }
+
+    // value is the 14-bit bend, returns true when the message belongs to a zone
+    pub fn pitch_bend(&mut self, channel: u8, value: u16) -> bool {
+        let state = &mut self.channels[channel as usize % NUM_CHANNELS];
+        state.bend = (value as f32 - PITCH_BEND_CENTER as f32) / PITCH_BEND_CENTER as f32;
+        !self.affected(channel).is_empty()
+    }
+
+    // The bend of any channel in semitones
+    pub fn bend(&self, channel: u8) -> f32 {
+        let state = &self.channels[channel as usize % NUM_CHANNELS];
+        state.bend * state.bend_range as f32
+    }
+
+    // Channel pressure, returns true for member channels
+    pub fn pressure(&mut self, channel: u8, value: u8) -> bool {
+        let state = &mut self.channels[channel as usize % NUM_CHANNELS];
+        state.pressure = Some(value as f32 / 127.0);
+        self.is_member(channel)
+    }
+
+    pub fn expression(&self, channel: u8) -> Option<Expression> {
+        let manager = if self.lower().contains(&channel) {
+            LOWER_MANAGER
+        } else if self.upper().contains(&channel) {
+            UPPER_MANAGER
+        } else {
+            return None;
+        };
+        let state = &self.channels[channel as usize];
+        let manager = &self.channels[manager as usize];
+        Some(Expression {
+            note: state.note?,
+            bend: state.bend * state.bend_range as f32 + manager.bend * manager.bend_range as f32,
+            pressure: state.pressure,
+            slide: state.slide,
+        })
+    }
+
+    fn lower(&self) -> RangeInclusive<u8> {
+        if self.lower_members > 0 {
+            LOWER_MANAGER + 1..=LOWER_MANAGER + self.lower_members
+        } else {
+            empty()
+        }
+    }
+
+    fn upper(&self) -> RangeInclusive<u8> {
+        if self.upper_members > 0 {
+            UPPER_MANAGER - self.upper_members..=UPPER_MANAGER - 1
+        } else {
+            empty()
+        }
+    }
+
+    fn member(&mut self, channel: u8) -> Option<&mut Channel> {
+        if self.is_member(channel) {
+            Some(&mut self.channels[channel as usize])
+        } else {
+            None
+        }
+    }
+
+    // The MPE Configuration Message sets the member count of the zone managed on the
+    // channel, the zone configured last wins the channels both would use
+    fn configure(&mut self, channel: u8, members: u8) {
+        let members = members.min(NUM_CHANNELS as u8 - 1);
+        match channel {
+            LOWER_MANAGER => {
+                self.lower_members = members;
+                self.upper_members = self.upper_members.min(shared_channels(members));
+            }
+            UPPER_MANAGER => {
+                self.upper_members = members;
+                self.lower_members = self.lower_members.min(shared_channels(members));
+            }
+            _ => return,
+        }
+
+        let (lower, upper) = (self.lower(), self.upper());
+        let default_bend_range = self.default_bend_range;
+        for (index, state) in self.channels.iter_mut().enumerate() {
+            let channel = index as u8;
+            *state = Channel::new(if lower.contains(&channel) || upper.contains(&channel) {
+                MEMBER_BEND_RANGE
+            } else {
+                default_bend_range
+            });
+        }
+    }
+
+    // Pitch bend sensitivity sent on a member channel applies to the whole zone
+    fn set_bend_range(&mut self, channel: u8, range: u8) {
+        let channels = if self.is_member(channel) {
+            if self.lower().contains(&channel) {
+                self.lower()
+            } else {
+                self.upper()
+            }
+        } else {
+            channel..=channel
+        };
+        for channel in channels {
+            self.channels[channel as usize].bend_range = range;
+        }
+    }
+}
+
+// Member channels left for the other zone
+fn shared_channels(members: u8) -> u8 {
+    (NUM_CHANNELS as u8 - 2).saturating_sub(members)
+}
+
+#[allow(clippy::reversed_empty_ranges)]
+fn empty() -> RangeInclusive<u8> {
+    // start past the end
+    1..=0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi_source::{MidiSource, Received};
+    use alloc::collections::VecDeque;
+    use alloc::vec::Vec;
+    use midi_port::MidiMessage;
+
+    struct Bytes(VecDeque<u8>);
+
+    impl embedded_hal::serial::Read<u8> for Bytes {
+        type Error = ();
+
+        fn read(&mut self) -> nb::Result<u8, ()> {
+            self.0.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    // Parses the bytes and passes the messages on like the MIDI input, returns whether
+    // each bend, pressure and control change belongs to a zone
+    fn send(mpe: &mut Mpe, bytes: &[u8]) -> Vec<bool> {
+        let mut source = MidiSource::new(Bytes(bytes.iter().copied().collect()));
+        let mut zoned = Vec::new();
+        while let Some(received) = source.receive() {
+            match received {
+                Received::Message(MidiMessage::NoteOn { channel, note, .. }) => {
+                    mpe.note_on(channel, note)
+                }
+                Received::Message(MidiMessage::NoteOff { channel, note, .. }) => {
+                    mpe.note_off(channel, note)
+                }
+                Received::Message(MidiMessage::Aftertouch {
+                    channel,
+                    note: None,
+                    value,
+                }) => zoned.push(mpe.pressure(channel, value)),
+                Received::Message(MidiMessage::PitchBendChange { channel, value }) => {
+                    zoned.push(mpe.pitch_bend(channel, value))
+                }
+                Received::Message(MidiMessage::ControlChange {
+                    channel,
+                    control,
+                    value,
+                }) => zoned.push(mpe.control_change(channel, control, value)),
+                _ => (),
+            }
+        }
+        zoned
+    }
+
+    // The MPE Configuration Message, RPN 6 with the member count as data entry
+    fn configure(mpe: &mut Mpe, manager: u8, members: u8) {
+        send(mpe, &[0xB0 | manager, 101, 0, 100, 6, 6, members]);
+    }
+
+    fn members(mpe: &Mpe) -> Vec<u8> {
+        (0..NUM_CHANNELS as u8)
+            .filter(|channel| mpe.is_member(*channel))
+            .collect()
+    }
+
+    #[test]
+    fn configuration_message_sets_up_the_lower_zone() {
+        let mut mpe = Mpe::new();
+        assert!(!mpe.enabled());
+
+        configure(&mut mpe, 0, 7);
+        assert!(mpe.enabled());
+        assert_eq!(members(&mpe), [1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(mpe.affected(0), 1..=7);
+        assert_eq!(mpe.affected(3), 3..=3);
+        assert!(mpe.affected(8).is_empty());
+    }
+
+    #[test]
+    fn configuration_message_sets_up_the_upper_zone() {
+        let mut mpe = Mpe::new();
+        configure(&mut mpe, 15, 3);
+        assert_eq!(members(&mpe), [12, 13, 14]);
+        assert_eq!(mpe.affected(15), 12..=14);
+        assert!(mpe.affected(0).is_empty());
+    }
+
+    #[test]
+    fn zone_configured_last_takes_the_shared_channels() {
+        let mut mpe = Mpe::new();
+        configure(&mut mpe, 0, 10);
+        configure(&mut mpe, 15, 10);
+        assert_eq!(
+            members(&mpe),
+            [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14]
+        );
+        assert_eq!(mpe.affected(0), 1..=4);
+        assert_eq!(mpe.affected(15), 5..=14);
+
+        // all channels, the upper manager channel becomes a member
+        configure(&mut mpe, 0, 15);
+        assert_eq!(mpe.affected(0), 1..=15);
+        assert_eq!(mpe.affected(15), 15..=15);
+    }
+
+    #[test]
+    fn zone_without_members_is_off() {
+        let mut mpe = Mpe::new();
+        configure(&mut mpe, 0, 7);
+        configure(&mut mpe, 0, 0);
+        assert!(!mpe.enabled());
+
+        // only the manager channels configure a zone
+        configure(&mut mpe, 5, 7);
+        assert!(!mpe.enabled());
+    }
+
+    #[test]
+    fn member_bend_adds_the_manager_bend() {
+        let mut mpe = Mpe::new();
+        configure(&mut mpe, 0, 7);
+        // half way up on the member, a quarter on the manager
+        assert_eq!(
+            send(
+                &mut mpe,
+                &[0xE2, 0x00, 0x60, 0x92, 60, 100, 0xE0, 0x00, 0x50]
+            ),
+            [true, true]
+        );
+        let expression = mpe.expression(2).unwrap();
+        assert_eq!(expression.note, 60);
+        assert_eq!(expression.bend, 0.5 * 48.0 + 0.25 * 2.0);
+    }
+
+    #[test]
+    fn bend_range_applies_to_the_whole_zone() {
+        let mut mpe = Mpe::new();
+        configure(&mut mpe, 0, 3);
+        send(&mut mpe, &[0xB1, 101, 0, 100, 0, 6, 12]);
+        send(&mut mpe, &[0xE1, 0x00, 0x60, 0xE3, 0x00, 0x60]);
+        assert_eq!(mpe.bend(1), 6.0);
+        assert_eq!(mpe.bend(3), 6.0);
+
+        // outside the zones only the channel itself
+        send(
+            &mut mpe,
+            &[
+                0xB8, 101, 0, 100, 0, 6, 24, 0xE8, 0x00, 0x60, 0xE9, 0x00, 0x60,
+            ],
+        );
+        assert_eq!(mpe.bend(8), 12.0);
+        assert_eq!(mpe.bend(9), 1.0);
+    }
+
+    #[test]
+    fn pressure_and_slide_follow_the_member_note() {
+        let mut mpe = Mpe::new();
+        configure(&mut mpe, 0, 7);
+        send(&mut mpe, &[0x93, 62, 100]);
+        assert_eq!(mpe.expression(3).unwrap().pressure, None);
+
+        assert_eq!(send(&mut mpe, &[0xD3, 127, 0xB3, 74, 127]), [true, true]);
+        let expression = mpe.expression(3).unwrap();
+        assert_eq!(expression.pressure, Some(1.0));
+        assert_eq!(expression.slide, 1.0);
+
+        send(&mut mpe, &[0xB3, 74, 64]);
+        assert_eq!(mpe.expression(3).unwrap().slide, 0.0);
+
+        // the pressure starts over with the next note
+        send(&mut mpe, &[0x83, 62, 0, 0x93, 64, 100]);
+        assert_eq!(mpe.expression(3).unwrap().pressure, None);
+    }
+
+    #[test]
+    fn messages_outside_the_zones_are_not_mpe() {
+        let mut mpe = Mpe::new();
+        configure(&mut mpe, 0, 3);
+        assert_eq!(
+            send(
+                &mut mpe,
+                &[0xD8, 100, 0xE8, 0x00, 0x60, 0xB8, 74, 100, 0xB1, 7, 100]
+            ),
+            [false, false, false, false]
+        );
+        send(&mut mpe, &[0x98, 60, 100]);
+        assert_eq!(mpe.expression(8), None);
+        // members without a note have no expression
+        assert_eq!(mpe.expression(2), None);
+    }
+
+    #[test]
+    fn mono_expression_follows_the_most_recent_note() {
+        let mut mpe = Mpe::new();
+        configure(&mut mpe, 0, 7);
+        assert_eq!(mpe.latest(), None);
+
+        send(&mut mpe, &[0x91, 60, 100, 0x92, 64, 100, 0x93, 67, 100]);
+        assert_eq!(mpe.latest(), Some(3));
+        // back to the previous note still playing
+        send(&mut mpe, &[0x83, 67, 0]);
+        assert_eq!(mpe.latest(), Some(2));
+        send(&mut mpe, &[0x93, 69, 100]);
+        assert_eq!(mpe.latest(), Some(3));
+        send(&mut mpe, &[0x81, 60, 0, 0x82, 64, 0, 0x83, 69, 0]);
+        assert_eq!(mpe.latest(), None);
+    }
+
+    #[test]
+    fn configuration_resets_the_channels() {
+        let mut mpe = Mpe::new();
+        mpe.set_default_bend_range(12);
+        configure(&mut mpe, 0, 3);
+        send(
+            &mut mpe,
+            &[0x91, 60, 100, 0xE1, 0x00, 0x60, 0xE5, 0x00, 0x60],
+        );
+        assert_eq!(mpe.bend(1), 24.0);
+        assert_eq!(mpe.bend(5), 6.0);
+
+        configure(&mut mpe, 0, 3);
+        assert_eq!(mpe.bend(1), 0.0);
+        assert_eq!(mpe.expression(1), None);
+        assert_eq!(mpe.default_bend_range(), 12);
+    }
+}
@@ -0,0 +1,87 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// Maps the patch parameters to MIDI CCs, so a DAW can record knob moves and play them
+// back. Only the undefined controllers are used.
+
+use alloc::vec::Vec;
+
+const PARAMETER_CONTROLS: [u8; 20] = [
+    20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 102, 103, 104, 105, 106, 107, 108, 109,
+];
+const MAX_VALUE: f32 = 127.0;
+
+// The patch parameter a controller moves
+pub fn parameter(control: u8) -> Option<usize> {
+    PARAMETER_CONTROLS.iter().position(|c| *c == control)
+}
+
+pub fn value(cc_value: u8) -> f32 {
+    cc_value as f32 / MAX_VALUE
+}
+
+fn cc_value(value: f32) -> u8 {
+    let scaled = value * MAX_VALUE + 0.5;
+    if scaled < 0.0 {
+        0
+    } else if scaled > MAX_VALUE {
+        MAX_VALUE as u8
+    } else {
+        scaled as u8
+    }
+}
+
+pub struct ParameterEcho {
+    pub enabled: bool,
+    pub channel: u32,
+    // the last values sent or received, changes of less than a CC step are not sent
+    sent: [u8; PARAMETER_CONTROLS.len()],
+}
+
+impl ParameterEcho {
+    pub fn new() -> Self {
+        ParameterEcho {
+            enabled: false,
+            channel: 0,
+            sent: [0; PARAMETER_CONTROLS.len()],
+        }
+    }
+
+    // A received value isn't echoed back
+    pub fn received(&mut self, parameter: usize, cc_value: u8) {
+        self.sent[parameter] = cc_value;
+    }
+
+    // Follows the values of the patch parameters, returns the controls and values to send
+    // when a knob turned since the last update. Other changes, like a patch load, are only
+    // followed.
+    pub fn update(&mut self, values: &[f32], turned: bool) -> Vec<(u8, u8)> {
+        let mut changes = Vec::new();
+        for (index, value) in values.iter().enumerate().take(self.sent.len()) {
+            let cc_value = cc_value(*value);
+            if cc_value != self.sent[index] {
+                self.sent[index] = cc_value;
+                if self.enabled && turned {
+                    changes.push((PARAMETER_CONTROLS[index], cc_value));
+                }
+            }
+        }
+        changes
+    }
+}
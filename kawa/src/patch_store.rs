@@ -0,0 +1,239 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::driver::flash::{Error, Sector, SharedFlash, PATCH_SECTORS};
+pub use crate::library::*;
+use crate::program::Program;
+use core::mem::size_of;
+
+// "KWP7" in little endian, bump the last byte when the record layout changes
+const PATCH_MAGIC: u32 = 0x3750_574B;
+// "KWPS", marks a completely written sector
+const SECTOR_MAGIC: u32 = 0x5350_574B;
+// the global settings follow the records, so they're written as safely
+const SETTINGS_OFFSET: usize = size_of::<Header>() + NUM_SLOTS * size_of::<Record>();
+pub const SETTINGS_SIZE: usize = 1024;
+
+// Starts each patch sector, the records follow it
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Header {
+    magic: u32,
+    generation: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Record {
+    magic: u32,
+    name: PatchName,
+    program: Program,
+}
+
+// The records don't fit in RAM, so every change writes all of them to the other patch
+// sector and the sector with the newest generation is the valid one. The settings block
+// is carried over with them.
+pub struct PatchStore {
+    flash: SharedFlash,
+}
+
+impl PatchStore {
+    pub fn new(flash: SharedFlash) -> Self {
+        PatchStore { flash }
+    }
+
+    pub fn name(&self, slot: usize) -> Option<PatchName> {
+        self.record(slot).map(|record| record.name)
+    }
+
+    pub fn load(&self, slot: usize) -> Option<Program> {
+        self.record(slot).map(|record| record.program)
+    }
+
+    pub fn save(&mut self, slot: usize, program: &Program, name: &PatchName) -> Result<(), Error> {
+        let record = Record {
+            magic: PATCH_MAGIC,
+            name: *name,
+            program: *program,
+        };
+        self.update(None, |index, records| {
+            if index == slot {
+                record
+            } else {
+                records[index]
+            }
+        })
+    }
+
+    pub fn rename(&mut self, slot: usize, name: &PatchName) -> Result<(), Error> {
+        if self.record(slot).is_none() {
+            return Ok(());
+        }
+        self.update(None, |index, records| {
+            let mut record = records[index];
+            if index == slot {
+                record.name = *name;
+            }
+            record
+        })
+    }
+
+    pub fn copy(&mut self, from: usize, to: usize) -> Result<(), Error> {
+        if from == to {
+            return Ok(());
+        }
+        self.update(None, |index, records| {
+            records[if index == to { from } else { index }]
+        })
+    }
+
+    pub fn swap(&mut self, a: usize, b: usize) -> Result<(), Error> {
+        if a == b {
+            return Ok(());
+        }
+        self.update(None, |index, records| {
+            records[if index == a {
+                b
+            } else if index == b {
+                a
+            } else {
+                index
+            }]
+        })
+    }
+
+    pub fn delete(&mut self, slot: usize) -> Result<(), Error> {
+        if self.record(slot).is_none() {
+            return Ok(());
+        }
+        self.update(None, |index, records| {
+            let mut record = records[index];
+            if index == slot {
+                record.magic = 0;
+            }
+            record
+        })
+    }
+
+    // The stored settings block, erased flash until settings are saved
+    pub fn settings(&self) -> Option<&'static [u8]> {
+        self.active_sector()
+            .map(|(sector, _)| self.settings_in(sector))
+    }
+
+    pub fn save_settings(&mut self, settings: &[u8]) -> Result<(), Error> {
+        self.update(Some(settings), |index, records| records[index])
+    }
+
+    // Writes "A01 name" for the slot into the first SLOT_LABEL_LENGTH bytes of label,
+    // names of empty slots are dashed
+    pub fn slot_label(&self, slot: usize, label: &mut [u8]) {
+        label[0] = b'A' + (slot / SLOTS_PER_BANK) as u8;
+        label[1] = b'0' + ((slot % SLOTS_PER_BANK + 1) / 10) as u8;
+        label[2] = b'0' + ((slot % SLOTS_PER_BANK + 1) % 10) as u8;
+        label[3] = b' ';
+        label[4..SLOT_LABEL_LENGTH].copy_from_slice(&self.name(slot).unwrap_or(EMPTY_NAME));
+    }
+
+    fn header(&self, sector: Sector) -> Option<Header> {
+        let header = unsafe { *(self.flash.borrow().read(sector).as_ptr() as *const Header) };
+        if header.magic == SECTOR_MAGIC {
+            Some(header)
+        } else {
+            None
+        }
+    }
+
+    fn active_sector(&self) -> Option<(Sector, u32)> {
+        PATCH_SECTORS
+            .iter()
+            .filter_map(|sector| {
+                self.header(*sector)
+                    .map(|header| (*sector, header.generation))
+            })
+            .max_by_key(|(_, generation)| *generation)
+    }
+
+    fn records_in(&self, sector: Sector) -> &'static [Record] {
+        let data = &self.flash.borrow().read(sector)[size_of::<Header>()..];
+        assert!(data.len() >= NUM_SLOTS * size_of::<Record>());
+        unsafe { core::slice::from_raw_parts(data.as_ptr() as *const Record, NUM_SLOTS) }
+    }
+
+    fn settings_in(&self, sector: Sector) -> &'static [u8] {
+        &self.flash.borrow().read(sector)[SETTINGS_OFFSET..SETTINGS_OFFSET + SETTINGS_SIZE]
+    }
+
+    fn records(&self) -> Option<&'static [Record]> {
+        self.active_sector()
+            .map(|(sector, _)| self.records_in(sector))
+    }
+
+    fn record(&self, slot: usize) -> Option<&'static Record> {
+        self.records()?
+            .get(slot)
+            .filter(|record| record.magic == PATCH_MAGIC)
+    }
+
+    // Writes the record returned by f for every slot, f gets the current records. The
+    // settings are kept unless new ones are given.
+    fn update(
+        &mut self,
+        settings: Option<&[u8]>,
+        f: impl Fn(usize, &[Record]) -> Record,
+    ) -> Result<(), Error> {
+        let (source, target, generation) = match self.active_sector() {
+            Some((sector, generation)) => {
+                let target = if sector.number == PATCH_SECTORS[0].number {
+                    PATCH_SECTORS[1]
+                } else {
+                    PATCH_SECTORS[0]
+                };
+                (sector, target, generation.wrapping_add(1))
+            }
+            // nothing stored yet, erased flash has no valid record
+            None => (PATCH_SECTORS[1], PATCH_SECTORS[0], 0),
+        };
+        let records = self.records_in(source);
+        let settings = settings.unwrap_or_else(|| self.settings_in(source));
+        assert!(settings.len() <= SETTINGS_SIZE && SETTINGS_OFFSET + SETTINGS_SIZE <= target.size);
+
+        let mut flash = self.flash.borrow_mut();
+        flash.erase(target)?;
+        for index in 0..NUM_SLOTS {
+            let record = f(index, records);
+            flash.program(
+                target,
+                size_of::<Header>() + index * size_of::<Record>(),
+                as_bytes(&record),
+            )?;
+        }
+        flash.program(target, SETTINGS_OFFSET, settings)?;
+        // the header goes last, an interrupted update leaves the previous sector valid
+        let header = Header {
+            magic: SECTOR_MAGIC,
+            generation,
+        };
+        flash.program(target, 0, as_bytes(&header))
+    }
+}
+
+pub fn as_bytes<T>(value: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+}
@@ -0,0 +1,208 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// Holds notes after their keys are released. Sustain holds every released note until
+// it lifts, sostenuto only the notes whose keys were down when it was pressed. A key
+// played again takes its note back from the pedals.
+
+use alloc::vec::Vec;
+
+pub struct Pedals {
+    sustain: bool,
+    sostenuto: bool,
+    // keys down, the notes sostenuto latches when pressed
+    held: Vec<u8>,
+    latched: Vec<u8>,
+    // released keys whose notes still sound
+    hanging: Vec<u8>,
+}
+
+impl Default for Pedals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pedals {
+    pub fn new() -> Self {
+        Pedals {
+            sustain: false,
+            sostenuto: false,
+            held: Vec::new(),
+            latched: Vec::new(),
+            hanging: Vec::new(),
+        }
+    }
+
+    pub fn note_on(&mut self, note: u8) {
+        self.hanging.retain(|hanging| *hanging != note);
+        if !self.held.contains(&note) {
+            self.held.push(note);
+        }
+    }
+
+    // Whether the note ends now or is held by a pedal
+    pub fn note_off(&mut self, note: u8) -> bool {
+        self.held.retain(|held| *held != note);
+        if self.sustain || self.latched.contains(&note) {
+            if !self.hanging.contains(&note) {
+                self.hanging.push(note);
+            }
+            false
+        } else {
+            true
+        }
+    }
+
+    // Both return the notes that end with the pedal lifted
+    pub fn set_sustain(&mut self, down: bool) -> Vec<u8> {
+        self.sustain = down;
+        self.release()
+    }
+
+    pub fn set_sostenuto(&mut self, down: bool) -> Vec<u8> {
+        // pedals send a stream of values, only a new press latches
+        if down == self.sostenuto {
+            return Vec::new();
+        }
+        self.sostenuto = down;
+        self.latched = if down { self.held.clone() } else { Vec::new() };
+        self.release()
+    }
+
+    fn release(&mut self) -> Vec<u8> {
+        if self.sustain {
+            return Vec::new();
+        }
+        let latched = &self.latched;
+        let (hanging, released) = core::mem::take(&mut self.hanging)
+            .into_iter()
+            .partition(|note| latched.contains(note));
+        self.hanging = hanging;
+        released
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notes_end_with_their_keys_without_pedals() {
+        let mut pedals = Pedals::new();
+        pedals.note_on(60);
+        assert!(pedals.note_off(60));
+        assert!(pedals.set_sustain(false).is_empty());
+    }
+
+    #[test]
+    fn sustain_holds_released_notes_until_lifted() {
+        let mut pedals = Pedals::new();
+        pedals.note_on(60);
+        assert!(pedals.set_sustain(true).is_empty());
+        pedals.note_on(64);
+        assert!(!pedals.note_off(60));
+        assert!(!pedals.note_off(64));
+        // pedals repeat their value while moving
+        assert!(pedals.set_sustain(true).is_empty());
+        assert_eq!(pedals.set_sustain(false), [60, 64]);
+        assert!(pedals.set_sustain(false).is_empty());
+    }
+
+    #[test]
+    fn sustain_keeps_keys_still_down() {
+        let mut pedals = Pedals::new();
+        pedals.set_sustain(true);
+        pedals.note_on(60);
+        assert!(pedals.set_sustain(false).is_empty());
+        assert!(pedals.note_off(60));
+    }
+
+    #[test]
+    fn replayed_key_takes_its_note_back() {
+        let mut pedals = Pedals::new();
+        pedals.set_sustain(true);
+        pedals.note_on(60);
+        assert!(!pedals.note_off(60));
+        pedals.note_on(60);
+        // the key is down again, lifting the pedal leaves it sounding
+        assert!(pedals.set_sustain(false).is_empty());
+        assert!(pedals.note_off(60));
+    }
+
+    #[test]
+    fn sostenuto_latches_only_keys_down_when_pressed() {
+        let mut pedals = Pedals::new();
+        pedals.note_on(48);
+        assert!(pedals.note_off(48));
+        pedals.note_on(60);
+        assert!(pedals.set_sostenuto(true).is_empty());
+        pedals.note_on(64);
+        assert!(!pedals.note_off(60));
+        assert!(pedals.note_off(64));
+        assert_eq!(pedals.set_sostenuto(false), [60]);
+    }
+
+    #[test]
+    fn sostenuto_latches_on_a_new_press_only() {
+        let mut pedals = Pedals::new();
+        pedals.note_on(60);
+        pedals.set_sostenuto(true);
+        pedals.note_on(64);
+        assert!(pedals.set_sostenuto(true).is_empty());
+        assert!(pedals.note_off(64));
+        assert!(!pedals.note_off(60));
+        assert_eq!(pedals.set_sostenuto(false), [60]);
+    }
+
+    #[test]
+    fn sostenuto_note_replayed_and_released_stays_latched() {
+        let mut pedals = Pedals::new();
+        pedals.note_on(60);
+        pedals.set_sostenuto(true);
+        assert!(!pedals.note_off(60));
+        pedals.note_on(60);
+        assert!(!pedals.note_off(60));
+        assert_eq!(pedals.set_sostenuto(false), [60]);
+    }
+
+    #[test]
+    fn lifting_sustain_leaves_sostenuto_notes() {
+        let mut pedals = Pedals::new();
+        pedals.note_on(60);
+        pedals.set_sostenuto(true);
+        pedals.set_sustain(true);
+        pedals.note_on(64);
+        assert!(!pedals.note_off(60));
+        assert!(!pedals.note_off(64));
+        assert_eq!(pedals.set_sustain(false), [64]);
+        assert_eq!(pedals.set_sostenuto(false), [60]);
+    }
+
+    #[test]
+    fn lifting_sostenuto_under_sustain_keeps_notes() {
+        let mut pedals = Pedals::new();
+        pedals.note_on(60);
+        pedals.set_sostenuto(true);
+        pedals.set_sustain(true);
+        assert!(!pedals.note_off(60));
+        assert!(pedals.set_sostenuto(false).is_empty());
+        assert_eq!(pedals.set_sustain(false), [60]);
+    }
+}
@@ -0,0 +1,80 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// Routes channel pressure and the aftertouch of the latest note to the pitch or to a
+// patch parameter. The value is smoothed every millisecond to avoid zipper noise.
+
+pub const DESTINATION_OFF: u32 = 0;
+pub const DESTINATION_PITCH: u32 = 1;
+// followed by the patch parameters, the destination is the parameter index + 2
+pub const DESTINATION_FIRST_PARAMETER: u32 = 2;
+
+// portion of the remaining distance covered every millisecond
+const SMOOTHING: f32 = 0.05;
+const SETTLED: f32 = 0.0005;
+
+pub struct Pressure {
+    pub destination: u32,
+    target: f32,
+    value: f32,
+}
+
+impl Default for Pressure {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pressure {
+    pub fn new() -> Self {
+        Pressure {
+            destination: DESTINATION_PITCH,
+            target: 0.0,
+            value: 0.0,
+        }
+    }
+
+    pub fn set(&mut self, value: u8) {
+        self.target = value as f32 / 127.0;
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    // Called every millisecond, returns the new value while it moves
+    pub fn update(&mut self) -> Option<f32> {
+        let error = self.target - self.value;
+        if error == 0.0 {
+            return None;
+        }
+
+        self.value = if error.abs() < SETTLED {
+            self.target
+        } else {
+            self.value + error * SMOOTHING
+        };
+        Some(self.value)
+    }
+
+    // The patch parameter index of the destination
+    pub fn parameter(&self) -> Option<u32> {
+        self.destination.checked_sub(DESTINATION_FIRST_PARAMETER)
+    }
+}
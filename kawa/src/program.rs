@@ -0,0 +1,62 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::elements_handlers::*;
+use crate::sequencer::Sequence;
+use crate::APP;
+
+// Everything stored with a patch
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Program {
+    pub patch: Patch,
+    pub modulation: ModulationSettings,
+    pub sequence: Sequence,
+    // 0 is equal temperament, otherwise the stored tuning slot + 1
+    pub tuning: u32,
+    // plays the user samples instead of the built-in ones
+    pub user_samples: bool,
+}
+
+impl Program {
+    pub fn edited() -> Self {
+        unsafe {
+            Program {
+                patch: *Elements_GetPatch(),
+                modulation: *Elements_GetModulation(),
+                sequence: (*APP).midi_input.sequencer.sequence,
+                tuning: (*APP).midi_input.tuning_index,
+                user_samples: (*APP).samples.selected(),
+            }
+        }
+    }
+
+    pub fn apply(&self) {
+        unsafe {
+            *Elements_GetPatch() = self.patch;
+            *Elements_GetModulation() = self.modulation;
+            // the sequencer steps through it in the MIDI interrupt
+            cortex_m::interrupt::free(|_| (*APP).midi_input.sequencer.sequence = self.sequence);
+            let table = (*APP).tunings.table_of(self.tuning);
+            // the notes played by the MIDI interrupt are tuned with it
+            cortex_m::interrupt::free(|_| (*APP).midi_input.set_tuning(self.tuning, &table));
+            (*APP).samples.select(self.user_samples);
+        }
+    }
+}
@@ -0,0 +1,244 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::driver::flash::{SharedFlash, SAMPLE_SECTOR};
+use crate::elements_handlers::*;
+use crate::patch_store::as_bytes;
+use crate::sysex;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+// The sample player excites the resonator with these instead of the built-in hits
+pub const MAX_SAMPLES: usize = 8;
+
+// "KWS1" in little endian, bump the last byte when the layout changes
+const SAMPLE_MAGIC: u32 = 0x3153_574B;
+
+// Programmed last, so a bank only becomes valid once completely written
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Header {
+    magic: u32,
+    count: u32,
+    // in samples from the start of the data, sample i ends where i + 1 starts
+    boundaries: [u32; MAX_SAMPLES + 1],
+}
+
+const DATA_OFFSET: usize = size_of::<Header>();
+const MAX_LENGTH: usize = (SAMPLE_SECTOR.size - DATA_OFFSET) / size_of::<i16>();
+
+// Flash is programmed a word at a time, so an odd sample waits for the next one
+struct Writer {
+    length: usize,
+    pending: Option<i16>,
+    last: i16,
+    header: Header,
+}
+
+pub enum SampleCommand {
+    Clear,
+    Data(Vec<i16>),
+    EndSample,
+    Commit,
+}
+
+impl SampleCommand {
+    // Sample data is 16-bit little endian, packed
+    pub fn parse(command: u8, payload: &[u8]) -> Option<Self> {
+        match command {
+            sysex::SAMPLES_CLEAR => Some(SampleCommand::Clear),
+            sysex::SAMPLES_DATA => Some(SampleCommand::Data(
+                sysex::unpack(payload)
+                    .chunks_exact(2)
+                    .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+                    .collect(),
+            )),
+            sysex::SAMPLES_END_SAMPLE => Some(SampleCommand::EndSample),
+            sysex::SAMPLES_COMMIT => Some(SampleCommand::Commit),
+            _ => None,
+        }
+    }
+}
+
+// Samples are uploaded one after another into the erased sector and the header is
+// written at the end, an upload that doesn't finish or fails to program leaves the sector
+// without a bank
+pub struct SampleStore {
+    flash: SharedFlash,
+    writer: Option<Writer>,
+    selected: bool,
+}
+
+impl SampleStore {
+    pub fn new(flash: SharedFlash) -> Self {
+        SampleStore {
+            flash,
+            writer: None,
+            selected: false,
+        }
+    }
+
+    pub fn selected(&self) -> bool {
+        self.selected
+    }
+
+    // Switches the sample player between the built-in and the user samples, the built-in
+    // ones play while there are none
+    pub fn select(&mut self, user: bool) {
+        self.selected = user;
+        unsafe {
+            match self.bank().filter(|_| user) {
+                Some((data, boundaries)) => Elements_SetSamples(
+                    data.as_ptr(),
+                    boundaries.as_ptr() as *const usize,
+                    boundaries.len() as u32 - 1,
+                ),
+                None => Elements_SetSamples(core::ptr::null(), core::ptr::null(), 0),
+            }
+        }
+    }
+
+    pub fn handle(&mut self, command: SampleCommand) {
+        match command {
+            SampleCommand::Clear => self.clear(),
+            SampleCommand::Data(samples) => self.append(&samples),
+            SampleCommand::EndSample => self.end_sample(),
+            SampleCommand::Commit => self.commit(),
+        }
+    }
+
+    fn bank(&self) -> Option<(&'static [i16], &'static [u32])> {
+        let data = self.flash.borrow().read(SAMPLE_SECTOR);
+        let header = unsafe { &*(data.as_ptr() as *const Header) };
+        let count = header.count as usize;
+        if header.magic != SAMPLE_MAGIC || count == 0 || count > MAX_SAMPLES {
+            return None;
+        }
+        let samples = unsafe {
+            core::slice::from_raw_parts(data[DATA_OFFSET..].as_ptr() as *const i16, MAX_LENGTH)
+        };
+        Some((samples, &header.boundaries[..count + 1]))
+    }
+
+    fn clear(&mut self) {
+        // the exciter must not read the sector while it changes
+        let selected = self.selected;
+        self.select(false);
+        self.selected = selected;
+
+        // the CPU stalls on flash access during the erase
+        unsafe {
+            Elements_Pause(true);
+        }
+        let erased = self.flash.borrow_mut().erase(SAMPLE_SECTOR);
+        unsafe {
+            Elements_Pause(false);
+        }
+        if erased.is_err() {
+            self.writer = None;
+            return;
+        }
+        self.writer = Some(Writer {
+            length: 0,
+            pending: None,
+            last: 0,
+            header: Header {
+                magic: SAMPLE_MAGIC,
+                count: 0,
+                boundaries: [0; MAX_SAMPLES + 1],
+            },
+        });
+    }
+
+    fn append(&mut self, samples: &[i16]) {
+        let writer = match &mut self.writer {
+            Some(writer) if (writer.header.count as usize) < MAX_SAMPLES => writer,
+            _ => return,
+        };
+        // the rest of a sample too long for the sector is dropped
+        let written = writer.length + writer.pending.is_some() as usize;
+        let samples = &samples[..samples.len().min(MAX_LENGTH - written)];
+        if let Some(last) = samples.last() {
+            writer.last = *last;
+        }
+
+        let mut bytes = Vec::with_capacity((samples.len() + 1) * 2);
+        for sample in writer.pending.take().iter().chain(samples) {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        if bytes.len() % 4 != 0 {
+            writer.pending = Some(i16::from_le_bytes([
+                bytes[bytes.len() - 2],
+                bytes[bytes.len() - 1],
+            ]));
+            bytes.truncate(bytes.len() - 2);
+        }
+
+        let offset = DATA_OFFSET + writer.length * 2;
+        let programmed = self
+            .flash
+            .borrow_mut()
+            .program(SAMPLE_SECTOR, offset, &bytes);
+        writer.length += bytes.len() / 2;
+        // the upload is dropped, the sector is left without a bank
+        if programmed.is_err() {
+            self.writer = None;
+        }
+    }
+
+    fn end_sample(&mut self) {
+        let last = match &self.writer {
+            Some(writer) if writer.length > 0 || writer.pending.is_some() => writer.last,
+            _ => return,
+        };
+        // the player interpolates past the end
+        self.append(&[last]);
+
+        if let Some(writer) = &mut self.writer {
+            let count = writer.header.count as usize;
+            if count < MAX_SAMPLES {
+                writer.header.count += 1;
+                writer.header.boundaries[count + 1] =
+                    (writer.length + writer.pending.is_some() as usize) as u32;
+            }
+        }
+    }
+
+    fn commit(&mut self) {
+        let writer = match self.writer.take() {
+            Some(writer) => writer,
+            None => return,
+        };
+        let mut flash = self.flash.borrow_mut();
+        let mut programmed = Ok(());
+        if let Some(pending) = writer.pending {
+            let offset = DATA_OFFSET + writer.length * 2;
+            programmed = flash.program(SAMPLE_SECTOR, offset, &pending.to_le_bytes());
+        }
+        if writer.header.count > 0 && programmed.is_ok() {
+            flash
+                .program(SAMPLE_SECTOR, 0, as_bytes(&writer.header))
+                .ok();
+        }
+        drop(flash);
+
+        let selected = self.selected;
+        self.select(selected);
+    }
+}
@@ -0,0 +1,193 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// Monophonic step sequencer with parameter locks. Like the arpeggiator it only
+// produces events, the sequence itself is stored with the patch.
+
+use crate::midi_clock::DIVISION_TICKS;
+
+pub const MAX_STEPS: usize = 32;
+pub const DEFAULT_STEPS: u8 = 16;
+pub const LOCKS_PER_STEP: usize = 2;
+pub const MIN_GATE: u8 = 5;
+pub const MAX_GATE: u8 = 100;
+
+pub const STEP_TRIG: u8 = 0x01;
+// the note is held into the next step
+pub const STEP_TIE: u8 = 0x02;
+
+// 1/16
+const DEFAULT_DIVISION: u8 = 10;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Lock {
+    // index of the Patch field plus one, 0 when unused
+    pub parameter: u32,
+    pub value: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Step {
+    pub note: u8,
+    pub velocity: u8,
+    // in percent of the step
+    pub gate: u8,
+    pub flags: u8,
+    pub locks: [Lock; LOCKS_PER_STEP],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sequence {
+    pub length: u8,
+    // index in DIVISION_TICKS
+    pub division: u8,
+    pub reserved: [u8; 2],
+    pub steps: [Step; MAX_STEPS],
+}
+
+impl Sequence {
+    pub fn new() -> Self {
+        Sequence {
+            length: DEFAULT_STEPS,
+            division: DEFAULT_DIVISION,
+            reserved: [0; 2],
+            steps: [Step {
+                note: 60,
+                velocity: 100,
+                gate: 50,
+                flags: 0,
+                locks: [Lock {
+                    parameter: 0,
+                    value: 0.0,
+                }; LOCKS_PER_STEP],
+            }; MAX_STEPS],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SeqEvent {
+    // locks replace the ones of the previous trig
+    Trig {
+        note: u8,
+        velocity: u8,
+        locks: [Lock; LOCKS_PER_STEP],
+    },
+    NoteOff,
+}
+
+pub struct Sequencer {
+    pub sequence: Sequence,
+    playing: bool,
+    step: usize,
+    ticks_to_step: u32,
+    gate_on: bool,
+    gate_off_at: Option<u32>,
+}
+
+impl Sequencer {
+    pub fn new() -> Self {
+        Sequencer {
+            sequence: Sequence::new(),
+            playing: false,
+            step: 0,
+            ticks_to_step: 0,
+            gate_on: false,
+            gate_off_at: None,
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    // Plays from the first step on the next clock tick
+    pub fn start(&mut self) {
+        self.playing = true;
+        self.step = 0;
+        self.ticks_to_step = 0;
+    }
+
+    // Plays on from the current step
+    pub fn resume(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn stop(&mut self) -> Option<SeqEvent> {
+        self.playing = false;
+        self.end_gate()
+    }
+
+    // Called on every clock tick at `now` milliseconds, `tick_ms` is the length of a tick
+    // at the current tempo
+    pub fn clock(&mut self, now: u32, tick_ms: f32) -> Option<SeqEvent> {
+        if !self.playing {
+            return None;
+        }
+        if self.ticks_to_step > 0 {
+            self.ticks_to_step -= 1;
+            return None;
+        }
+
+        let sequence = &self.sequence;
+        let step_ticks = DIVISION_TICKS[sequence.division as usize % DIVISION_TICKS.len()];
+        let length = (sequence.length as usize).max(1).min(MAX_STEPS);
+        let step = sequence.steps[self.step % length];
+        self.step = (self.step + 1) % length;
+        self.ticks_to_step = step_ticks - 1;
+
+        if step.flags & STEP_TRIG == 0 {
+            return self.end_gate();
+        }
+
+        self.gate_on = true;
+        self.gate_off_at = if step.flags & STEP_TIE != 0 || step.gate >= MAX_GATE {
+            None
+        } else {
+            let gate_ms = step_ticks as f32 * tick_ms * step.gate as f32 / 100.0;
+            Some(now.wrapping_add((gate_ms as u32).max(1)))
+        };
+        Some(SeqEvent::Trig {
+            note: step.note,
+            velocity: step.velocity,
+            locks: step.locks,
+        })
+    }
+
+    // Called every millisecond to end the gate of the current step
+    pub fn update(&mut self, now: u32) -> Option<SeqEvent> {
+        match self.gate_off_at {
+            Some(gate_off_at) if now.wrapping_sub(gate_off_at) as i32 >= 0 => self.end_gate(),
+            _ => None,
+        }
+    }
+
+    fn end_gate(&mut self) -> Option<SeqEvent> {
+        self.gate_off_at = None;
+        if self.gate_on {
+            self.gate_on = false;
+            Some(SeqEvent::NoteOff)
+        } else {
+            None
+        }
+    }
+}
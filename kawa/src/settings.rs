@@ -0,0 +1,332 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// Global settings, kept apart from the patches: the MIDI setup, how the performance
+// controls play and the audio and trigger inputs. All fields are u32, so the record is
+// also handled as a list of values, each with its own limit.
+
+use crate::audio_input;
+use crate::elements::NUM_PATCH_PARAMETERS;
+use crate::input_gate;
+use crate::library::NUM_SLOTS;
+use crate::midi_source::{ALL_CHANNELS, NUM_CHANNELS};
+use crate::mpe::MANAGER_BEND_RANGE;
+use crate::pressure;
+use crate::sysex;
+use crate::trigger_input;
+use crate::tuning::{self, MasterTuning};
+use crate::velocity;
+use crate::voice_allocator;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+// Bump when the fields change, stored settings of another version are not loaded
+pub const VERSION: u8 = 3;
+
+// MIDI channel of the input, or all of them
+pub const OMNI: u32 = 0;
+// in semitones
+pub const MAX_BEND_RANGE: u32 = 48;
+// The startup patch is a slot + 1, or the init patch
+pub const INIT_PATCH: u32 = 0;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Settings {
+    // OMNI or the channel + 1
+    pub midi_channel: u32,
+    pub velocity_curve: u32,
+    pub velocity_sensitivity: u32,
+    pub bend_range: u32,
+    pub fine_tune: u32,
+    pub octave: u32,
+    pub semitone: u32,
+    pub steal_mode: u32,
+    pub unison: u32,
+    pub detune: u32,
+    pub spread: u32,
+    pub pressure_destination: u32,
+    pub slide_target: u32,
+    pub input_routing: u32,
+    pub input_gain: [u32; audio_input::NUM_CHANNELS],
+    pub input_threshold: [u32; audio_input::NUM_CHANNELS],
+    pub gate_enabled: u32,
+    pub gate_note: u32,
+    pub gate_threshold: u32,
+    pub gate_hysteresis: u32,
+    pub gate_dynamics: u32,
+    pub trigger_mode: u32,
+    pub trigger_polarity: u32,
+    pub trigger_note: u32,
+    pub trigger_resolution: u32,
+    pub thru: u32,
+    pub thru_channels: u32,
+    pub echo: u32,
+    pub echo_channel: u32,
+    pub startup_patch: u32,
+}
+
+const NUM_VALUES: usize = size_of::<Settings>() / size_of::<u32>();
+
+// The highest value of each field
+const MAX: Settings = Settings {
+    midi_channel: NUM_CHANNELS,
+    velocity_curve: velocity::CURVE_LAST - 1,
+    velocity_sensitivity: velocity::MAX_SENSITIVITY,
+    bend_range: MAX_BEND_RANGE,
+    fine_tune: tuning::MAX_FINE_TUNE,
+    octave: tuning::MAX_OCTAVE,
+    semitone: tuning::MAX_SEMITONE,
+    steal_mode: voice_allocator::STEAL_LAST - 1,
+    unison: 1,
+    detune: voice_allocator::MAX_DETUNE,
+    spread: voice_allocator::MAX_SPREAD,
+    pressure_destination: pressure::DESTINATION_FIRST_PARAMETER + NUM_PATCH_PARAMETERS as u32 - 1,
+    slide_target: NUM_PATCH_PARAMETERS as u32,
+    input_routing: audio_input::ROUTING_LAST - 1,
+    input_gain: [audio_input::MAX_GAIN; audio_input::NUM_CHANNELS],
+    input_threshold: [audio_input::MAX_THRESHOLD; audio_input::NUM_CHANNELS],
+    gate_enabled: 1,
+    gate_note: input_gate::MAX_NOTE,
+    gate_threshold: input_gate::MAX_THRESHOLD,
+    gate_hysteresis: input_gate::MAX_HYSTERESIS,
+    gate_dynamics: 1,
+    trigger_mode: trigger_input::MODE_LAST - 1,
+    trigger_polarity: trigger_input::POLARITY_LAST - 1,
+    trigger_note: trigger_input::MAX_NOTE,
+    trigger_resolution: trigger_input::PULSES_PER_BEAT.len() as u32 - 1,
+    thru: 1,
+    thru_channels: ALL_CHANNELS as u32,
+    echo: 1,
+    echo_channel: NUM_CHANNELS - 1,
+    startup_patch: NUM_SLOTS as u32,
+};
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Settings {
+    // The defaults, the same as the state everything starts in
+    pub fn new() -> Self {
+        let master_tuning = MasterTuning::new();
+        Settings {
+            midi_channel: OMNI,
+            velocity_curve: velocity::CURVE_LINEAR,
+            velocity_sensitivity: velocity::MAX_SENSITIVITY,
+            bend_range: MANAGER_BEND_RANGE as u32,
+            fine_tune: master_tuning.fine_tune,
+            octave: master_tuning.octave,
+            semitone: master_tuning.semitone,
+            steal_mode: voice_allocator::STEAL_RELEASED,
+            unison: 0,
+            detune: voice_allocator::DEFAULT_DETUNE,
+            spread: voice_allocator::DEFAULT_SPREAD,
+            pressure_destination: pressure::DESTINATION_PITCH,
+            slide_target: 0,
+            input_routing: audio_input::ROUTING_STEREO,
+            input_gain: [0; audio_input::NUM_CHANNELS],
+            input_threshold: [audio_input::DEFAULT_THRESHOLD; audio_input::NUM_CHANNELS],
+            gate_enabled: 0,
+            gate_note: input_gate::DEFAULT_NOTE,
+            gate_threshold: input_gate::DEFAULT_THRESHOLD,
+            gate_hysteresis: input_gate::DEFAULT_HYSTERESIS,
+            gate_dynamics: 1,
+            trigger_mode: trigger_input::MODE_OFF,
+            trigger_polarity: trigger_input::POLARITY_LOW,
+            trigger_note: trigger_input::DEFAULT_NOTE,
+            trigger_resolution: trigger_input::DEFAULT_RESOLUTION,
+            thru: 0,
+            thru_channels: ALL_CHANNELS as u32,
+            echo: 0,
+            echo_channel: 0,
+            startup_patch: INIT_PATCH,
+        }
+    }
+
+    // Values past their limit are clamped to it
+    pub fn validated(mut self) -> Self {
+        for (value, max) in self.values_mut().iter_mut().zip(MAX.values()) {
+            *value = (*value).min(*max);
+        }
+        self
+    }
+
+    // The version followed by the values in little endian, the data of a SysEx dump
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(1 + NUM_VALUES * size_of::<u32>());
+        data.push(VERSION);
+        for value in self.values() {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data
+    }
+
+    // Dumps of another version or length are refused
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() != 1 + NUM_VALUES * size_of::<u32>() || data[0] != VERSION {
+            return None;
+        }
+        let mut settings = Settings::new();
+        for (value, bytes) in settings.values_mut().iter_mut().zip(data[1..].chunks(4)) {
+            *value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        Some(settings.validated())
+    }
+
+    fn values(&self) -> &[u32] {
+        unsafe { core::slice::from_raw_parts(self as *const Settings as *const u32, NUM_VALUES) }
+    }
+
+    fn values_mut(&mut self) -> &mut [u32] {
+        unsafe { core::slice::from_raw_parts_mut(self as *mut Settings as *mut u32, NUM_VALUES) }
+    }
+}
+
+pub enum SettingsCommand {
+    Request,
+    Dump(Settings),
+}
+
+impl SettingsCommand {
+    // The dump is packed
+    pub fn parse(command: u8, payload: &[u8]) -> Option<Self> {
+        match command {
+            sysex::SETTINGS_REQUEST => Some(SettingsCommand::Request),
+            sysex::SETTINGS_DUMP => {
+                Settings::from_bytes(&sysex::unpack(payload)).map(SettingsCommand::Dump)
+            }
+            _ => None,
+        }
+    }
+}
+
+// The reply to a request
+pub fn dump(settings: &Settings) -> Vec<u8> {
+    sysex::message(sysex::SETTINGS_DUMP, &sysex::pack(&settings.to_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edited() -> Settings {
+        Settings {
+            midi_channel: 10,
+            velocity_curve: velocity::CURVE_LAST - 1,
+            fine_tune: 0,
+            steal_mode: voice_allocator::STEAL_LAST - 1,
+            unison: 1,
+            input_gain: [5, audio_input::MAX_GAIN],
+            trigger_resolution: 3,
+            thru_channels: 0x0101,
+            startup_patch: NUM_SLOTS as u32,
+            ..Settings::new()
+        }
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let settings = edited();
+        let data = settings.to_bytes();
+        assert_eq!(data.len(), 1 + NUM_VALUES * 4);
+        assert_eq!(data[0], VERSION);
+        // the first value, in little endian
+        assert_eq!(data[1..5], [10, 0, 0, 0]);
+        assert_eq!(Settings::from_bytes(&data), Some(settings));
+        assert_eq!(
+            Settings::from_bytes(&Settings::new().to_bytes()),
+            Some(Settings::new())
+        );
+    }
+
+    #[test]
+    fn other_version_is_refused() {
+        let mut data = edited().to_bytes();
+        data[0] = VERSION + 1;
+        assert_eq!(Settings::from_bytes(&data), None);
+        data[0] = VERSION - 1;
+        assert_eq!(Settings::from_bytes(&data), None);
+    }
+
+    #[test]
+    fn other_length_is_refused() {
+        let data = edited().to_bytes();
+        assert_eq!(Settings::from_bytes(&data[..data.len() - 1]), None);
+        assert_eq!(Settings::from_bytes(&data[..data.len() - 4]), None);
+        let mut longer = data.clone();
+        longer.extend_from_slice(&[0; 4]);
+        assert_eq!(Settings::from_bytes(&longer), None);
+        assert_eq!(Settings::from_bytes(&[]), None);
+        assert_eq!(Settings::from_bytes(&[VERSION]), None);
+    }
+
+    #[test]
+    fn values_past_the_limits_are_clamped() {
+        let mut data = vec![VERSION];
+        for _ in 0..NUM_VALUES {
+            data.extend_from_slice(&u32::MAX.to_le_bytes());
+        }
+        let settings = Settings::from_bytes(&data).unwrap();
+        assert_eq!(settings, MAX);
+        assert_eq!(settings.midi_channel, NUM_CHANNELS);
+        assert_eq!(settings.fine_tune, tuning::MAX_FINE_TUNE);
+        assert_eq!(
+            settings.input_threshold,
+            [audio_input::MAX_THRESHOLD; audio_input::NUM_CHANNELS]
+        );
+        assert_eq!(settings.startup_patch, NUM_SLOTS as u32);
+    }
+
+    #[test]
+    fn values_within_the_limits_are_kept() {
+        assert_eq!(Settings::new().validated(), Settings::new());
+        assert_eq!(edited().validated(), edited());
+        assert_eq!(MAX.validated(), MAX);
+
+        let settings = Settings {
+            octave: tuning::MAX_OCTAVE + 1,
+            echo_channel: NUM_CHANNELS,
+            ..edited()
+        };
+        let validated = settings.validated();
+        assert_eq!(validated.octave, tuning::MAX_OCTAVE);
+        assert_eq!(validated.echo_channel, NUM_CHANNELS - 1);
+        // the neighbours stay as they were
+        assert_eq!(validated.fine_tune, settings.fine_tune);
+        assert_eq!(validated.semitone, settings.semitone);
+    }
+
+    #[test]
+    fn dump_parses_back() {
+        let message = dump(&edited());
+        let (command, payload) = sysex::parse(&message[1..message.len() - 1]).unwrap();
+        match SettingsCommand::parse(command, payload) {
+            Some(SettingsCommand::Dump(settings)) => assert_eq!(settings, edited()),
+            _ => panic!("not a settings dump"),
+        }
+        assert!(matches!(
+            SettingsCommand::parse(sysex::SETTINGS_REQUEST, &[]),
+            Some(SettingsCommand::Request)
+        ));
+        assert!(SettingsCommand::parse(sysex::SAMPLES_DATA, &[]).is_none());
+    }
+}
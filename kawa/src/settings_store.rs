@@ -0,0 +1,73 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::driver::flash::{Error, SharedFlash};
+use crate::patch_store::{as_bytes, PatchStore, SETTINGS_SIZE};
+use crate::settings::{self, Settings};
+use core::mem::size_of;
+
+// "KWGS" in little endian, the settings version follows it
+const SETTINGS_MAGIC: u32 = 0x5347_574B;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Record {
+    magic: u32,
+    version: u32,
+    settings: Settings,
+}
+
+// A single record in the settings block of the patch sectors, written with all the
+// patches so an interrupted save leaves the previous settings
+pub struct SettingsStore {
+    patches: PatchStore,
+}
+
+impl SettingsStore {
+    pub fn new(flash: SharedFlash) -> Self {
+        SettingsStore {
+            patches: PatchStore::new(flash),
+        }
+    }
+
+    // The defaults until settings of the current version are saved
+    pub fn load(&self) -> Settings {
+        let data = match self.patches.settings() {
+            Some(data) if data.len() >= size_of::<Record>() => data,
+            _ => return Settings::new(),
+        };
+        // the block is a byte slice, nothing promises it is aligned for the record
+        let record = unsafe { core::ptr::read_unaligned(data.as_ptr() as *const Record) };
+        if record.magic == SETTINGS_MAGIC && record.version == settings::VERSION as u32 {
+            record.settings.validated()
+        } else {
+            Settings::new()
+        }
+    }
+
+    pub fn save(&mut self, settings: &Settings) -> Result<(), Error> {
+        assert!(size_of::<Record>() <= SETTINGS_SIZE);
+        let record = Record {
+            magic: SETTINGS_MAGIC,
+            version: settings::VERSION as u32,
+            settings: *settings,
+        };
+        self.patches.save_settings(as_bytes(&record))
+    }
+}
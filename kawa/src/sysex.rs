@@ -0,0 +1,171 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// Kawa's own SysEx messages: F0 7D 4B 57 <command> <payload> F7, 7D is the manufacturer
+// ID for non-commercial use followed by "KW". Payloads with 8-bit data are packed in
+// groups of 7 bytes, each group preceded by a byte with their top bits.
+
+use alloc::vec::Vec;
+
+const HEADER: [u8; 3] = [0x7D, 0x4B, 0x57];
+
+pub const SAMPLES_CLEAR: u8 = 0x10;
+pub const SAMPLES_DATA: u8 = 0x11;
+pub const SAMPLES_END_SAMPLE: u8 = 0x12;
+pub const SAMPLES_COMMIT: u8 = 0x13;
+
+// Answered with a dump of the global settings, a received dump replaces them
+pub const SETTINGS_REQUEST: u8 = 0x20;
+pub const SETTINGS_DUMP: u8 = 0x21;
+
+// Universal identity reply: any device, family "KW", member 0, version 1.0.0.0
+pub const IDENTITY_REPLY: [u8; 15] = [
+    0xF0, 0x7E, 0x7F, 0x06, 0x02, 0x7D, 0x4B, 0x57, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0xF7,
+];
+
+// Universal identity request: 7E <device> 06 01, answered whatever the device
+pub fn is_identity_request(sysex: &[u8]) -> bool {
+    sysex.len() == 4 && sysex[0] == 0x7E && sysex[2..] == [0x06, 0x01]
+}
+
+// Parses the bytes of a SysEx message, without F0 and F7, into the command and payload
+pub fn parse(sysex: &[u8]) -> Option<(u8, &[u8])> {
+    if sysex.len() > HEADER.len() && sysex[..HEADER.len()] == HEADER {
+        Some((sysex[HEADER.len()], &sysex[HEADER.len() + 1..]))
+    } else {
+        None
+    }
+}
+
+// A complete message, F0 and F7 included
+pub fn message(command: u8, payload: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(HEADER.len() + payload.len() + 3);
+    message.push(0xF0);
+    message.extend_from_slice(&HEADER);
+    message.push(command);
+    message.extend_from_slice(payload);
+    message.push(0xF7);
+    message
+}
+
+pub fn pack(data: &[u8]) -> Vec<u8> {
+    let mut packed = Vec::with_capacity(data.len() * 8 / 7 + 1);
+    for group in data.chunks(7) {
+        let top_bits = group
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (index, byte)| bits | (byte >> 7) << index);
+        packed.push(top_bits);
+        packed.extend(group.iter().map(|byte| byte & 0x7F));
+    }
+    packed
+}
+
+pub fn unpack(packed: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(packed.len() * 7 / 8);
+    for group in packed.chunks(8) {
+        let (top_bits, bytes) = group.split_first().unwrap();
+        for (index, byte) in bytes.iter().enumerate() {
+            data.push(byte | ((top_bits >> index) & 1) << 7);
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity_request_from_any_device() {
+        assert!(is_identity_request(&[0x7E, 0x7F, 0x06, 0x01]));
+        assert!(is_identity_request(&[0x7E, 0x05, 0x06, 0x01]));
+        assert!(!is_identity_request(&[0x7F, 0x7F, 0x06, 0x01]));
+        assert!(!is_identity_request(&[0x7E, 0x7F, 0x06, 0x02]));
+        assert!(!is_identity_request(&[0x7E, 0x7F, 0x06, 0x01, 0x00]));
+        assert!(!is_identity_request(&[0x7E, 0x7F, 0x06]));
+    }
+
+    #[test]
+    fn parse_takes_the_command_and_payload() {
+        assert_eq!(
+            parse(&[0x7D, 0x4B, 0x57, SETTINGS_DUMP, 1, 2, 3]),
+            Some((SETTINGS_DUMP, &[1, 2, 3][..]))
+        );
+        assert_eq!(
+            parse(&[0x7D, 0x4B, 0x57, SETTINGS_REQUEST]),
+            Some((SETTINGS_REQUEST, &[][..]))
+        );
+        // no command, or another manufacturer
+        assert_eq!(parse(&[0x7D, 0x4B, 0x57]), None);
+        assert_eq!(parse(&[0x7D, 0x4B, 0x58, SETTINGS_REQUEST]), None);
+        assert_eq!(parse(&[]), None);
+    }
+
+    #[test]
+    fn message_is_framed_and_parses_back() {
+        let message = message(SAMPLES_DATA, &[0x01, 0x02]);
+        assert_eq!(
+            message,
+            [0xF0, 0x7D, 0x4B, 0x57, SAMPLES_DATA, 0x01, 0x02, 0xF7]
+        );
+        assert_eq!(
+            parse(&message[1..message.len() - 1]),
+            Some((SAMPLES_DATA, &[0x01, 0x02][..]))
+        );
+    }
+
+    #[test]
+    fn pack_puts_the_top_bits_first() {
+        assert_eq!(pack(&[0x80, 0x01, 0xFF]), [0b101, 0x00, 0x01, 0x7F]);
+        assert_eq!(pack(&[]), []);
+        assert_eq!(unpack(&[0b101, 0x00, 0x01, 0x7F]), [0x80, 0x01, 0xFF]);
+    }
+
+    #[test]
+    fn pack_makes_groups_of_seven() {
+        let data = [0xFF; 15];
+        let packed = pack(&data);
+        assert_eq!(packed.len(), 8 + 8 + 2);
+        assert_eq!(packed[0], 0x7F);
+        assert_eq!(packed[8], 0x7F);
+        assert_eq!(packed[16], 0x01);
+        assert!(packed.iter().all(|byte| *byte < 0x80));
+    }
+
+    #[test]
+    fn unpack_restores_any_length() {
+        for length in 0..=30 {
+            let data: Vec<u8> = (0..length).map(|index| (index * 37 + 200) as u8).collect();
+            let packed = pack(&data);
+            assert_eq!(packed.len(), length + data.chunks(7).count());
+            assert_eq!(unpack(&packed), data, "length {}", length);
+        }
+    }
+
+    #[test]
+    fn unpack_takes_what_a_cut_group_holds() {
+        assert_eq!(unpack(&[0x01, 0x00, 0x10, 0x7F]), [0x80, 0x10, 0x7F]);
+        // top bits without data
+        assert_eq!(
+            unpack(&[0x7F, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x7F]).len(),
+            7
+        );
+    }
+}
@@ -0,0 +1,403 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// The trigger jack on PE9 as a gate, a clock or a sustain pedal input.
+
+use crate::midi_clock::{tick_ms, DEFAULT_BPM, MAX_BPM, MIN_BPM, TICKS_PER_BEAT};
+
+pub const MODE_OFF: u32 = 0;
+pub const MODE_GATE: u32 = 1;
+pub const MODE_CLOCK: u32 = 2;
+pub const MODE_SUSTAIN: u32 = 3;
+pub const MODE_LAST: u32 = 4;
+
+// The pin is pulled up, so a switch to ground is active low
+pub const POLARITY_LOW: u32 = 0;
+pub const POLARITY_HIGH: u32 = 1;
+pub const POLARITY_LAST: u32 = 2;
+
+// Clock pulses per beat: quarters, eighths, sixteenths or MIDI clock resolution
+pub const PULSES_PER_BEAT: [u32; 4] = [1, 2, 4, 24];
+pub const DEFAULT_RESOLUTION: u32 = 2;
+
+pub const MAX_NOTE: u32 = 127;
+pub const DEFAULT_NOTE: u32 = 60;
+
+// edges closer than this to the last one are contact bounce
+const DEBOUNCE_MS: u32 = 2;
+// the clock stops when no pulse arrives for this long
+const CLOCK_TIMEOUT_MS: u32 = 2000;
+const MS_PER_MINUTE: f32 = 60_000.0;
+
+#[derive(Debug, PartialEq)]
+pub enum TriggerEvent {
+    Gate(bool),
+    // clock ticks to play now at TICKS_PER_BEAT, the first pulse after a pause starts
+    Clock { ticks: u32, start: bool },
+    Sustain(bool),
+}
+
+// Fills the clock ticks between pulses at the tempo measured between the last two, the
+// next pulse catches up on ticks the previous one still missed.
+pub struct PulseClock {
+    pub resolution: u32,
+    last_pulse: Option<u32>,
+    measured: bool,
+    bpm: f32,
+    phase: f32,
+    ticks: u32,
+}
+
+impl Default for PulseClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PulseClock {
+    pub fn new() -> Self {
+        PulseClock {
+            resolution: DEFAULT_RESOLUTION,
+            last_pulse: None,
+            measured: false,
+            bpm: DEFAULT_BPM,
+            phase: 0.0,
+            ticks: 0,
+        }
+    }
+
+    pub fn bpm(&self) -> f32 {
+        self.bpm
+    }
+
+    pub fn is_running(&self, now: u32) -> bool {
+        self.last_pulse
+            .is_some_and(|last| now.wrapping_sub(last) <= CLOCK_TIMEOUT_MS)
+    }
+
+    // Returns the ticks to play for a pulse at `now` milliseconds
+    pub fn pulse(&mut self, now: u32) -> u32 {
+        let pulses = PULSES_PER_BEAT[self.resolution as usize];
+        let mut ticks = 1;
+        if self.is_running(now) {
+            let interval = now.wrapping_sub(self.last_pulse.unwrap()).max(1);
+            self.bpm = (MS_PER_MINUTE / (interval * pulses) as f32).clamp(MIN_BPM, MAX_BPM);
+            self.measured = true;
+            ticks += self.ticks_per_pulse().saturating_sub(self.ticks);
+        } else {
+            self.measured = false;
+        }
+        self.last_pulse = Some(now);
+        self.ticks = 1;
+        self.phase = 0.0;
+        ticks
+    }
+
+    // Called every millisecond, whether a tick between pulses is due
+    pub fn update(&mut self, now: u32) -> bool {
+        if !self.measured || !self.is_running(now) || self.ticks >= self.ticks_per_pulse() {
+            return false;
+        }
+        self.phase += 1.0 / tick_ms(self.bpm);
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+            self.ticks += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn ticks_per_pulse(&self) -> u32 {
+        TICKS_PER_BEAT as u32 / PULSES_PER_BEAT[self.resolution as usize]
+    }
+}
+
+pub struct TriggerInput {
+    pub mode: u32,
+    pub polarity: u32,
+    // played in gate mode
+    pub note: u32,
+    pub clock: PulseClock,
+    active: bool,
+    last_edge: u32,
+}
+
+impl Default for TriggerInput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TriggerInput {
+    pub fn new() -> Self {
+        TriggerInput {
+            mode: MODE_OFF,
+            polarity: POLARITY_LOW,
+            note: DEFAULT_NOTE,
+            clock: PulseClock::new(),
+            active: false,
+            last_edge: 0,
+        }
+    }
+
+    // Called on every edge of the pin and polled every millisecond, so an edge lost to
+    // the debouncing is still noticed
+    pub fn update(&mut self, high: bool, now: u32) -> Option<TriggerEvent> {
+        let active = high == (self.polarity == POLARITY_HIGH);
+        if active == self.active || now.wrapping_sub(self.last_edge) < DEBOUNCE_MS {
+            return None;
+        }
+        self.active = active;
+        self.last_edge = now;
+
+        match self.mode {
+            MODE_GATE => Some(TriggerEvent::Gate(active)),
+            MODE_CLOCK if active => {
+                let start = !self.clock.is_running(now);
+                let ticks = self.clock.pulse(now);
+                Some(TriggerEvent::Clock { ticks, start })
+            }
+            MODE_SUSTAIN => Some(TriggerEvent::Sustain(active)),
+            _ => None,
+        }
+    }
+
+    // Ends what the input holds before its mode or polarity change, the current level
+    // is picked up again by the next update
+    pub fn release(&mut self) -> Option<TriggerEvent> {
+        if !self.active {
+            return None;
+        }
+        self.active = false;
+        match self.mode {
+            MODE_GATE => Some(TriggerEvent::Gate(false)),
+            MODE_SUSTAIN => Some(TriggerEvent::Sustain(false)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pulses at the given milliseconds with the clock polled every millisecond, returns
+    // the millisecond of every tick played
+    fn run(clock: &mut PulseClock, pulses: &[u32], until: u32) -> Vec<u32> {
+        let mut ticks = Vec::new();
+        for now in 0..=until {
+            if pulses.contains(&now) {
+                for _ in 0..clock.pulse(now) {
+                    ticks.push(now);
+                }
+            }
+            if clock.update(now) {
+                ticks.push(now);
+            }
+        }
+        ticks
+    }
+
+    fn count(ticks: &[u32], from: u32, to: u32) -> usize {
+        ticks
+            .iter()
+            .filter(|&&tick| tick >= from && tick < to)
+            .count()
+    }
+
+    fn clock(resolution: u32) -> PulseClock {
+        let mut clock = PulseClock::new();
+        clock.resolution = resolution;
+        clock
+    }
+
+    #[test]
+    fn every_resolution_fills_a_beat() {
+        // 100 BPM, 600 ms a beat
+        for resolution in 0..PULSES_PER_BEAT.len() as u32 {
+            let pulses_per_beat = PULSES_PER_BEAT[resolution as usize];
+            let interval = 600 / pulses_per_beat;
+            let pulses: Vec<u32> = (0..=4 * pulses_per_beat)
+                .map(|pulse| pulse * interval)
+                .collect();
+            let mut clock = clock(resolution);
+            let ticks = run(&mut clock, &pulses, 2400);
+
+            assert!((clock.bpm() - 100.0).abs() < 0.01);
+            let per_pulse = TICKS_PER_BEAT / pulses_per_beat as usize;
+            // nothing to measure before the second pulse, which catches up on the
+            // missed ticks, then every pulse gets its ticks
+            assert_eq!(count(&ticks, 0, interval), 1);
+            assert_eq!(count(&ticks, interval, interval + 1), per_pulse);
+            assert_eq!(count(&ticks, interval, 2 * interval), 2 * per_pulse - 1);
+            for pulse in pulses.windows(2).skip(2) {
+                assert_eq!(count(&ticks, pulse[0], pulse[1]), per_pulse);
+            }
+            assert_eq!(count(&ticks, 0, 2400), 4 * TICKS_PER_BEAT);
+        }
+    }
+
+    #[test]
+    fn ticks_are_spread_at_the_measured_tempo() {
+        let mut clock = clock(0);
+        let ticks = run(&mut clock, &[0, 600, 1200], 1199);
+        // the second pulse catches up on the first beat, then a tick every 25 ms
+        assert_eq!(count(&ticks, 600, 601), TICKS_PER_BEAT);
+        let filled: Vec<u32> = ticks.iter().cloned().filter(|&tick| tick > 600).collect();
+        assert_eq!(filled.len(), TICKS_PER_BEAT - 1);
+        for (step, tick) in filled.iter().enumerate() {
+            // polled in the millisecond of the pulse too
+            let expected = 600 + 25 * (step as u32 + 1) - 1;
+            assert!(
+                *tick >= expected && *tick <= expected + 1,
+                "tick at {}",
+                tick
+            );
+        }
+    }
+
+    #[test]
+    fn early_pulse_catches_up_the_missing_ticks() {
+        for resolution in 0..PULSES_PER_BEAT.len() as u32 {
+            let pulses_per_beat = PULSES_PER_BEAT[resolution as usize];
+            let interval = 600 / pulses_per_beat;
+            // the third pulse comes at two thirds of the interval
+            let pulses = [0, interval, interval + interval * 2 / 3];
+            let mut clock = clock(resolution);
+            let ticks = run(&mut clock, &pulses, pulses[2]);
+            let per_pulse = TICKS_PER_BEAT / pulses_per_beat as usize;
+            assert_eq!(ticks.len(), 1 + 2 * per_pulse);
+            assert!(count(&ticks, pulses[2], pulses[2] + 1) >= 1);
+        }
+    }
+
+    #[test]
+    fn late_pulse_plays_no_extra_ticks() {
+        let mut clock = clock(0);
+        // 200 BPM measured, then the next pulse only a beat at 100 BPM later
+        let ticks = run(&mut clock, &[0, 300, 900], 900);
+        assert_eq!(count(&ticks, 300, 600), 2 * TICKS_PER_BEAT - 1);
+        assert_eq!(count(&ticks, 600, 900), 0);
+        assert_eq!(count(&ticks, 900, 901), 1);
+        assert_eq!(ticks.len(), 2 * TICKS_PER_BEAT + 1);
+    }
+
+    #[test]
+    fn tempo_is_clamped() {
+        let mut fast = clock(0);
+        fast.pulse(0);
+        fast.pulse(1);
+        assert_eq!(fast.bpm(), MAX_BPM);
+
+        let mut slow = clock(3);
+        slow.pulse(0);
+        slow.pulse(1900);
+        assert_eq!(slow.bpm(), MIN_BPM);
+    }
+
+    #[test]
+    fn clock_stops_without_pulses() {
+        let mut clock = clock(0);
+        let ticks = run(&mut clock, &[0, 600], 5000);
+        assert_eq!(ticks.len(), 2 * TICKS_PER_BEAT);
+        assert!(clock.is_running(600 + CLOCK_TIMEOUT_MS));
+        assert!(!clock.is_running(600 + CLOCK_TIMEOUT_MS + 1));
+
+        // starting over, the pause isn't measured as the tempo
+        let bpm = clock.bpm();
+        let ticks = run(&mut clock, &[5000], 5600);
+        assert_eq!(ticks, [5000]);
+        assert_eq!(clock.bpm(), bpm);
+    }
+
+    #[test]
+    fn clock_input_starts_after_a_pause() {
+        let mut input = TriggerInput::new();
+        input.mode = MODE_CLOCK;
+        input.clock.resolution = 0;
+        assert_eq!(
+            input.update(false, 100),
+            Some(TriggerEvent::Clock {
+                ticks: 1,
+                start: true
+            })
+        );
+        // only the active edge is a pulse
+        assert_eq!(input.update(true, 110), None);
+        assert_eq!(
+            input.update(false, 700),
+            Some(TriggerEvent::Clock {
+                ticks: TICKS_PER_BEAT as u32,
+                start: false
+            })
+        );
+        assert_eq!(input.update(true, 710), None);
+        assert_eq!(
+            input.update(false, 700 + CLOCK_TIMEOUT_MS + 1),
+            Some(TriggerEvent::Clock {
+                ticks: 1,
+                start: true
+            })
+        );
+    }
+
+    #[test]
+    fn gate_edges_are_debounced() {
+        let mut input = TriggerInput::new();
+        input.mode = MODE_GATE;
+        // pulled up, a switch to ground is active
+        assert_eq!(input.update(true, 10), None);
+        assert_eq!(input.update(false, 10), Some(TriggerEvent::Gate(true)));
+        // contact bounce
+        assert_eq!(input.update(true, 11), None);
+        assert_eq!(input.update(false, 11), None);
+        assert_eq!(input.update(true, 11), None);
+        // the level after the bounce is picked up by the next poll
+        assert_eq!(input.update(true, 12), Some(TriggerEvent::Gate(false)));
+        assert_eq!(input.update(true, 13), None);
+        assert_eq!(input.update(false, 20), Some(TriggerEvent::Gate(true)));
+    }
+
+    #[test]
+    fn polarity_high_follows_gate_signals() {
+        let mut input = TriggerInput::new();
+        input.mode = MODE_GATE;
+        input.polarity = POLARITY_HIGH;
+        assert_eq!(input.update(false, 10), None);
+        assert_eq!(input.update(true, 20), Some(TriggerEvent::Gate(true)));
+        assert_eq!(input.update(false, 30), Some(TriggerEvent::Gate(false)));
+    }
+
+    #[test]
+    fn release_ends_a_held_sustain() {
+        let mut input = TriggerInput::new();
+        input.mode = MODE_SUSTAIN;
+        assert_eq!(input.update(false, 10), Some(TriggerEvent::Sustain(true)));
+        assert_eq!(input.release(), Some(TriggerEvent::Sustain(false)));
+        assert_eq!(input.release(), None);
+        // still held after the change, picked up again
+        assert_eq!(input.update(false, 20), Some(TriggerEvent::Sustain(true)));
+
+        input.mode = MODE_OFF;
+        assert_eq!(input.update(true, 30), None);
+        assert_eq!(input.update(false, 40), None);
+        assert_eq!(input.release(), None);
+    }
+}
@@ -0,0 +1,421 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// Tuning tables map MIDI notes to fractional note numbers. They arrive as MIDI Tuning
+// Standard SysEx messages, Scala files are converted to those by tools/scl2syx.py.
+
+use alloc::vec::Vec;
+
+pub const NUM_NOTES: usize = 128;
+pub const NAME_LENGTH: usize = 16;
+
+pub type TuningTable = [f32; NUM_NOTES];
+pub type TuningName = [u8; NAME_LENGTH];
+
+// Bytes between F0 and F7
+const UNIVERSAL_NON_REALTIME: u8 = 0x7E;
+const UNIVERSAL_REALTIME: u8 = 0x7F;
+const MIDI_TUNING: u8 = 0x08;
+const BULK_DUMP: u8 = 0x01;
+const SINGLE_NOTE_CHANGE: u8 = 0x02;
+const SINGLE_NOTE_CHANGE_BANK: u8 = 0x07;
+
+const BULK_DUMP_SIZE: usize = 5 + NAME_LENGTH + NUM_NOTES * 3 + 1;
+// 7F 7F 7F leaves the note as it was
+const NO_CHANGE: [u8; 3] = [0x7F, 0x7F, 0x7F];
+
+// A dump is moved once into the pending tuning, boxing it would only allocate more
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, PartialEq)]
+pub enum TuningMessage {
+    Bulk {
+        program: u8,
+        name: TuningName,
+        table: TuningTable,
+    },
+    // (MIDI note, note number) pairs
+    NoteChanges(Vec<(u8, f32)>),
+}
+
+// Fine tune in cents from -39 to +38, A4 at about 430 Hz to 450 Hz, set above the floor
+pub const FINE_TUNE_FLOOR: i32 = -39;
+pub const MAX_FINE_TUNE: u32 = 77;
+// Transpose in octaves and semitones, also set above their floors
+pub const OCTAVE_FLOOR: i32 = -3;
+pub const MAX_OCTAVE: u32 = 6;
+pub const SEMITONE_FLOOR: i32 = -12;
+pub const MAX_SEMITONE: u32 = 24;
+
+// Shifts every note after the tuning table, for acoustic instruments and transposed
+// keyboard setups
+pub struct MasterTuning {
+    pub fine_tune: u32,
+    pub octave: u32,
+    pub semitone: u32,
+}
+
+impl Default for MasterTuning {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MasterTuning {
+    pub fn new() -> Self {
+        MasterTuning {
+            fine_tune: -FINE_TUNE_FLOOR as u32,
+            octave: -OCTAVE_FLOOR as u32,
+            semitone: -SEMITONE_FLOOR as u32,
+        }
+    }
+
+    pub fn cents(&self) -> i32 {
+        FINE_TUNE_FLOOR + self.fine_tune as i32
+    }
+
+    pub fn octaves(&self) -> i32 {
+        OCTAVE_FLOOR + self.octave as i32
+    }
+
+    pub fn semitones(&self) -> i32 {
+        SEMITONE_FLOOR + self.semitone as i32
+    }
+
+    // in semitones, added to the note number
+    pub fn offset(&self) -> f32 {
+        (self.octaves() * 12 + self.semitones()) as f32 + self.cents() as f32 / 100.0
+    }
+}
+
+pub fn equal_temperament() -> TuningTable {
+    let mut table = [0.0; NUM_NOTES];
+    for (note, value) in table.iter_mut().enumerate() {
+        *value = note as f32;
+    }
+    table
+}
+
+// Parses the bytes of a SysEx message, without F0 and F7
+pub fn parse(sysex: &[u8]) -> Option<TuningMessage> {
+    match sysex {
+        [UNIVERSAL_NON_REALTIME, _device, MIDI_TUNING, BULK_DUMP, program, data @ ..] => {
+            parse_bulk(sysex, *program, data)
+        }
+        [UNIVERSAL_REALTIME, _device, MIDI_TUNING, SINGLE_NOTE_CHANGE, _program, data @ ..] => {
+            parse_note_changes(data)
+        }
+        [UNIVERSAL_NON_REALTIME | UNIVERSAL_REALTIME, _device, MIDI_TUNING, SINGLE_NOTE_CHANGE_BANK, _bank, _program, data @ ..] => {
+            parse_note_changes(data)
+        }
+        _ => None,
+    }
+}
+
+fn parse_bulk(sysex: &[u8], program: u8, data: &[u8]) -> Option<TuningMessage> {
+    if sysex.len() != BULK_DUMP_SIZE {
+        return None;
+    }
+    let checksum = sysex[..sysex.len() - 1]
+        .iter()
+        .fold(0, |checksum, byte| checksum ^ byte)
+        & 0x7F;
+    if checksum != sysex[sysex.len() - 1] {
+        return None;
+    }
+
+    let mut name = [b' '; NAME_LENGTH];
+    name.copy_from_slice(&data[..NAME_LENGTH]);
+    let mut table = equal_temperament();
+    for (value, frequency) in table.iter_mut().zip(data[NAME_LENGTH..].chunks(3)) {
+        if let Some(note) = note_number(frequency) {
+            *value = note;
+        }
+    }
+    Some(TuningMessage::Bulk {
+        program,
+        name,
+        table,
+    })
+}
+
+fn parse_note_changes(data: &[u8]) -> Option<TuningMessage> {
+    let (count, changes) = data.split_first()?;
+    if changes.len() < *count as usize * 4 {
+        return None;
+    }
+    Some(TuningMessage::NoteChanges(
+        changes
+            .chunks(4)
+            .take(*count as usize)
+            .filter_map(|change| note_number(&change[1..]).map(|note| (change[0], note)))
+            .collect(),
+    ))
+}
+
+// Frequency data is the semitone followed by a 14-bit fraction of the next semitone
+fn note_number(frequency: &[u8]) -> Option<f32> {
+    if frequency == NO_CHANGE {
+        return None;
+    }
+    let fraction = ((frequency[1] as u32) << 7) | frequency[2] as u32;
+    Some(frequency[0] as f32 + fraction as f32 / 16384.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A bulk dump from tools/scl2syx.py of tools/test/just.scl, 5-limit just intonation
+    // on C with A4 at 440 Hz
+    const JUST_DUMP: &[u8] = include_bytes!("../tools/test/just.syx");
+
+    // One step of the 14-bit fraction
+    const STEP: f32 = 1.0 / 16384.0;
+
+    // Without F0 and F7
+    fn sysex(message: &[u8]) -> &[u8] {
+        &message[1..message.len() - 1]
+    }
+
+    fn bulk_dump(program: u8, frequencies: &[[u8; 3]]) -> Vec<u8> {
+        let mut dump = vec![
+            UNIVERSAL_NON_REALTIME,
+            0x7F,
+            MIDI_TUNING,
+            BULK_DUMP,
+            program,
+        ];
+        dump.extend_from_slice(b"Test tuning     ");
+        for frequency in frequencies {
+            dump.extend_from_slice(frequency);
+        }
+        let checksum = dump.iter().fold(0, |checksum, byte| checksum ^ byte) & 0x7F;
+        dump.push(checksum);
+        dump
+    }
+
+    fn equal_frequencies() -> Vec<[u8; 3]> {
+        (0..NUM_NOTES as u8).map(|note| [note, 0, 0]).collect()
+    }
+
+    fn assert_near(value: f32, expected: f32) {
+        assert!(
+            (value - expected).abs() <= STEP,
+            "{} is not {}",
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn bulk_dump_of_a_scale() {
+        let (program, name, table) = match parse(sysex(JUST_DUMP)) {
+            Some(TuningMessage::Bulk {
+                program,
+                name,
+                table,
+            }) => (program, name, table),
+            _ => panic!("not a bulk dump"),
+        };
+        assert_eq!(program, 0);
+        assert_eq!(&name, b"just            ");
+        // A4 is the reference, C4 is a major sixth (5/3) below
+        assert_near(table[69], 69.0);
+        let c4 = 69.0 - 12.0 * (5.0f32 / 3.0).log2();
+        assert_near(table[60], c4);
+        assert_near(table[64], c4 + 12.0 * (5.0f32 / 4.0).log2());
+        assert_near(table[67], c4 + 12.0 * (3.0f32 / 2.0).log2());
+        assert_near(table[72], c4 + 12.0);
+        assert_near(table[0], c4 - 60.0);
+    }
+
+    #[test]
+    fn bulk_dump_of_equal_temperament() {
+        let dump = bulk_dump(3, &equal_frequencies());
+        assert_eq!(dump.len(), BULK_DUMP_SIZE);
+        assert_eq!(
+            parse(&dump),
+            Some(TuningMessage::Bulk {
+                program: 3,
+                name: *b"Test tuning     ",
+                table: equal_temperament(),
+            })
+        );
+    }
+
+    #[test]
+    fn bulk_dump_keeps_unchanged_notes_equal() {
+        let mut frequencies = equal_frequencies();
+        frequencies[60] = [60, 0x40, 0x00];
+        frequencies[61] = NO_CHANGE;
+        // the highest frequency, 7F 7F 7F means no change
+        frequencies[127] = [127, 0x7F, 0x7E];
+        match parse(&bulk_dump(0, &frequencies)) {
+            Some(TuningMessage::Bulk { table, .. }) => {
+                assert_eq!(table[60], 60.5);
+                assert_eq!(table[61], 61.0);
+                assert_eq!(table[127], 128.0 - 2.0 * STEP);
+            }
+            _ => panic!("not a bulk dump"),
+        }
+    }
+
+    #[test]
+    fn bulk_dump_with_a_bad_checksum_is_ignored() {
+        let mut dump = bulk_dump(0, &equal_frequencies());
+        *dump.last_mut().unwrap() ^= 0x01;
+        assert_eq!(parse(&dump), None);
+
+        let mut dump = sysex(JUST_DUMP).to_vec();
+        dump[100] ^= 0x01;
+        assert_eq!(parse(&dump), None);
+    }
+
+    #[test]
+    fn bulk_dump_of_the_wrong_length_is_ignored() {
+        let dump = bulk_dump(0, &equal_frequencies()[..NUM_NOTES - 1]);
+        assert_eq!(parse(&dump), None);
+        assert_eq!(parse(&dump[..20]), None);
+        assert_eq!(parse(&sysex(JUST_DUMP)[..BULK_DUMP_SIZE - 1]), None);
+
+        let mut dump = bulk_dump(0, &equal_frequencies());
+        dump.insert(BULK_DUMP_SIZE - 1, 0);
+        assert_eq!(parse(&dump), None);
+    }
+
+    #[test]
+    fn single_note_changes() {
+        let message = [
+            UNIVERSAL_REALTIME,
+            0x7F,
+            MIDI_TUNING,
+            SINGLE_NOTE_CHANGE,
+            0,
+            3,
+            60,
+            60,
+            0x40,
+            0x00,
+            61,
+            0x7F,
+            0x7F,
+            0x7F,
+            69,
+            68,
+            0x7F,
+            0x7F,
+        ];
+        assert_eq!(
+            parse(&message),
+            Some(TuningMessage::NoteChanges(vec![
+                (60, 60.5),
+                (69, 69.0 - STEP)
+            ]))
+        );
+    }
+
+    #[test]
+    fn single_note_changes_with_a_bank() {
+        for sub_id in [UNIVERSAL_NON_REALTIME, UNIVERSAL_REALTIME].iter() {
+            let message = [
+                *sub_id,
+                0x7F,
+                MIDI_TUNING,
+                SINGLE_NOTE_CHANGE_BANK,
+                1,
+                2,
+                1,
+                64,
+                63,
+                0x60,
+                0x00,
+            ];
+            assert_eq!(
+                parse(&message),
+                Some(TuningMessage::NoteChanges(vec![(64, 63.75)]))
+            );
+        }
+    }
+
+    #[test]
+    fn short_note_changes_are_ignored() {
+        let message = [
+            UNIVERSAL_REALTIME,
+            0x7F,
+            MIDI_TUNING,
+            SINGLE_NOTE_CHANGE,
+            0,
+            2,
+            60,
+            60,
+            0x40,
+            0x00,
+            61,
+            61,
+        ];
+        assert_eq!(parse(&message), None);
+        assert_eq!(parse(&message[..5]), None);
+    }
+
+    #[test]
+    fn other_messages_are_not_tunings() {
+        // identity request and a real time single note change sent as non real time
+        assert_eq!(parse(&[0x7E, 0x7F, 0x06, 0x01]), None);
+        assert_eq!(
+            parse(&[
+                0x7E,
+                0x7F,
+                MIDI_TUNING,
+                SINGLE_NOTE_CHANGE,
+                0,
+                1,
+                60,
+                60,
+                0,
+                0
+            ]),
+            None
+        );
+        assert_eq!(parse(&[0x7D, 0x4B, 0x57]), None);
+    }
+
+    #[test]
+    fn master_tuning_offset() {
+        let mut master_tuning = MasterTuning::new();
+        assert_eq!(master_tuning.offset(), 0.0);
+
+        // A4 stays between 430 Hz and 450 Hz
+        master_tuning.fine_tune = 0;
+        assert_eq!(master_tuning.cents(), -39);
+        assert!(440.0 * 2f32.powf(master_tuning.offset() / 12.0) > 430.0);
+        master_tuning.fine_tune = MAX_FINE_TUNE;
+        assert_eq!(master_tuning.cents(), 38);
+        assert!(440.0 * 2f32.powf(master_tuning.offset() / 12.0) < 450.0);
+
+        master_tuning.octave = MAX_OCTAVE;
+        master_tuning.semitone = 0;
+        master_tuning.fine_tune = MAX_FINE_TUNE;
+        assert_eq!(master_tuning.octaves(), 3);
+        assert_eq!(master_tuning.semitones(), -12);
+        assert_eq!(
+            master_tuning.offset(),
+            24.0 + master_tuning.cents() as f32 / 100.0
+        );
+    }
+}
@@ -0,0 +1,100 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::driver::flash::{Error, SharedFlash, TUNING_SECTOR};
+use crate::patch_store::as_bytes;
+use crate::tuning::*;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+// Stored tables, the tuning of a patch is 0 for equal temperament or the slot + 1
+pub const NUM_TUNINGS: usize = 8;
+
+// "KWT1" in little endian, bump the last byte when the record layout changes
+const TUNING_MAGIC: u32 = 0x3154_574B;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Record {
+    magic: u32,
+    name: TuningName,
+    table: TuningTable,
+}
+
+// The whole sector is rewritten on every change, the tables are small enough to be
+// copied to RAM meanwhile
+pub struct TuningStore {
+    flash: SharedFlash,
+}
+
+impl TuningStore {
+    pub fn new(flash: SharedFlash) -> Self {
+        TuningStore { flash }
+    }
+
+    pub fn table(&self, slot: usize) -> Option<&'static TuningTable> {
+        self.record(slot).map(|record| &record.table)
+    }
+
+    // The table of a patch tuning index, a deleted tuning falls back to equal temperament
+    pub fn table_of(&self, index: u32) -> TuningTable {
+        (index as usize)
+            .checked_sub(1)
+            .and_then(|slot| self.table(slot))
+            .copied()
+            .unwrap_or_else(equal_temperament)
+    }
+
+    pub fn save(
+        &mut self,
+        slot: usize,
+        name: &TuningName,
+        table: &TuningTable,
+    ) -> Result<(), Error> {
+        if slot >= NUM_TUNINGS {
+            return Ok(());
+        }
+
+        let mut records: Vec<Record> = self.records().to_vec();
+        records[slot] = Record {
+            magic: TUNING_MAGIC,
+            name: *name,
+            table: *table,
+        };
+
+        let mut flash = self.flash.borrow_mut();
+        flash.erase(TUNING_SECTOR)?;
+        for (index, record) in records.iter().enumerate() {
+            flash.program(TUNING_SECTOR, index * size_of::<Record>(), as_bytes(record))?;
+        }
+        Ok(())
+    }
+
+    fn records(&self) -> &'static [Record] {
+        let data = self.flash.borrow().read(TUNING_SECTOR);
+        assert!(data.len() >= NUM_TUNINGS * size_of::<Record>());
+        unsafe { core::slice::from_raw_parts(data.as_ptr() as *const Record, NUM_TUNINGS) }
+    }
+
+    fn record(&self, slot: usize) -> Option<&'static Record> {
+        self.records()
+            .get(slot)
+            .filter(|record| record.magic == TUNING_MAGIC)
+    }
+}
@@ -28,6 +28,7 @@ pub struct Button<'a> {
     highlight: bool,
     dirty: bool,
     handler: Box<dyn FnMut(bool) -> bool>,
+    gesture_handler: Option<Box<dyn FnMut(Gesture) -> bool>>,
 }
 
 impl<'a> core::fmt::Debug for Button<'a> {
@@ -50,8 +51,16 @@ impl<'a> Button<'a> {
             highlight: false,
             dirty: true,
             handler,
+            gesture_handler: None,
         }
     }
+
+    // Handles long, double and combo presses, plain presses still go to the main handler
+    // and so does a double press, as the second of two presses
+    pub fn with_gesture_handler(mut self, handler: Box<dyn FnMut(Gesture) -> bool>) -> Self {
+        self.gesture_handler = Some(handler);
+        self
+    }
 }
 
 impl Drawable for Button<'_> {
@@ -85,12 +94,90 @@ impl<'a> InputConsumer for Button<'a> {
     }
 
     fn input_update(&mut self, input_id: InputId, value: Value) {
-        if let Value::Bool(value) = value {
+        if let Value::Gesture(gesture) = value {
             if input_id == self.input_id {
-                if (self.handler)(value) {
+                let mut dirty = false;
+                if gesture.is_short_press() {
+                    dirty = (self.handler)(true);
+                }
+                if gesture != Gesture::Press {
+                    if let Some(gesture_handler) = &mut self.gesture_handler {
+                        dirty = (gesture_handler)(gesture) || dirty;
+                    }
+                }
+                if dirty {
                     self.dirty = true;
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::rc::Rc;
+    use alloc::vec::Vec;
+    use core::cell::{Cell, RefCell};
+
+    const ID: InputId = 1;
+
+    fn press(button: &mut Button, gesture: Gesture) {
+        button.input_update(ID, Value::Gesture(gesture));
+    }
+
+    #[test]
+    fn plain_button_takes_every_short_press() {
+        let presses = Rc::new(Cell::new(0));
+        let counted = presses.clone();
+        let mut button = Button::new(
+            Point::zero(),
+            "B",
+            ID,
+            Box::new(move |_value: bool| {
+                counted.set(counted.get() + 1);
+                false
+            }),
+        );
+
+        press(&mut button, Gesture::Press);
+        press(&mut button, Gesture::DoublePress);
+        assert_eq!(presses.get(), 2);
+
+        press(&mut button, Gesture::LongPress);
+        press(&mut button, Gesture::Combo(2));
+        button.input_update(2, Value::Gesture(Gesture::Press));
+        assert_eq!(presses.get(), 2);
+    }
+
+    #[test]
+    fn gesture_handler_gets_the_other_gestures() {
+        let presses = Rc::new(Cell::new(0));
+        let gestures = Rc::new(RefCell::new(Vec::new()));
+        let counted = presses.clone();
+        let recorded = gestures.clone();
+        let mut button = Button::new(
+            Point::zero(),
+            "B",
+            ID,
+            Box::new(move |_value: bool| {
+                counted.set(counted.get() + 1);
+                false
+            }),
+        )
+        .with_gesture_handler(Box::new(move |gesture: Gesture| {
+            recorded.borrow_mut().push(gesture);
+            true
+        }));
+
+        press(&mut button, Gesture::Press);
+        press(&mut button, Gesture::DoublePress);
+        press(&mut button, Gesture::LongPress);
+        press(&mut button, Gesture::Combo(2));
+        assert_eq!(presses.get(), 2);
+        assert_eq!(
+            *gestures.borrow(),
+            [Gesture::DoublePress, Gesture::LongPress, Gesture::Combo(2)]
+        );
+    }
+}
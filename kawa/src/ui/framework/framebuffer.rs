@@ -0,0 +1,285 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::*;
+use embedded_graphics::prelude::*;
+
+pub const WIDTH: usize = 128;
+pub const HEIGHT: usize = 64;
+
+// ST7920 graphics RAM is addressed in 16 pixel wide words
+pub const WORD_BITS: usize = 16;
+pub const WORDS_PER_ROW: usize = WIDTH / WORD_BITS;
+
+// Setting the ST7920 address costs about as much as sending two data words,
+// so changed words separated by a gap this small are sent in one span.
+const SPAN_MERGE_GAP: usize = 2;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Span {
+    pub row: u8,
+    pub first_word: u8,
+    pub last_word: u8,
+}
+
+impl Span {
+    pub fn region(&self) -> (Point, Size) {
+        (
+            Point::new(self.first_word as i32 * WORD_BITS as i32, self.row as i32),
+            Size::new(
+                (self.last_word - self.first_word + 1) as u32 * WORD_BITS as u32,
+                1,
+            ),
+        )
+    }
+}
+
+pub struct FrameBuffer {
+    front: [u16; WORDS_PER_ROW * HEIGHT],
+    back: [u16; WORDS_PER_ROW * HEIGHT],
+}
+
+impl FrameBuffer {
+    pub fn new() -> Self {
+        FrameBuffer {
+            front: [0; WORDS_PER_ROW * HEIGHT],
+            back: [0; WORDS_PER_ROW * HEIGHT],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for word in self.back.iter_mut() {
+            *word = 0;
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, on: bool) {
+        if x >= WIDTH || y >= HEIGHT {
+            return;
+        }
+        let index = y * WORDS_PER_ROW + x / WORD_BITS;
+        let mask = 0x8000 >> (x % WORD_BITS);
+        if on {
+            self.back[index] |= mask;
+        } else {
+            self.back[index] &= !mask;
+        }
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> bool {
+        if x >= WIDTH || y >= HEIGHT {
+            return false;
+        }
+        self.back[y * WORDS_PER_ROW + x / WORD_BITS] & (0x8000 >> (x % WORD_BITS)) != 0
+    }
+
+    // Spans of words that differ between the drawn frame and the one last sent
+    pub fn diff(&self) -> Spans<'_> {
+        Spans {
+            framebuffer: self,
+            row: 0,
+            word: 0,
+        }
+    }
+
+    pub fn span_pixels(&self, span: Span) -> impl Iterator<Item = Pixel<BinaryColor>> + '_ {
+        let y = span.row as usize;
+        let first_x = span.first_word as usize * WORD_BITS;
+        let last_x = (span.last_word as usize + 1) * WORD_BITS;
+        (first_x..last_x).map(move |x| {
+            Pixel(
+                Point::new(x as i32, y as i32),
+                if self.get_pixel(x, y) {
+                    BinaryColor::On
+                } else {
+                    BinaryColor::Off
+                },
+            )
+        })
+    }
+
+    // Marks the drawn frame as sent to the display
+    pub fn commit(&mut self) {
+        self.front.copy_from_slice(&self.back);
+    }
+
+    fn word_changed(&self, row: usize, word: usize) -> bool {
+        let index = row * WORDS_PER_ROW + word;
+        self.front[index] != self.back[index]
+    }
+}
+
+impl Default for FrameBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drawing<BinaryColor> for FrameBuffer {
+    fn draw<T>(&mut self, item_pixels: T)
+    where
+        T: IntoIterator<Item = Pixel<BinaryColor>>,
+    {
+        for Pixel(point, color) in item_pixels {
+            if point.x >= 0 && point.y >= 0 {
+                self.set_pixel(point.x as usize, point.y as usize, color == BinaryColor::On);
+            }
+        }
+    }
+}
+
+pub struct Spans<'a> {
+    framebuffer: &'a FrameBuffer,
+    row: usize,
+    word: usize,
+}
+
+impl<'a> Iterator for Spans<'a> {
+    type Item = Span;
+
+    fn next(&mut self) -> Option<Span> {
+        while self.row < HEIGHT {
+            while self.word < WORDS_PER_ROW && !self.framebuffer.word_changed(self.row, self.word) {
+                self.word += 1;
+            }
+
+            if self.word < WORDS_PER_ROW {
+                let first_word = self.word;
+                let mut last_word = first_word;
+                let mut gap = 0;
+                self.word += 1;
+                while self.word < WORDS_PER_ROW && gap <= SPAN_MERGE_GAP {
+                    if self.framebuffer.word_changed(self.row, self.word) {
+                        last_word = self.word;
+                        gap = 0;
+                    } else {
+                        gap += 1;
+                    }
+                    self.word += 1;
+                }
+                self.word = last_word + 1;
+
+                return Some(Span {
+                    row: self.row as u8,
+                    first_word: first_word as u8,
+                    last_word: last_word as u8,
+                });
+            }
+
+            self.row += 1;
+            self.word = 0;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(framebuffer: &FrameBuffer) -> Vec<(u8, u8, u8)> {
+        framebuffer
+            .diff()
+            .map(|span| (span.row, span.first_word, span.last_word))
+            .collect()
+    }
+
+    #[test]
+    fn unchanged_frame_has_no_spans() {
+        let mut framebuffer = FrameBuffer::new();
+        assert_eq!(spans(&framebuffer), []);
+
+        framebuffer.set_pixel(5, 5, true);
+        framebuffer.commit();
+        framebuffer.clear();
+        framebuffer.set_pixel(5, 5, true);
+        assert_eq!(spans(&framebuffer), []);
+    }
+
+    #[test]
+    fn changed_pixel_gives_its_word() {
+        let mut framebuffer = FrameBuffer::new();
+        framebuffer.set_pixel(40, 7, true);
+        assert_eq!(spans(&framebuffer), [(7, 2, 2)]);
+
+        framebuffer.commit();
+        assert_eq!(spans(&framebuffer), []);
+
+        framebuffer.set_pixel(40, 7, false);
+        assert_eq!(spans(&framebuffer), [(7, 2, 2)]);
+    }
+
+    #[test]
+    fn opposite_corners_give_two_small_spans() {
+        let mut framebuffer = FrameBuffer::new();
+        framebuffer.set_pixel(0, 0, true);
+        framebuffer.set_pixel(WIDTH - 1, HEIGHT - 1, true);
+        assert_eq!(spans(&framebuffer), [(0, 0, 0), (HEIGHT as u8 - 1, 7, 7)]);
+    }
+
+    #[test]
+    fn close_words_merge_into_one_span() {
+        let mut framebuffer = FrameBuffer::new();
+        // two unchanged words between
+        framebuffer.set_pixel(0, 3, true);
+        framebuffer.set_pixel(3 * WORD_BITS, 3, true);
+        // three unchanged words between
+        framebuffer.set_pixel(0, 4, true);
+        framebuffer.set_pixel(4 * WORD_BITS, 4, true);
+        framebuffer.set_pixel(7 * WORD_BITS, 4, true);
+        assert_eq!(spans(&framebuffer), [(3, 0, 3), (4, 0, 0), (4, 4, 7)]);
+    }
+
+    #[test]
+    fn drawn_rows_give_one_span_each() {
+        let mut framebuffer = FrameBuffer::new();
+        for y in 10..13 {
+            for x in 20..60 {
+                framebuffer.set_pixel(x, y, true);
+            }
+        }
+        assert_eq!(spans(&framebuffer), [(10, 1, 3), (11, 1, 3), (12, 1, 3)]);
+    }
+
+    #[test]
+    fn pixels_outside_are_dropped() {
+        let mut framebuffer = FrameBuffer::new();
+        framebuffer.set_pixel(WIDTH, 0, true);
+        framebuffer.set_pixel(0, HEIGHT, true);
+        framebuffer.draw(Some(Pixel(Point::new(-1, 0), BinaryColor::On)));
+        assert_eq!(spans(&framebuffer), []);
+    }
+
+    #[test]
+    fn span_covers_its_words() {
+        let mut framebuffer = FrameBuffer::new();
+        framebuffer.set_pixel(17, 2, true);
+        let span = framebuffer.diff().next().unwrap();
+        assert_eq!(span.region(), (Point::new(16, 2), Size::new(16, 1)));
+
+        let on: Vec<i32> = framebuffer
+            .span_pixels(span)
+            .filter(|Pixel(_, color)| *color == BinaryColor::On)
+            .map(|Pixel(point, _)| point.x)
+            .collect();
+        assert_eq!(on, [17]);
+        assert_eq!(framebuffer.span_pixels(span).count(), WORD_BITS);
+    }
+}
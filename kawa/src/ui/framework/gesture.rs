@@ -0,0 +1,309 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::*;
+use alloc::vec::Vec;
+
+// All times are in ticks of a monotonic millisecond counter
+pub const DEBOUNCE_TICKS: u32 = 10;
+pub const LONG_PRESS_TICKS: u32 = 600;
+pub const DOUBLE_PRESS_TICKS: u32 = 300;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Gesture {
+    // Short press, reported on release
+    Press,
+    // Reported while still held, the release is then ignored
+    LongPress,
+    // Second short press following a Press, reported on release instead of a Press
+    DoublePress,
+    // Pressed while the given button is held, reported on press
+    Combo(InputId),
+}
+
+impl Gesture {
+    // A double press is also a second short press, for widgets that don't tell them apart
+    pub fn is_short_press(self) -> bool {
+        self == Gesture::Press || self == Gesture::DoublePress
+    }
+}
+
+#[derive(Default)]
+struct ButtonState {
+    raw: bool,
+    raw_since: u32,
+    pressed: bool,
+    pressed_at: u32,
+    released_at: Option<u32>,
+    // the release reports nothing, after a long press, a combo or a knob turn. The button
+    // still works as a modifier while held.
+    consumed: bool,
+}
+
+pub struct GestureRecognizer {
+    input_ids: Vec<InputId>,
+    buttons: Vec<ButtonState>,
+}
+
+impl GestureRecognizer {
+    pub fn new(input_ids: &[InputId]) -> Self {
+        GestureRecognizer {
+            input_ids: input_ids.to_vec(),
+            buttons: input_ids.iter().map(|_| ButtonState::default()).collect(),
+        }
+    }
+
+    // Feeds the raw state of a button sampled at `now`
+    pub fn update(&mut self, input_id: InputId, raw: bool, now: u32) -> Option<Gesture> {
+        let button = self.index(input_id)?;
+        let state = &mut self.buttons[button];
+        if raw != state.raw {
+            state.raw = raw;
+            state.raw_since = now;
+        }
+
+        if state.raw != state.pressed && now.wrapping_sub(state.raw_since) >= DEBOUNCE_TICKS {
+            if state.raw {
+                self.press(button, now)
+            } else {
+                self.release(button, now)
+            }
+        } else if state.pressed
+            && !state.consumed
+            && now.wrapping_sub(state.pressed_at) >= LONG_PRESS_TICKS
+        {
+            state.consumed = true;
+            state.released_at = None;
+            Some(Gesture::LongPress)
+        } else {
+            None
+        }
+    }
+
+    // Used when a knob turns while buttons are held, so that those presses act as modifiers only
+    pub fn consume_held(&mut self) -> bool {
+        let mut any_held = false;
+        for state in self.buttons.iter_mut().filter(|state| state.pressed) {
+            state.consumed = true;
+            state.released_at = None;
+            any_held = true;
+        }
+        any_held
+    }
+
+    fn index(&self, input_id: InputId) -> Option<usize> {
+        self.input_ids.iter().position(|id| *id == input_id)
+    }
+
+    fn press(&mut self, button: usize, now: u32) -> Option<Gesture> {
+        let held = self.buttons.iter().position(|state| state.pressed);
+
+        let state = &mut self.buttons[button];
+        state.pressed = true;
+        state.pressed_at = now;
+        state.consumed = false;
+
+        if let Some(held) = held {
+            state.consumed = true;
+            state.released_at = None;
+            self.buttons[held].consumed = true;
+            self.buttons[held].released_at = None;
+            Some(Gesture::Combo(self.input_ids[held]))
+        } else {
+            None
+        }
+    }
+
+    fn release(&mut self, button: usize, now: u32) -> Option<Gesture> {
+        let state = &mut self.buttons[button];
+        state.pressed = false;
+
+        if state.consumed {
+            return None;
+        }
+
+        match state.released_at {
+            Some(released_at) if state.pressed_at.wrapping_sub(released_at) <= DOUBLE_PRESS_TICKS => {
+                state.released_at = None;
+                Some(Gesture::DoublePress)
+            }
+            _ => {
+                state.released_at = Some(now);
+                Some(Gesture::Press)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: InputId = 1;
+    const B: InputId = 2;
+
+    // Samples both buttons every tick, each edge sets the raw state of a button from its tick on
+    fn run(edges: &[(u32, InputId, bool)], ticks: u32) -> Vec<(u32, InputId, Gesture)> {
+        let mut recognizer = GestureRecognizer::new(&[A, B]);
+        let mut raw = [false; 2];
+        let mut gestures = Vec::new();
+        for now in 0..ticks {
+            for (at, input_id, state) in edges {
+                if *at == now {
+                    raw[(*input_id - A) as usize] = *state;
+                }
+            }
+            for input_id in [A, B].iter() {
+                let state = raw[(*input_id - A) as usize];
+                if let Some(gesture) = recognizer.update(*input_id, state, now) {
+                    gestures.push((now, *input_id, gesture));
+                }
+            }
+        }
+        gestures
+    }
+
+    #[test]
+    fn press_is_reported_on_release() {
+        let gestures = run(&[(100, A, true), (200, A, false)], 1000);
+        assert_eq!(gestures, [(210, A, Gesture::Press)]);
+    }
+
+    #[test]
+    fn bounces_are_filtered() {
+        let edges = [
+            (100, A, true),
+            (103, A, false),
+            (105, A, true),
+            (200, A, false),
+            (202, A, true),
+            (204, A, false),
+        ];
+        assert_eq!(run(&edges, 1000), [(214, A, Gesture::Press)]);
+
+        // shorter than the debounce time
+        assert_eq!(run(&[(100, A, true), (105, A, false)], 1000), []);
+    }
+
+    #[test]
+    fn long_press_is_reported_while_held() {
+        let gestures = run(&[(100, A, true), (1000, A, false)], 2000);
+        assert_eq!(gestures, [(110 + LONG_PRESS_TICKS, A, Gesture::LongPress)]);
+    }
+
+    #[test]
+    fn quick_second_press_is_a_double_press() {
+        let edges = [
+            (100, A, true),
+            (150, A, false),
+            (300, A, true),
+            (350, A, false),
+            (500, A, true),
+            (550, A, false),
+        ];
+        assert_eq!(
+            run(&edges, 1000),
+            [
+                (160, A, Gesture::Press),
+                (360, A, Gesture::DoublePress),
+                // a third press starts over
+                (560, A, Gesture::Press),
+            ]
+        );
+    }
+
+    #[test]
+    fn slow_second_press_is_a_press() {
+        let edges = [
+            (100, A, true),
+            (150, A, false),
+            (600, A, true),
+            (650, A, false),
+        ];
+        assert_eq!(
+            run(&edges, 1000),
+            [(160, A, Gesture::Press), (660, A, Gesture::Press)]
+        );
+    }
+
+    #[test]
+    fn press_while_another_is_held_is_a_combo() {
+        let edges = [
+            (100, A, true),
+            (200, B, true),
+            (300, B, false),
+            (400, A, false),
+        ];
+        assert_eq!(run(&edges, 1000), [(210, B, Gesture::Combo(A))]);
+    }
+
+    #[test]
+    fn combo_follows_a_long_press() {
+        let edges = [
+            (100, A, true),
+            (1000, B, true),
+            (1100, B, false),
+            (1200, A, false),
+        ];
+        assert_eq!(
+            run(&edges, 2000),
+            [
+                (110 + LONG_PRESS_TICKS, A, Gesture::LongPress),
+                (1010, B, Gesture::Combo(A)),
+            ]
+        );
+    }
+
+    #[test]
+    fn combo_follows_a_knob_turn() {
+        let mut recognizer = GestureRecognizer::new(&[A, B]);
+        recognizer.update(A, true, 0);
+        recognizer.update(A, true, DEBOUNCE_TICKS);
+        assert!(recognizer.consume_held());
+
+        recognizer.update(B, true, 100);
+        assert_eq!(
+            recognizer.update(B, true, 100 + DEBOUNCE_TICKS),
+            Some(Gesture::Combo(A))
+        );
+        recognizer.update(B, false, 200);
+        assert_eq!(recognizer.update(B, false, 200 + DEBOUNCE_TICKS), None);
+        recognizer.update(A, false, 300);
+        assert_eq!(recognizer.update(A, false, 300 + DEBOUNCE_TICKS), None);
+    }
+
+    #[test]
+    fn held_buttons_can_be_consumed() {
+        let mut recognizer = GestureRecognizer::new(&[A, B]);
+        assert!(!recognizer.consume_held());
+
+        recognizer.update(A, true, 0);
+        recognizer.update(A, true, DEBOUNCE_TICKS);
+        assert!(recognizer.consume_held());
+        assert_eq!(recognizer.update(A, false, 100), None);
+        assert_eq!(recognizer.update(A, false, 100 + DEBOUNCE_TICKS), None);
+    }
+
+    #[test]
+    fn unknown_buttons_are_ignored() {
+        let mut recognizer = GestureRecognizer::new(&[A]);
+        assert_eq!(recognizer.update(B, true, 0), None);
+        assert_eq!(recognizer.update(B, true, 1000), None);
+    }
+}
@@ -20,17 +20,33 @@
 use super::*;
 use alloc::boxed::Box;
 use core::cmp::max;
-use embedded_graphics::{fonts::Font6x12, prelude::*};
+use embedded_graphics::{fonts::Font6x12, prelude::*, primitives::Rectangle};
 use numtoa::NumToA;
 
+pub enum KnobSwitch<'a> {
+    // Pressing the knob restores the default value
+    Reset(Box<dyn FnMut() -> i16>),
+    // Pressing the knob toggles between the primary and the alternate parameter,
+    // optionally shown with labels
+    Alternate(
+        &'a str,
+        Box<dyn FnMut(i8, bool) -> i16>,
+        Option<&'a [&'a str]>,
+    ),
+}
+
 pub struct Knob<'a> {
     pos: Point,
+    size: Size,
     caption: &'a str,
     input_id: InputId,
-    value: u8,
+    value: i16,
+    labels: Option<&'a [&'a str]>,
     last_input_value: Option<i32>,
     dirty: bool,
-    handler: Box<dyn FnMut(i8) -> u8>,
+    handler: Box<dyn FnMut(i8, bool) -> i16>,
+    switch_input_id: Option<InputId>,
+    switch: Option<KnobSwitch<'a>>,
 }
 
 impl<'a> core::fmt::Debug for Knob<'a> {
@@ -44,48 +60,92 @@ impl<'a> Knob<'a> {
         pos: Point,
         caption: &'a str,
         input_id: InputId,
-        mut handler: Box<dyn FnMut(i8) -> u8>,
+        mut handler: Box<dyn FnMut(i8, bool) -> i16>,
     ) -> Self {
         Knob {
             pos,
+            size: Size::new(0, 0),
             caption,
             input_id,
-            value: (handler)(0),
+            value: (handler)(0, false),
+            labels: None,
             last_input_value: None,
             dirty: true,
             handler,
+            switch_input_id: None,
+            switch: None,
+        }
+    }
+
+    pub fn with_switch(mut self, input_id: InputId, switch: KnobSwitch<'a>) -> Self {
+        self.switch_input_id = Some(input_id);
+        self.switch = Some(switch);
+        self
+    }
+
+    // Shows labels[value] instead of the value
+    pub fn with_labels(mut self, labels: &'a [&'a str]) -> Self {
+        self.labels = Some(labels);
+        self
+    }
+
+    fn press_switch(&mut self) {
+        match &mut self.switch {
+            Some(KnobSwitch::Reset(reset)) => {
+                self.value = (reset)();
+                self.dirty = true;
+            }
+            Some(KnobSwitch::Alternate(caption, handler, labels)) => {
+                core::mem::swap(&mut self.caption, caption);
+                core::mem::swap(&mut self.handler, handler);
+                core::mem::swap(&mut self.labels, labels);
+                self.value = (self.handler)(0, false);
+                self.dirty = true;
+            }
+            None => (),
         }
     }
 }
 
 impl Drawable for Knob<'_> {
     fn render(&mut self, drawing: &mut impl Drawing<BinaryColor>) -> (Point, Size) {
+        if self.size.width != 0 && self.size.height != 0 {
+            drawing.draw(
+                Rectangle::new(
+                    self.pos,
+                    self.pos + Point::new(self.size.width as i32 - 1, self.size.height as i32 - 1),
+                )
+                .stroke(Some(BinaryColor::Off))
+                .fill(Some(BinaryColor::Off)),
+            );
+        }
+
         let render_caption = Font6x12::render_str(&self.caption)
             .fill(Some(BinaryColor::Off))
             .stroke(Some(BinaryColor::On))
             .translate(self.pos);
         drawing.draw(render_caption);
 
-        let mut buffer = [0u8; 3];
-        self.value.numtoa(10, &mut buffer);
-        if buffer[buffer.len() - 2] == 0 {
-            buffer[buffer.len() - 2] = b' ';
-        }
-        let text = &buffer[buffer.len() - 2..buffer.len()];
-        let render_value = Font6x12::render_str(unsafe { core::str::from_utf8_unchecked(text) })
+        let mut buffer = [0u8; 6];
+        let text = match self.labels {
+            Some(labels) => labels.get(self.value as usize).map_or("", |label| label),
+            None => unsafe { core::str::from_utf8_unchecked(self.value.numtoa(10, &mut buffer)) },
+        };
+        let render_value = Font6x12::render_str(text)
             .fill(Some(BinaryColor::Off))
             .stroke(Some(BinaryColor::On))
             .translate(self.pos + Point::new(0, render_caption.size().height as i32));
         drawing.draw(render_value);
         self.dirty = false;
 
-        (
-            self.pos,
-            Size::new(
-                max(render_caption.size().width, render_value.size().width),
-                render_caption.size().height + render_value.size().height,
-            ),
-        )
+        let size = Size::new(
+            max(render_caption.size().width, render_value.size().width),
+            render_caption.size().height + render_value.size().height,
+        );
+        let cover_size = Size::new(max(size.width, self.size.width), size.height);
+        self.size = size;
+
+        (self.pos, cover_size)
     }
 
     fn is_dirty(&self) -> bool {
@@ -95,24 +155,38 @@ impl Drawable for Knob<'_> {
 
 impl InputConsumer for Knob<'_> {
     fn input_reset(&mut self) {
+        // the parameter may have been changed elsewhere, e.g. by loading a patch
+        self.value = (self.handler)(0, false);
         self.last_input_value = None;
+        self.dirty = true;
     }
 
     fn input_update(&mut self, input_id: InputId, value: Value) {
-        if let Value::Int(input_value) = value {
-            if input_id == self.input_id {
-                if let Some(last_input_value) = self.last_input_value {
-                    let delta = input_value - last_input_value;
-                    if delta != 0 {
-                        self.value = (self.handler)(delta as i8);
-                        self.dirty = true;
-                    }
-                } else {
-                    self.value = (self.handler)(0);
+        if let Value::Gesture(gesture) = value {
+            if gesture.is_short_press() && Some(input_id) == self.switch_input_id {
+                self.press_switch();
+            }
+            return;
+        }
+
+        let (input_value, fine) = match value {
+            Value::Int(input_value) => (input_value, false),
+            Value::Fine(input_value) => (input_value, true),
+            _ => return,
+        };
+
+        if input_id == self.input_id {
+            if let Some(last_input_value) = self.last_input_value {
+                let delta = input_value - last_input_value;
+                if delta != 0 {
+                    self.value = (self.handler)(delta as i8, fine);
                     self.dirty = true;
                 }
-                self.last_input_value = Some(input_value);
+            } else {
+                self.value = (self.handler)(0, false);
+                self.dirty = true;
             }
+            self.last_input_value = Some(input_value);
         }
     }
 }
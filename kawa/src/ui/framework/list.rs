@@ -0,0 +1,175 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::*;
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use embedded_graphics::{fonts::Font6x12, prelude::*};
+
+const ROW_HEIGHT: i32 = 12;
+const CHAR_WIDTH: u32 = 6;
+
+pub type LabelFn = Box<dyn FnMut(usize, &mut [u8])>;
+
+pub struct ListInputs {
+    // moves the selection by one item
+    pub scroll_knob: InputId,
+    // moves the selection by `page_size` items
+    pub page_knob: InputId,
+    pub page_size: usize,
+}
+
+pub struct List {
+    pos: Point,
+    rows: usize,
+    width: usize,
+    count: usize,
+    selected: usize,
+    top: usize,
+    inputs: ListInputs,
+    last_scroll_value: Option<i32>,
+    last_page_value: Option<i32>,
+    dirty: bool,
+    item: LabelFn,
+    select: Box<dyn FnMut(usize)>,
+}
+
+impl core::fmt::Debug for List {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "List({}/{})", self.selected, self.count)
+    }
+}
+
+impl List {
+    // `item` writes the label of an item into a space filled buffer of `width` characters,
+    // `select` is told about the selected item whenever it changes and when the panel is shown
+    pub fn new(
+        pos: Point,
+        rows: usize,
+        width: usize,
+        count: usize,
+        inputs: ListInputs,
+        item: LabelFn,
+        select: Box<dyn FnMut(usize)>,
+    ) -> Self {
+        List {
+            pos,
+            rows,
+            width,
+            count,
+            selected: 0,
+            top: 0,
+            inputs,
+            last_scroll_value: None,
+            last_page_value: None,
+            dirty: true,
+            item,
+            select,
+        }
+    }
+
+    fn move_selection(&mut self, delta: i32) {
+        let selected = (self.selected as i32 + delta).max(0).min(self.count as i32 - 1) as usize;
+        if selected == self.selected {
+            return;
+        }
+
+        self.selected = selected;
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + self.rows {
+            self.top = self.selected + 1 - self.rows;
+        }
+        (self.select)(self.selected);
+        self.dirty = true;
+    }
+}
+
+impl Drawable for List {
+    fn render(&mut self, drawing: &mut impl Drawing<BinaryColor>) -> (Point, Size) {
+        let mut label: Vec<u8> = vec![b' '; self.width];
+
+        for row in 0..self.rows {
+            let index = self.top + row;
+            for c in label.iter_mut() {
+                *c = b' ';
+            }
+            if index < self.count {
+                (self.item)(index, &mut label[..]);
+            }
+
+            let highlight = index == self.selected;
+            let render = Font6x12::render_str(unsafe { core::str::from_utf8_unchecked(&label) })
+                .fill(Some(if highlight {
+                    BinaryColor::On
+                } else {
+                    BinaryColor::Off
+                }))
+                .stroke(Some(if highlight {
+                    BinaryColor::Off
+                } else {
+                    BinaryColor::On
+                }))
+                .translate(self.pos + Point::new(0, row as i32 * ROW_HEIGHT));
+            drawing.draw(render);
+        }
+        self.dirty = false;
+
+        (
+            self.pos,
+            Size::new(
+                self.width as u32 * CHAR_WIDTH,
+                self.rows as u32 * ROW_HEIGHT as u32,
+            ),
+        )
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+}
+
+impl InputConsumer for List {
+    fn input_reset(&mut self) {
+        self.last_scroll_value = None;
+        self.last_page_value = None;
+        (self.select)(self.selected);
+        self.dirty = true;
+    }
+
+    fn input_update(&mut self, input_id: InputId, value: Value) {
+        let input_value = match value {
+            Value::Int(input_value) | Value::Fine(input_value) => input_value,
+            _ => return,
+        };
+
+        if input_id == self.inputs.scroll_knob {
+            if let Some(last_scroll_value) = self.last_scroll_value {
+                self.move_selection(input_value - last_scroll_value);
+            }
+            self.last_scroll_value = Some(input_value);
+        } else if input_id == self.inputs.page_knob {
+            if let Some(last_page_value) = self.last_page_value {
+                self.move_selection((input_value - last_page_value) * self.inputs.page_size as i32);
+            }
+            self.last_page_value = Some(input_value);
+        }
+    }
+}
@@ -18,12 +18,22 @@
 // along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
 
 pub mod button;
+pub mod framebuffer;
+pub mod gesture;
 pub mod knob;
+pub mod list;
 pub mod panel;
+pub mod steps;
+pub mod text_edit;
 
 pub use button::Button;
-pub use knob::Knob;
+pub use framebuffer::FrameBuffer;
+pub use gesture::{Gesture, GestureRecognizer};
+pub use knob::{Knob, KnobSwitch};
+pub use list::{List, ListInputs};
 pub use panel::Panel;
+pub use steps::{Cell, Steps};
+pub use text_edit::{TextEdit, TextEditInputs};
 
 pub use embedded_graphics::{
     geometry::{Point, Size},
@@ -33,8 +43,10 @@ pub use embedded_graphics::{
 
 #[derive(Copy, Clone)]
 pub enum Value {
-    Bool(bool),
     Int(i32),
+    // Knob position while a button is held, for fine editing
+    Fine(i32),
+    Gesture(Gesture),
 }
 
 pub type InputId = u32;
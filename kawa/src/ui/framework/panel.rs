@@ -18,12 +18,15 @@
 // along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
 
 use super::*;
-use super::{button::Button, knob::Knob};
+use super::{button::Button, knob::Knob, list::List, steps::Steps, text_edit::TextEdit};
 use alloc::vec::Vec;
 
 pub struct Panel<'a> {
     buttons: Vec<Button<'a>>,
     knobs: Vec<Knob<'a>>,
+    lists: Vec<List>,
+    text_edits: Vec<TextEdit>,
+    steps: Vec<Steps>,
 }
 
 impl<'a> Panel<'a> {
@@ -31,54 +34,54 @@ impl<'a> Panel<'a> {
         Panel {
             buttons: elements.0,
             knobs: elements.1,
+            lists: Vec::new(),
+            text_edits: Vec::new(),
+            steps: Vec::new(),
         }
     }
-}
 
-fn extend_rect_to_cover(pos: &mut Point, size: &mut Size, cover_pos: &Point, cover_size: &Size) {
-    if cover_pos.x < pos.x {
-        pos.x = cover_pos.x;
-    }
-    if cover_pos.y < pos.y {
-        pos.y = cover_pos.y;
+    pub fn with_list(mut self, list: List) -> Self {
+        self.lists.push(list);
+        self
     }
-    if cover_pos.x + cover_size.width as i32 > pos.x + size.width as i32 {
-        size.width = cover_pos.x as u32 + cover_size.width - pos.x as u32;
-    }
-    if cover_pos.y + cover_size.height as i32 > pos.y + size.height as i32 {
-        size.height = cover_pos.y as u32 + cover_size.height - pos.y as u32;
+
+    pub fn with_text_edit(mut self, text_edit: TextEdit) -> Self {
+        self.text_edits.push(text_edit);
+        self
     }
-}
 
-impl Drawable for Panel<'_> {
-    fn render(&mut self, drawing: &mut impl Drawing<BinaryColor>) -> (Point, Size) {
-        let mut panel_pos = Point {
-            x: core::i32::MAX,
-            y: core::i32::MAX,
-        };
-        let mut panel_size = Size {
-            width: 0,
-            height: 0,
-        };
+    pub fn with_steps(mut self, steps: Steps) -> Self {
+        self.steps.push(steps);
+        self
+    }
 
+    // Draws the widgets that changed, the framebuffer finds what to send to the display
+    pub fn render(&mut self, drawing: &mut impl Drawing<BinaryColor>) {
         for component in self.buttons.iter_mut() {
             if component.is_dirty() {
-                let (pos, size) = component.render(drawing);
-                extend_rect_to_cover(&mut panel_pos, &mut panel_size, &pos, &size);
+                component.render(drawing);
             }
         }
         for component in self.knobs.iter_mut() {
             if component.is_dirty() {
-                let (pos, size) = component.render(drawing);
-                extend_rect_to_cover(&mut panel_pos, &mut panel_size, &pos, &size);
+                component.render(drawing);
+            }
+        }
+        for component in self.lists.iter_mut() {
+            if component.is_dirty() {
+                component.render(drawing);
+            }
+        }
+        for component in self.text_edits.iter_mut() {
+            if component.is_dirty() {
+                component.render(drawing);
+            }
+        }
+        for component in self.steps.iter_mut() {
+            if component.is_dirty() {
+                component.render(drawing);
             }
         }
-
-        (panel_pos, panel_size)
-    }
-
-    fn is_dirty(&self) -> bool {
-        true
     }
 }
 
@@ -90,6 +93,15 @@ impl<'a> InputConsumer for Panel<'a> {
         for component in self.knobs.iter_mut() {
             component.input_reset();
         }
+        for component in self.lists.iter_mut() {
+            component.input_reset();
+        }
+        for component in self.text_edits.iter_mut() {
+            component.input_reset();
+        }
+        for component in self.steps.iter_mut() {
+            component.input_reset();
+        }
     }
 
     fn input_update(&mut self, input_id: InputId, value: Value) {
@@ -99,5 +111,14 @@ impl<'a> InputConsumer for Panel<'a> {
         for component in self.knobs.iter_mut() {
             component.input_update(input_id, value);
         }
+        for component in self.lists.iter_mut() {
+            component.input_update(input_id, value);
+        }
+        for component in self.text_edits.iter_mut() {
+            component.input_update(input_id, value);
+        }
+        for component in self.steps.iter_mut() {
+            component.input_update(input_id, value);
+        }
     }
 }
@@ -0,0 +1,155 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::*;
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use embedded_graphics::{prelude::*, primitives::Rectangle};
+
+const CELL_WIDTH: i32 = 4;
+const CELL_HEIGHT: i32 = 6;
+// the marked cell is underlined below this gap
+const MARK_GAP: i32 = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Cell {
+    pub filled: bool,
+    // drawn connected to the next cell
+    pub joined: bool,
+    pub marked: bool,
+}
+
+// Row of cells, e.g. the steps of a sequence. The cells can change on any input,
+// so they are checked after each one and redrawn when changed.
+pub struct Steps {
+    pos: Point,
+    count: usize,
+    cells: Vec<Option<Cell>>,
+    dirty: bool,
+    // None for cells that are not shown
+    cell: Box<dyn FnMut(usize) -> Option<Cell>>,
+}
+
+impl core::fmt::Debug for Steps {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "Steps({})", self.count)
+    }
+}
+
+impl Steps {
+    pub fn new(pos: Point, count: usize, cell: Box<dyn FnMut(usize) -> Option<Cell>>) -> Self {
+        Steps {
+            pos,
+            count,
+            cells: vec![None; count],
+            dirty: true,
+            cell,
+        }
+    }
+
+    fn refresh(&mut self) {
+        for index in 0..self.count {
+            let cell = (self.cell)(index);
+            if cell != self.cells[index] {
+                self.cells[index] = cell;
+                self.dirty = true;
+            }
+        }
+    }
+
+    fn size(&self) -> Size {
+        Size::new(
+            self.count as u32 * CELL_WIDTH as u32,
+            (CELL_HEIGHT + MARK_GAP + 1) as u32,
+        )
+    }
+}
+
+fn rectangle(
+    top_left: Point,
+    width: i32,
+    height: i32,
+    color: BinaryColor,
+) -> Rectangle<BinaryColor> {
+    Rectangle::new(top_left, top_left + Point::new(width - 1, height - 1))
+        .stroke(Some(color))
+        .fill(Some(color))
+}
+
+impl Drawable for Steps {
+    fn render(&mut self, drawing: &mut impl Drawing<BinaryColor>) -> (Point, Size) {
+        let size = self.size();
+        drawing.draw(rectangle(
+            self.pos,
+            size.width as i32,
+            size.height as i32,
+            BinaryColor::Off,
+        ));
+
+        for (index, cell) in self.cells.iter().enumerate() {
+            let cell = match cell {
+                Some(cell) => cell,
+                None => continue,
+            };
+            let x = self.pos + Point::new(index as i32 * CELL_WIDTH, 0);
+            let width = if cell.joined {
+                CELL_WIDTH
+            } else {
+                CELL_WIDTH - 1
+            };
+            if cell.filled {
+                drawing.draw(rectangle(x, width, CELL_HEIGHT, BinaryColor::On));
+            } else {
+                drawing.draw(rectangle(
+                    x + Point::new(0, CELL_HEIGHT - 1),
+                    width,
+                    1,
+                    BinaryColor::On,
+                ));
+            }
+            if cell.marked {
+                drawing.draw(rectangle(
+                    x + Point::new(0, CELL_HEIGHT + MARK_GAP),
+                    CELL_WIDTH - 1,
+                    1,
+                    BinaryColor::On,
+                ));
+            }
+        }
+        self.dirty = false;
+
+        (self.pos, size)
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+}
+
+impl InputConsumer for Steps {
+    fn input_reset(&mut self) {
+        self.refresh();
+        self.dirty = true;
+    }
+
+    fn input_update(&mut self, _input_id: InputId, _value: Value) {
+        self.refresh();
+    }
+}
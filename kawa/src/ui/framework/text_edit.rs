@@ -0,0 +1,172 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::*;
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use embedded_graphics::{fonts::Font6x12, prelude::*};
+
+pub const CHARSET: &[u8] =
+    b" ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_.,#&+!?";
+
+const CHAR_WIDTH: i32 = 6;
+
+pub type LoadFn = Box<dyn FnMut(&mut [u8])>;
+pub type StoreFn = Box<dyn FnMut(&[u8])>;
+
+pub struct TextEditInputs {
+    pub char_knob: InputId,
+    pub cursor_left: InputId,
+    pub cursor_right: InputId,
+    pub insert: InputId,
+    pub delete: InputId,
+}
+
+// Fixed length text field, unused positions are spaces
+pub struct TextEdit {
+    pos: Point,
+    text: Vec<u8>,
+    cursor: usize,
+    inputs: TextEditInputs,
+    last_input_value: Option<i32>,
+    dirty: bool,
+    load: LoadFn,
+    store: StoreFn,
+}
+
+impl core::fmt::Debug for TextEdit {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "TextEdit({})", self.text.len())
+    }
+}
+
+impl TextEdit {
+    // `load` fills the text when the panel is shown, `store` gets it back after every edit
+    pub fn new(
+        pos: Point,
+        length: usize,
+        inputs: TextEditInputs,
+        load: LoadFn,
+        store: StoreFn,
+    ) -> Self {
+        TextEdit {
+            pos,
+            text: vec![b' '; length],
+            cursor: 0,
+            inputs,
+            last_input_value: None,
+            dirty: true,
+            load,
+            store,
+        }
+    }
+
+    fn change_char(&mut self, delta: i32) {
+        let index = CHARSET
+            .iter()
+            .position(|c| *c == self.text[self.cursor])
+            .unwrap_or(0) as i32;
+        let index = (index + delta).rem_euclid(CHARSET.len() as i32);
+        self.text[self.cursor] = CHARSET[index as usize];
+    }
+
+    fn insert(&mut self) {
+        let last = self.text.len() - 1;
+        self.text.copy_within(self.cursor..last, self.cursor + 1);
+        self.text[self.cursor] = b' ';
+    }
+
+    fn delete(&mut self) {
+        let length = self.text.len();
+        self.text.copy_within(self.cursor + 1..length, self.cursor);
+        self.text[length - 1] = b' ';
+    }
+
+    fn edited(&mut self) {
+        (self.store)(&self.text[..]);
+        self.dirty = true;
+    }
+}
+
+impl Drawable for TextEdit {
+    fn render(&mut self, drawing: &mut impl Drawing<BinaryColor>) -> (Point, Size) {
+        let render_text = Font6x12::render_str(unsafe { core::str::from_utf8_unchecked(&self.text) })
+            .fill(Some(BinaryColor::Off))
+            .stroke(Some(BinaryColor::On))
+            .translate(self.pos);
+        drawing.draw(render_text);
+
+        let cursor = &self.text[self.cursor..self.cursor + 1];
+        let render_cursor = Font6x12::render_str(unsafe { core::str::from_utf8_unchecked(cursor) })
+            .fill(Some(BinaryColor::On))
+            .stroke(Some(BinaryColor::Off))
+            .translate(self.pos + Point::new(self.cursor as i32 * CHAR_WIDTH, 0));
+        drawing.draw(render_cursor);
+        self.dirty = false;
+
+        (self.pos, render_text.size())
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+}
+
+impl InputConsumer for TextEdit {
+    fn input_reset(&mut self) {
+        (self.load)(&mut self.text[..]);
+        self.cursor = 0;
+        self.last_input_value = None;
+        self.dirty = true;
+    }
+
+    fn input_update(&mut self, input_id: InputId, value: Value) {
+        match value {
+            Value::Int(input_value) | Value::Fine(input_value)
+                if input_id == self.inputs.char_knob =>
+            {
+                if let Some(last_input_value) = self.last_input_value {
+                    let delta = input_value - last_input_value;
+                    if delta != 0 {
+                        self.change_char(delta);
+                        self.edited();
+                    }
+                }
+                self.last_input_value = Some(input_value);
+            }
+            Value::Gesture(gesture) if gesture.is_short_press() => {
+                if input_id == self.inputs.cursor_left && self.cursor > 0 {
+                    self.cursor -= 1;
+                    self.dirty = true;
+                } else if input_id == self.inputs.cursor_right && self.cursor + 1 < self.text.len() {
+                    self.cursor += 1;
+                    self.dirty = true;
+                } else if input_id == self.inputs.insert {
+                    self.insert();
+                    self.edited();
+                } else if input_id == self.inputs.delete {
+                    self.delete();
+                    self.edited();
+                }
+            }
+            _ => (),
+        }
+    }
+}
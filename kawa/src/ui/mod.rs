@@ -17,14 +17,75 @@
 // You should have received a copy of the GNU General Public License
 // along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
 
-pub mod framework;
+use crate::elements_handlers::NUM_PATCH_PARAMETERS;
+use crate::midi_clock::DIVISION_TICKS;
+use crate::{InputDeviceId, PanelId, APP};
+use alloc::boxed::Box;
+use framework::*;
+
+pub use kawa::framework;
+pub mod panel_arp;
 pub mod panel_blow;
 pub mod panel_bow;
+pub mod panel_ext;
+pub mod panel_input;
+pub mod panel_library;
+pub mod panel_midi;
+pub mod panel_mod;
+pub mod panel_name;
 pub mod panel_out;
 pub mod panel_res;
+pub mod panel_save;
+pub mod panel_seq;
+pub mod panel_settings;
 pub mod panel_strike;
+pub mod panel_voice;
 
 pub const KNOB_POS_X: [i32; 4] = [0, 32, 64, 96];
 pub const KNOB_POS_Y: i32 = 40;
 pub const BUTTON_POS_X: [i32; 5] = [0, 26, 51, 77, 102];
 pub const BUTTON_POS_Y: i32 = 0;
+pub const LIST_POS_Y: i32 = 14;
+pub const LIST_ROWS: usize = 4;
+pub const TEXT_EDIT_POS_Y: i32 = 26;
+pub const STEPS_POS_Y: i32 = 20;
+
+// "Off" followed by the Patch fields in declaration order
+pub const PARAMETER_LABELS: [&str; NUM_PATCH_PARAMETERS + 1] = [
+    "Off", "EnvS", "BowL", "BowT", "BlwL", "BlwM", "BlwT", "StrL", "StrM", "StrT", "Sig", "Geo",
+    "Bri", "Damp", "Pos", "RMFr", "RMOf", "RvDf", "RvLP", "Spc", "MFrq",
+];
+
+// Pressure goes to the pitch or to one of the parameters above
+pub const PRESSURE_LABELS: [&str; NUM_PATCH_PARAMETERS + 2] = [
+    "Off", "Ptch", "EnvS", "BowL", "BowT", "BlwL", "BlwM", "BlwT", "StrL", "StrM", "StrT", "Sig",
+    "Geo", "Bri", "Damp", "Pos", "RMFr", "RMOf", "RvDf", "RvLP", "Spc", "MFrq",
+];
+
+pub const DIVISION_LABELS: [&str; DIVISION_TICKS.len()] = [
+    "1/1", "1/2", "1/2T", "1/4.", "1/4", "1/4T", "1/8.", "1/8", "1/8T", "1/16.", "1/16", "1/16T",
+    "1/32",
+];
+
+// Opens the patch library, long press goes straight to saving
+pub fn setup_sys_button<'a>() -> Button<'a> {
+    Button::new(
+        Point::new(BUTTON_POS_X[4], BUTTON_POS_Y),
+        "Sys",
+        InputDeviceId::Button5 as InputId,
+        Box::new(|_value: bool| {
+            unsafe {
+                (*APP).open_panel(&mut *APP, PanelId::PanelLibrary);
+            }
+            true
+        }),
+    )
+    .with_gesture_handler(Box::new(|gesture: Gesture| {
+        if gesture == Gesture::LongPress {
+            unsafe {
+                (*APP).open_panel(&mut *APP, PanelId::PanelSave);
+            }
+        }
+        false
+    }))
+}
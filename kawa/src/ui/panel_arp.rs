@@ -0,0 +1,164 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::framework::*;
+use super::*;
+
+use crate::arpeggiator::*;
+use crate::elements_handlers::*;
+use crate::midi_clock::{DIVISION_TICKS, MAX_BPM, MIN_BPM};
+use crate::{InputDeviceId, PanelId, APP};
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+const MODE_LABELS: [&str; MODE_LAST as usize] = ["Off", "Up", "Down", "UpDn", "Rnd", "Play"];
+
+// The arpeggiator plays from the MIDI interrupt
+fn change_settings<F: FnOnce(&mut ArpSettings) -> i16>(change: F) -> i16 {
+    cortex_m::interrupt::free(|_| change(unsafe { &mut (*APP).midi_input.arpeggiator.settings }))
+}
+
+// Tempo of the internal clock, used while no MIDI clock arrives
+pub fn adjust_tempo(delta: i8) -> i16 {
+    cortex_m::interrupt::free(|_| unsafe {
+        adjust_range(
+            &mut (*APP).midi_input.tempo,
+            delta,
+            MIN_BPM as u32,
+            MAX_BPM as u32,
+        )
+    })
+}
+
+fn setup_knobs<'a>() -> Vec<Knob<'a>> {
+    vec![
+        Knob::new(
+            Point::new(KNOB_POS_X[0], KNOB_POS_Y),
+            "Mode",
+            InputDeviceId::Knob1 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                cortex_m::interrupt::free(|_| {
+                    let midi_input = unsafe { &mut (*APP).midi_input };
+                    let mut mode = midi_input.arpeggiator.settings.mode;
+                    let shown = adjust_index(&mut mode, delta, MODE_LAST);
+                    if mode != midi_input.arpeggiator.settings.mode {
+                        midi_input.set_arp_mode(mode);
+                    }
+                    shown
+                })
+            }),
+        )
+        .with_labels(&MODE_LABELS),
+        Knob::new(
+            Point::new(KNOB_POS_X[1], KNOB_POS_Y),
+            "Oct",
+            InputDeviceId::Knob2 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                change_settings(|settings| {
+                    adjust_range(&mut settings.octaves, delta, MIN_OCTAVES, MAX_OCTAVES)
+                })
+            }),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[2], KNOB_POS_Y),
+            "Gate",
+            InputDeviceId::Knob3 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                change_settings(|settings| {
+                    adjust_range(&mut settings.gate, delta, MIN_GATE, MAX_GATE)
+                })
+            }),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[3], KNOB_POS_Y),
+            "Rate",
+            InputDeviceId::Knob4 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                change_settings(|settings| {
+                    adjust_index(&mut settings.division, delta, DIVISION_TICKS.len() as u32)
+                })
+            }),
+        )
+        .with_labels(&DIVISION_LABELS)
+        .with_switch(
+            InputDeviceId::Knob4Switch as InputId,
+            KnobSwitch::Alternate(
+                "BPM",
+                Box::new(|delta: i8, _fine: bool| adjust_tempo(delta)),
+                None,
+            ),
+        ),
+    ]
+}
+
+pub fn setup_play_buttons<'a>(active: i8) -> Vec<Button<'a>> {
+    vec![
+        Button::new(
+            Point::new(BUTTON_POS_X[0], BUTTON_POS_Y),
+            if active == 0 { "*Arp" } else { " Arp" },
+            InputDeviceId::Button1 as InputId,
+            Box::new(|_value: bool| {
+                unsafe {
+                    (*APP).change_panel(&mut *APP, PanelId::PanelArp);
+                }
+                true
+            }),
+        ),
+        Button::new(
+            Point::new(BUTTON_POS_X[1], BUTTON_POS_Y),
+            if active == 1 { "*Seq" } else { " Seq" },
+            InputDeviceId::Button2 as InputId,
+            Box::new(|_value: bool| {
+                unsafe {
+                    (*APP).change_panel(&mut *APP, PanelId::PanelSeq);
+                }
+                true
+            }),
+        ),
+        Button::new(
+            Point::new(BUTTON_POS_X[2], BUTTON_POS_Y),
+            if active == 2 { "*Voi" } else { " Voi" },
+            InputDeviceId::Button3 as InputId,
+            Box::new(|_value: bool| {
+                unsafe {
+                    (*APP).change_panel(&mut *APP, PanelId::PanelVoice);
+                }
+                true
+            }),
+        ),
+        Button::new(
+            Point::new(BUTTON_POS_X[3], BUTTON_POS_Y),
+            "Res",
+            InputDeviceId::Button4 as InputId,
+            Box::new(|_value: bool| {
+                unsafe {
+                    (*APP).change_panel(&mut *APP, PanelId::PanelRes);
+                }
+                true
+            }),
+        ),
+        setup_sys_button(),
+    ]
+}
+
+pub fn setup<'a>() -> (Vec<Button<'a>>, Vec<Knob<'a>>) {
+    (setup_play_buttons(0), setup_knobs())
+}
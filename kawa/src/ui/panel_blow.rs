@@ -33,24 +33,40 @@ fn setup_knobs<'a>() -> Vec<Knob<'a>> {
             "Lvl",
             InputDeviceId::Knob1 as InputId,
             create_knob_handler(Param::ExcBlowLevel),
+        )
+        .with_switch(
+            InputDeviceId::Knob1Switch as InputId,
+            KnobSwitch::Reset(create_reset_handler(Param::ExcBlowLevel)),
         ),
         Knob::new(
             Point::new(KNOB_POS_X[1], KNOB_POS_Y),
             "Tmbr",
             InputDeviceId::Knob2 as InputId,
             create_knob_handler(Param::ExcBlowTimbre),
+        )
+        .with_switch(
+            InputDeviceId::Knob2Switch as InputId,
+            KnobSwitch::Reset(create_reset_handler(Param::ExcBlowTimbre)),
         ),
         Knob::new(
             Point::new(KNOB_POS_X[2], KNOB_POS_Y),
             "Cntr",
             InputDeviceId::Knob3 as InputId,
             create_knob_handler(Param::ExcEnvShape),
+        )
+        .with_switch(
+            InputDeviceId::Knob3Switch as InputId,
+            KnobSwitch::Reset(create_reset_handler(Param::ExcEnvShape)),
         ),
         Knob::new(
             Point::new(KNOB_POS_X[3], KNOB_POS_Y),
             "Flow",
             InputDeviceId::Knob4 as InputId,
             create_knob_handler(Param::ExcBlowMeta),
+        )
+        .with_switch(
+            InputDeviceId::Knob4Switch as InputId,
+            KnobSwitch::Reset(create_reset_handler(Param::ExcBlowMeta)),
         ),
     ]
 }
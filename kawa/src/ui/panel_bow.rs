@@ -34,18 +34,30 @@ fn setup_knobs<'a>() -> Vec<Knob<'a>> {
             "Lvl",
             InputDeviceId::Knob1 as InputId,
             create_knob_handler(Param::ExcBowLevel),
+        )
+        .with_switch(
+            InputDeviceId::Knob1Switch as InputId,
+            KnobSwitch::Reset(create_reset_handler(Param::ExcBowLevel)),
         ),
         Knob::new(
             Point::new(KNOB_POS_X[1], KNOB_POS_Y),
             "Tmbr",
             InputDeviceId::Knob2 as InputId,
             create_knob_handler(Param::ExcBowTimbre),
+        )
+        .with_switch(
+            InputDeviceId::Knob2Switch as InputId,
+            KnobSwitch::Reset(create_reset_handler(Param::ExcBowTimbre)),
         ),
         Knob::new(
             Point::new(KNOB_POS_X[2], KNOB_POS_Y),
             "Cntr",
             InputDeviceId::Knob3 as InputId,
             create_knob_handler(Param::ExcEnvShape),
+        )
+        .with_switch(
+            InputDeviceId::Knob3Switch as InputId,
+            KnobSwitch::Reset(create_reset_handler(Param::ExcEnvShape)),
         ),
     ]
 }
@@ -96,12 +108,7 @@ pub fn setup_exciter_buttons<'a>(active: i8) -> Vec<Button<'a>> {
                 true
             }),
         ),
-        Button::new(
-            Point::new(BUTTON_POS_X[4], BUTTON_POS_Y),
-            "Sys",
-            InputDeviceId::Button5 as InputId,
-            Box::new(|_value: bool| true),
-        ),
+        setup_sys_button(),
     ]
 }
 
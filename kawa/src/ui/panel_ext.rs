@@ -0,0 +1,112 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::framework::*;
+use super::*;
+
+use crate::trigger_input::{self, TriggerInput, MODE_LAST, POLARITY_LAST, PULSES_PER_BEAT};
+use crate::{InputDeviceId, PanelId, APP};
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+const MODE_LABELS: [&str; MODE_LAST as usize] = ["Off", "Gate", "Clk", "Sus"];
+const POLARITY_LABELS: [&str; POLARITY_LAST as usize] = ["Lo", "Hi"];
+const RESOLUTION_LABELS: [&str; PULSES_PER_BEAT.len()] = ["1/4", "1/8", "1/16", "24"];
+
+fn trigger() -> &'static mut TriggerInput {
+    unsafe { &mut (*APP).midi_input.trigger }
+}
+
+// Settings change through the MIDI input, which ends a held gate or pedal first
+fn change_trigger<F: FnOnce(&mut TriggerInput)>(change: F) {
+    cortex_m::interrupt::free(|_| {
+        let midi_input = unsafe { &mut (*APP).midi_input };
+        midi_input.release_trigger();
+        change(&mut midi_input.trigger);
+    });
+}
+
+fn setup_ext_knobs<'a>() -> Vec<Knob<'a>> {
+    vec![
+        Knob::new(
+            Point::new(KNOB_POS_X[0], KNOB_POS_Y),
+            "Mode",
+            InputDeviceId::Knob1 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                let mut mode = trigger().mode;
+                let value = adjust_index(&mut mode, delta, MODE_LAST);
+                if mode != trigger().mode {
+                    change_trigger(|trigger| trigger.mode = mode);
+                }
+                value
+            }),
+        )
+        .with_labels(&MODE_LABELS),
+        Knob::new(
+            Point::new(KNOB_POS_X[1], KNOB_POS_Y),
+            "Pol",
+            InputDeviceId::Knob2 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                let mut polarity = trigger().polarity;
+                let value = adjust_index(&mut polarity, delta, POLARITY_LAST);
+                if polarity != trigger().polarity {
+                    change_trigger(|trigger| trigger.polarity = polarity);
+                }
+                value
+            }),
+        )
+        .with_labels(&POLARITY_LABELS),
+        Knob::new(
+            Point::new(KNOB_POS_X[2], KNOB_POS_Y),
+            "Note",
+            InputDeviceId::Knob3 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                let mut note = trigger().note;
+                let value = adjust_range(&mut note, delta, 0, trigger_input::MAX_NOTE);
+                if note != trigger().note {
+                    // the gate closes on the note it opened
+                    change_trigger(|trigger| trigger.note = note);
+                }
+                value
+            }),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[3], KNOB_POS_Y),
+            "Res",
+            InputDeviceId::Knob4 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                adjust_index(
+                    &mut trigger().clock.resolution,
+                    delta,
+                    PULSES_PER_BEAT.len() as u32,
+                )
+            }),
+        )
+        .with_labels(&RESOLUTION_LABELS),
+    ]
+}
+
+pub fn setup<'a>() -> (Vec<Button<'a>>, Vec<Knob<'a>>) {
+    (
+        panel_voice::setup_buttons(PanelId::PanelExt),
+        setup_ext_knobs(),
+    )
+}
@@ -0,0 +1,232 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::framework::*;
+use super::*;
+
+use crate::audio_input::*;
+use crate::elements_handlers::*;
+use crate::input_gate::{self, InputGate};
+use crate::{InputDeviceId, PanelId, APP};
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+const ROUTING_LABELS: [&str; ROUTING_LAST as usize] = ["L/R", "Mono", "Off"];
+const SWITCH_LABELS: [&str; 2] = ["Off", "On"];
+
+// One row per channel, left on top, from -60 dBFS in 2 dB cells
+const METER_POS_Y: [i32; 2] = [16, 28];
+const METER_CELLS: usize = 30;
+const METER_DB_PER_CELL: i32 = 2;
+const METER_FLOOR: i32 = -(METER_CELLS as i32) * METER_DB_PER_CELL;
+
+fn audio_input() -> &'static mut AudioInput {
+    unsafe { &mut (*APP).audio_input }
+}
+
+fn input_gate() -> &'static mut InputGate {
+    unsafe { &mut (*APP).midi_input.input_gate }
+}
+
+// Shows a level in dBFS with the cell of the threshold marked
+fn setup_meter(row: usize, level: fn() -> f32, threshold: fn() -> i32) -> Steps {
+    Steps::new(
+        Point::new(0, METER_POS_Y[row]),
+        METER_CELLS,
+        Box::new(move |index: usize| {
+            let cell_level = METER_FLOOR + index as i32 * METER_DB_PER_CELL;
+            let filled = level() > cell_level as f32;
+            let threshold = threshold();
+            Some(Cell {
+                filled,
+                joined: filled,
+                marked: threshold >= cell_level && threshold < cell_level + METER_DB_PER_CELL,
+            })
+        }),
+    )
+}
+
+fn gain_handler(channel: usize) -> Box<dyn FnMut(i8, bool) -> i16> {
+    Box::new(move |delta: i8, _fine: bool| {
+        let input = audio_input();
+        let value = adjust_range(&mut input.gain[channel], delta, 0, MAX_GAIN);
+        input.apply();
+        value
+    })
+}
+
+fn threshold_handler(channel: usize) -> Box<dyn FnMut(i8, bool) -> i16> {
+    Box::new(move |delta: i8, _fine: bool| {
+        let input = audio_input();
+        adjust_range(&mut input.threshold[channel], delta, 0, MAX_THRESHOLD);
+        input.apply();
+        input.threshold_level(channel) as i16
+    })
+}
+
+fn setup_input_knobs<'a>() -> Vec<Knob<'a>> {
+    vec![
+        Knob::new(
+            Point::new(KNOB_POS_X[0], KNOB_POS_Y),
+            "Rout",
+            InputDeviceId::Knob1 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                let input = audio_input();
+                let value = adjust_index(&mut input.routing, delta, ROUTING_LAST);
+                input.apply();
+                value
+            }),
+        )
+        .with_labels(&ROUTING_LABELS),
+        Knob::new(
+            Point::new(KNOB_POS_X[1], KNOB_POS_Y),
+            "GnL",
+            InputDeviceId::Knob2 as InputId,
+            gain_handler(CHANNEL_LEFT),
+        )
+        .with_switch(
+            InputDeviceId::Knob2Switch as InputId,
+            KnobSwitch::Alternate("GnR", gain_handler(CHANNEL_RIGHT), None),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[2], KNOB_POS_Y),
+            "GtL",
+            InputDeviceId::Knob3 as InputId,
+            threshold_handler(CHANNEL_LEFT),
+        )
+        .with_switch(
+            InputDeviceId::Knob3Switch as InputId,
+            KnobSwitch::Alternate("GtR", threshold_handler(CHANNEL_RIGHT), None),
+        ),
+    ]
+}
+
+fn switch_handler(switch: fn() -> &'static mut bool) -> Box<dyn FnMut(i8, bool) -> i16> {
+    Box::new(move |delta: i8, _fine: bool| {
+        let mut on = *switch() as u32;
+        let value = adjust_index(&mut on, delta, SWITCH_LABELS.len() as u32);
+        *switch() = on != 0;
+        value
+    })
+}
+
+fn setup_trigger_knobs<'a>() -> Vec<Knob<'a>> {
+    vec![
+        Knob::new(
+            Point::new(KNOB_POS_X[0], KNOB_POS_Y),
+            "Trig",
+            InputDeviceId::Knob1 as InputId,
+            switch_handler(|| &mut input_gate().enabled),
+        )
+        .with_labels(&SWITCH_LABELS)
+        .with_switch(
+            InputDeviceId::Knob1Switch as InputId,
+            KnobSwitch::Alternate(
+                "Note",
+                Box::new(|delta: i8, _fine: bool| {
+                    adjust_range(&mut input_gate().note, delta, 0, input_gate::MAX_NOTE)
+                }),
+                None,
+            ),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[1], KNOB_POS_Y),
+            "Thr",
+            InputDeviceId::Knob2 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                let gate = input_gate();
+                adjust_range(&mut gate.threshold, delta, 0, input_gate::MAX_THRESHOLD);
+                gate.threshold_level() as i16
+            }),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[2], KNOB_POS_Y),
+            "Hyst",
+            InputDeviceId::Knob3 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                adjust_range(
+                    &mut input_gate().hysteresis,
+                    delta,
+                    0,
+                    input_gate::MAX_HYSTERESIS,
+                )
+            }),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[3], KNOB_POS_Y),
+            "Dyn",
+            InputDeviceId::Knob4 as InputId,
+            switch_handler(|| &mut input_gate().dynamics),
+        )
+        .with_labels(&SWITCH_LABELS),
+    ]
+}
+
+// Pressing the strike button again cycles through the strike, input and trigger pages
+pub fn setup_strike_button<'a>(page: PanelId) -> Button<'a> {
+    let (caption, next) = match page {
+        PanelId::PanelInput => ("*In", PanelId::PanelTrigger),
+        PanelId::PanelTrigger => ("*Trg", PanelId::PanelStrike),
+        _ => ("*Str", PanelId::PanelInput),
+    };
+    Button::new(
+        Point::new(BUTTON_POS_X[2], BUTTON_POS_Y),
+        caption,
+        InputDeviceId::Button3 as InputId,
+        Box::new(move |_value: bool| {
+            unsafe {
+                (*APP).change_panel(&mut *APP, next);
+            }
+            true
+        }),
+    )
+}
+
+fn setup_buttons<'a>(page: PanelId) -> Vec<Button<'a>> {
+    let mut buttons = super::panel_bow::setup_exciter_buttons(2);
+    buttons[2] = setup_strike_button(page);
+    buttons
+}
+
+pub fn setup_input<'a>() -> Panel<'a> {
+    Panel::new((setup_buttons(PanelId::PanelInput), setup_input_knobs()))
+        .with_steps(setup_meter(
+            0,
+            || audio_input().level(CHANNEL_LEFT),
+            || audio_input().threshold_level(CHANNEL_LEFT),
+        ))
+        .with_steps(setup_meter(
+            1,
+            || audio_input().level(CHANNEL_RIGHT),
+            || audio_input().threshold_level(CHANNEL_RIGHT),
+        ))
+}
+
+// The envelope follows the louder channel
+pub fn setup_trigger<'a>() -> Panel<'a> {
+    Panel::new((setup_buttons(PanelId::PanelTrigger), setup_trigger_knobs())).with_steps(
+        setup_meter(
+            0,
+            || unsafe { Elements_GetInputEnvelope() },
+            || input_gate().threshold_level(),
+        ),
+    )
+}
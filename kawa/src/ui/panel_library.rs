@@ -0,0 +1,160 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::framework::*;
+use super::*;
+
+use crate::patch_store::*;
+use crate::{InputDeviceId, PanelId, APP};
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+fn setup_list() -> List {
+    List::new(
+        Point::new(0, LIST_POS_Y),
+        LIST_ROWS,
+        SLOT_LABEL_LENGTH,
+        NUM_SLOTS,
+        ListInputs {
+            scroll_knob: InputDeviceId::Knob1 as InputId,
+            page_knob: InputDeviceId::Knob2 as InputId,
+            page_size: SLOTS_PER_BANK,
+        },
+        Box::new(|slot: usize, label: &mut [u8]| unsafe {
+            (*APP).librarian.slot_label(slot, label);
+        }),
+        Box::new(|slot: usize| unsafe {
+            (*APP).librarian.preview(slot);
+        }),
+    )
+}
+
+// Redraws the list after the stored patches changed
+fn refresh() {
+    unsafe {
+        (*APP).change_panel(&mut *APP, PanelId::PanelLibrary);
+    }
+}
+
+fn setup_buttons<'a>() -> Vec<Button<'a>> {
+    vec![
+        Button::new(
+            Point::new(BUTTON_POS_X[0], BUTTON_POS_Y),
+            "Back",
+            InputDeviceId::Button1 as InputId,
+            Box::new(|_value: bool| {
+                unsafe {
+                    (*APP).librarian.end_preview();
+                    (*APP).change_panel(&mut *APP, (*APP).return_panel);
+                }
+                true
+            }),
+        )
+        .with_gesture_handler(Box::new(|gesture: Gesture| {
+            // long press opens the global settings, they return to the same page
+            if gesture == Gesture::LongPress {
+                unsafe {
+                    (*APP).librarian.end_preview();
+                    (*APP).change_panel(&mut *APP, PanelId::PanelSettings);
+                }
+            }
+            false
+        })),
+        Button::new(
+            Point::new(BUTTON_POS_X[1], BUTTON_POS_Y),
+            "Load",
+            InputDeviceId::Button2 as InputId,
+            Box::new(|_value: bool| {
+                unsafe {
+                    (*APP).librarian.load();
+                    (*APP).change_panel(&mut *APP, (*APP).return_panel);
+                }
+                true
+            }),
+        ),
+        Button::new(
+            Point::new(BUTTON_POS_X[2], BUTTON_POS_Y),
+            "Save",
+            InputDeviceId::Button3 as InputId,
+            Box::new(|_value: bool| {
+                unsafe {
+                    (*APP).librarian.begin_naming(false);
+                    (*APP).change_panel(&mut *APP, PanelId::PanelName);
+                }
+                true
+            }),
+        )
+        .with_gesture_handler(Box::new(|gesture: Gesture| {
+            // long press renames the selected patch
+            if gesture == Gesture::LongPress {
+                unsafe {
+                    (*APP).librarian.begin_naming(true);
+                    (*APP).change_panel(&mut *APP, PanelId::PanelName);
+                }
+            }
+            false
+        })),
+        Button::new(
+            Point::new(BUTTON_POS_X[3], BUTTON_POS_Y),
+            "Copy",
+            InputDeviceId::Button4 as InputId,
+            Box::new(|_value: bool| {
+                unsafe {
+                    (*APP).librarian.copy();
+                }
+                refresh();
+                true
+            }),
+        )
+        .with_gesture_handler(Box::new(|gesture: Gesture| {
+            // long press swaps the patch marked by copy with the selected one
+            if gesture == Gesture::LongPress {
+                unsafe {
+                    (*APP).librarian.swap();
+                }
+                refresh();
+            }
+            false
+        })),
+        Button::new(
+            Point::new(BUTTON_POS_X[4], BUTTON_POS_Y),
+            "Del",
+            InputDeviceId::Button5 as InputId,
+            Box::new(|_value: bool| true),
+        )
+        .with_gesture_handler(Box::new(|gesture: Gesture| {
+            // double press, so that a patch is not deleted by accident
+            if gesture == Gesture::DoublePress {
+                unsafe {
+                    (*APP).librarian.delete();
+                }
+                refresh();
+            }
+            false
+        })),
+    ]
+}
+
+// Browses the stored patches, the selected one is previewed until loaded or left.
+// Knob 1 scrolls the slots, knob 2 the banks.
+pub fn setup<'a>() -> Panel<'a> {
+    Panel::new((setup_buttons(), Vec::new())).with_list(setup_list())
+}
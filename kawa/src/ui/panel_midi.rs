@@ -0,0 +1,132 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::framework::*;
+use super::*;
+
+use crate::midi_output::{Thru, NUM_CHANNELS};
+use crate::parameter_cc::ParameterEcho;
+use crate::{InputDeviceId, PanelId, APP};
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefMut;
+
+const SWITCH_LABELS: [&str; 2] = ["Off", "On"];
+const CHANNELS_POS_Y: i32 = 16;
+
+fn thru() -> RefMut<'static, Thru> {
+    unsafe { (*APP).midi_input.thru.borrow_mut() }
+}
+
+fn parameter_echo() -> &'static mut ParameterEcho {
+    unsafe { &mut (*APP).midi_input.parameter_echo }
+}
+
+fn selected_channel() -> &'static mut u32 {
+    unsafe { &mut (*APP).thru_channel }
+}
+
+fn switch(on: &mut bool, delta: i8) -> i16 {
+    let mut value = *on as u32;
+    adjust_index(&mut value, delta, SWITCH_LABELS.len() as u32);
+    *on = value != 0;
+    value as i16
+}
+
+// Channels passing the thru filter are filled, the selected one is marked
+fn setup_channel_row() -> Steps {
+    Steps::new(
+        Point::new(0, CHANNELS_POS_Y),
+        NUM_CHANNELS as usize,
+        Box::new(|index: usize| {
+            let filled = cortex_m::interrupt::free(|_| thru().passes(index as u32));
+            Some(Cell {
+                filled,
+                joined: false,
+                marked: index as u32 == *selected_channel(),
+            })
+        }),
+    )
+}
+
+fn setup_midi_knobs<'a>() -> Vec<Knob<'a>> {
+    vec![
+        Knob::new(
+            Point::new(KNOB_POS_X[0], KNOB_POS_Y),
+            "Thru",
+            InputDeviceId::Knob1 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                cortex_m::interrupt::free(|_| switch(&mut thru().enabled, delta))
+            }),
+        )
+        .with_labels(&SWITCH_LABELS),
+        Knob::new(
+            Point::new(KNOB_POS_X[1], KNOB_POS_Y),
+            "Ch",
+            InputDeviceId::Knob2 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                adjust_index(selected_channel(), delta, NUM_CHANNELS) + 1
+            }),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[2], KNOB_POS_Y),
+            "Pass",
+            InputDeviceId::Knob3 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                let channel = *selected_channel();
+                cortex_m::interrupt::free(|_| {
+                    let mut thru = thru();
+                    let mut passes = thru.passes(channel);
+                    let value = switch(&mut passes, delta);
+                    thru.set_passes(channel, passes);
+                    value
+                })
+            }),
+        )
+        .with_labels(&SWITCH_LABELS),
+        Knob::new(
+            Point::new(KNOB_POS_X[3], KNOB_POS_Y),
+            "CC",
+            InputDeviceId::Knob4 as InputId,
+            Box::new(|delta: i8, _fine: bool| switch(&mut parameter_echo().enabled, delta)),
+        )
+        .with_labels(&SWITCH_LABELS)
+        .with_switch(
+            InputDeviceId::Knob4Switch as InputId,
+            KnobSwitch::Alternate(
+                "CCCh",
+                Box::new(|delta: i8, _fine: bool| {
+                    adjust_index(&mut parameter_echo().channel, delta, NUM_CHANNELS) + 1
+                }),
+                None,
+            ),
+        ),
+    ]
+}
+
+// Thru with its channel filter and the CCs sent for knob moves
+pub fn setup<'a>() -> Panel<'a> {
+    Panel::new((
+        panel_voice::setup_buttons(PanelId::PanelMidi),
+        setup_midi_knobs(),
+    ))
+    .with_steps(setup_channel_row())
+}
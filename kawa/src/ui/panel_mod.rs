@@ -0,0 +1,293 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::framework::*;
+use super::*;
+
+use crate::elements_handlers::*;
+use crate::{InputDeviceId, PanelId, APP};
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+const SLOT_LABELS: [&str; NUM_MODULATION_SLOTS] = ["S1", "S2", "S3", "S4", "S5", "S6", "S7", "S8"];
+const SOURCE_LABELS: [&str; MODULATION_SOURCE_LAST as usize] = [
+    "Off", "LFO1", "LFO2", "Env", "Vel", "AT", "Whl", "Note", "In",
+];
+const SHAPE_LABELS: [&str; LFO_SHAPE_LAST as usize] = ["Sin", "Tri", "Saw", "Sqr", "Rnd"];
+const LFO_DIVISION_LABELS: [&str; LFO_DIVISION_LAST as usize] = [
+    "Free", "2/1", "1/1", "1/2", "1/2T", "1/4.", "1/4", "1/4T", "1/8.", "1/8", "1/8T", "1/16.",
+    "1/16", "1/16T", "1/32",
+];
+
+const AMOUNT_MIN: f32 = -1.0;
+const AMOUNT_MAX: f32 = 1.0;
+
+fn modulation() -> &'static mut ModulationSettings {
+    unsafe { &mut *Elements_GetModulation() }
+}
+
+fn selected_slot() -> &'static mut ModulationSlot {
+    unsafe { &mut modulation().slot[(*APP).modulation_slot as usize] }
+}
+
+fn setup_matrix_knobs<'a>() -> Vec<Knob<'a>> {
+    vec![
+        Knob::new(
+            Point::new(KNOB_POS_X[0], KNOB_POS_Y),
+            "Slot",
+            InputDeviceId::Knob1 as InputId,
+            Box::new(|delta: i8, _fine: bool| unsafe {
+                let slot = (*APP).modulation_slot;
+                let value = adjust_index(
+                    &mut (*APP).modulation_slot,
+                    delta,
+                    NUM_MODULATION_SLOTS as u32,
+                );
+                if (*APP).modulation_slot != slot {
+                    // show the source, destination and amount of the new slot
+                    (*APP).change_panel(&mut *APP, PanelId::PanelModMatrix);
+                }
+                value
+            }),
+        )
+        .with_labels(&SLOT_LABELS),
+        Knob::new(
+            Point::new(KNOB_POS_X[1], KNOB_POS_Y),
+            "Src",
+            InputDeviceId::Knob2 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                adjust_index(&mut selected_slot().source, delta, MODULATION_SOURCE_LAST)
+            }),
+        )
+        .with_labels(&SOURCE_LABELS)
+        .with_switch(
+            InputDeviceId::Knob2Switch as InputId,
+            KnobSwitch::Reset(Box::new(|| {
+                selected_slot().source = 0;
+                0
+            })),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[2], KNOB_POS_Y),
+            "Dst",
+            InputDeviceId::Knob3 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                adjust_index(
+                    &mut selected_slot().destination,
+                    delta,
+                    NUM_PATCH_PARAMETERS as u32,
+                )
+            }),
+        )
+        .with_labels(&PARAMETER_LABELS[1..]),
+        Knob::new(
+            Point::new(KNOB_POS_X[3], KNOB_POS_Y),
+            "Amt",
+            InputDeviceId::Knob4 as InputId,
+            Box::new(|delta: i8, fine: bool| {
+                adjust_value(
+                    &mut selected_slot().amount,
+                    delta,
+                    fine,
+                    AMOUNT_MIN,
+                    AMOUNT_MAX,
+                )
+            }),
+        )
+        .with_switch(
+            InputDeviceId::Knob4Switch as InputId,
+            KnobSwitch::Reset(Box::new(|| {
+                selected_slot().amount = 0.0;
+                0
+            })),
+        ),
+    ]
+}
+
+fn setup_lfo_knobs<'a>() -> Vec<Knob<'a>> {
+    vec![
+        Knob::new(
+            Point::new(KNOB_POS_X[0], KNOB_POS_Y),
+            "Rt1",
+            InputDeviceId::Knob1 as InputId,
+            Box::new(|delta: i8, fine: bool| {
+                adjust_value(
+                    &mut modulation().lfo[0].rate,
+                    delta,
+                    fine,
+                    PARAM_MIN,
+                    PARAM_MAX,
+                )
+            }),
+        )
+        .with_switch(
+            InputDeviceId::Knob1Switch as InputId,
+            KnobSwitch::Alternate(
+                "Div1",
+                Box::new(|delta: i8, _fine: bool| {
+                    adjust_index(&mut modulation().lfo[0].division, delta, LFO_DIVISION_LAST)
+                }),
+                Some(&LFO_DIVISION_LABELS),
+            ),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[1], KNOB_POS_Y),
+            "Sh1",
+            InputDeviceId::Knob2 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                adjust_index(&mut modulation().lfo[0].shape, delta, LFO_SHAPE_LAST)
+            }),
+        )
+        .with_labels(&SHAPE_LABELS),
+        Knob::new(
+            Point::new(KNOB_POS_X[2], KNOB_POS_Y),
+            "Rt2",
+            InputDeviceId::Knob3 as InputId,
+            Box::new(|delta: i8, fine: bool| {
+                adjust_value(
+                    &mut modulation().lfo[1].rate,
+                    delta,
+                    fine,
+                    PARAM_MIN,
+                    PARAM_MAX,
+                )
+            }),
+        )
+        .with_switch(
+            InputDeviceId::Knob3Switch as InputId,
+            KnobSwitch::Alternate(
+                "Div2",
+                Box::new(|delta: i8, _fine: bool| {
+                    adjust_index(&mut modulation().lfo[1].division, delta, LFO_DIVISION_LAST)
+                }),
+                Some(&LFO_DIVISION_LABELS),
+            ),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[3], KNOB_POS_Y),
+            "Sh2",
+            InputDeviceId::Knob4 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                adjust_index(&mut modulation().lfo[1].shape, delta, LFO_SHAPE_LAST)
+            }),
+        )
+        .with_labels(&SHAPE_LABELS),
+    ]
+}
+
+macro_rules! envelope_bind {
+    ($PARAM:ident) => {
+        Box::new(|delta: i8, fine: bool| {
+            adjust_value(&mut modulation().$PARAM, delta, fine, PARAM_MIN, PARAM_MAX)
+        })
+    };
+}
+
+fn setup_envelope_knobs<'a>() -> Vec<Knob<'a>> {
+    vec![
+        Knob::new(
+            Point::new(KNOB_POS_X[0], KNOB_POS_Y),
+            "Att",
+            InputDeviceId::Knob1 as InputId,
+            envelope_bind!(envelope_attack),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[1], KNOB_POS_Y),
+            "Dec",
+            InputDeviceId::Knob2 as InputId,
+            envelope_bind!(envelope_decay),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[2], KNOB_POS_Y),
+            "Sus",
+            InputDeviceId::Knob3 as InputId,
+            envelope_bind!(envelope_sustain),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[3], KNOB_POS_Y),
+            "Rel",
+            InputDeviceId::Knob4 as InputId,
+            envelope_bind!(envelope_release),
+        ),
+    ]
+}
+
+pub fn setup_modulation_buttons<'a>(active: i8) -> Vec<Button<'a>> {
+    vec![
+        Button::new(
+            Point::new(BUTTON_POS_X[0], BUTTON_POS_Y),
+            if active == 0 { "*Mtx" } else { " Mtx" },
+            InputDeviceId::Button1 as InputId,
+            Box::new(|_value: bool| {
+                unsafe {
+                    (*APP).change_panel(&mut *APP, PanelId::PanelModMatrix);
+                }
+                true
+            }),
+        ),
+        Button::new(
+            Point::new(BUTTON_POS_X[1], BUTTON_POS_Y),
+            if active == 1 { "*LFO" } else { " LFO" },
+            InputDeviceId::Button2 as InputId,
+            Box::new(|_value: bool| {
+                unsafe {
+                    (*APP).change_panel(&mut *APP, PanelId::PanelModLfo);
+                }
+                true
+            }),
+        ),
+        Button::new(
+            Point::new(BUTTON_POS_X[2], BUTTON_POS_Y),
+            if active == 2 { "*Env" } else { " Env" },
+            InputDeviceId::Button3 as InputId,
+            Box::new(|_value: bool| {
+                unsafe {
+                    (*APP).change_panel(&mut *APP, PanelId::PanelModEnv);
+                }
+                true
+            }),
+        ),
+        Button::new(
+            Point::new(BUTTON_POS_X[3], BUTTON_POS_Y),
+            "Arp",
+            InputDeviceId::Button4 as InputId,
+            Box::new(|_value: bool| {
+                unsafe {
+                    (*APP).change_panel(&mut *APP, PanelId::PanelArp);
+                }
+                true
+            }),
+        ),
+        setup_sys_button(),
+    ]
+}
+
+pub fn setup_matrix<'a>() -> (Vec<Button<'a>>, Vec<Knob<'a>>) {
+    (setup_modulation_buttons(0), setup_matrix_knobs())
+}
+
+pub fn setup_lfo<'a>() -> (Vec<Button<'a>>, Vec<Knob<'a>>) {
+    (setup_modulation_buttons(1), setup_lfo_knobs())
+}
+
+pub fn setup_envelope<'a>() -> (Vec<Button<'a>>, Vec<Knob<'a>>) {
+    (setup_modulation_buttons(2), setup_envelope_knobs())
+}
@@ -0,0 +1,105 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::framework::*;
+use super::*;
+
+use crate::patch_store::*;
+use crate::{InputDeviceId, APP};
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+fn setup_text_edit() -> TextEdit {
+    TextEdit::new(
+        Point::new(0, TEXT_EDIT_POS_Y),
+        NAME_LENGTH,
+        TextEditInputs {
+            char_knob: InputDeviceId::Knob1 as InputId,
+            cursor_left: InputDeviceId::Button1 as InputId,
+            cursor_right: InputDeviceId::Button2 as InputId,
+            insert: InputDeviceId::Button3 as InputId,
+            delete: InputDeviceId::Button4 as InputId,
+        },
+        Box::new(|text: &mut [u8]| unsafe {
+            text.copy_from_slice((*APP).librarian.name());
+        }),
+        Box::new(|text: &[u8]| unsafe {
+            (*APP).librarian.set_name(text);
+        }),
+    )
+}
+
+fn setup_buttons<'a>() -> Vec<Button<'a>> {
+    vec![
+        Button::new(
+            Point::new(BUTTON_POS_X[0], BUTTON_POS_Y),
+            "<",
+            InputDeviceId::Button1 as InputId,
+            Box::new(|_value: bool| false),
+        ),
+        Button::new(
+            Point::new(BUTTON_POS_X[1], BUTTON_POS_Y),
+            ">",
+            InputDeviceId::Button2 as InputId,
+            Box::new(|_value: bool| false),
+        ),
+        Button::new(
+            Point::new(BUTTON_POS_X[2], BUTTON_POS_Y),
+            "Ins",
+            InputDeviceId::Button3 as InputId,
+            Box::new(|_value: bool| false),
+        ),
+        Button::new(
+            Point::new(BUTTON_POS_X[3], BUTTON_POS_Y),
+            "Del",
+            InputDeviceId::Button4 as InputId,
+            Box::new(|_value: bool| false),
+        ),
+        Button::new(
+            Point::new(BUTTON_POS_X[4], BUTTON_POS_Y),
+            "OK",
+            InputDeviceId::Button5 as InputId,
+            Box::new(|_value: bool| {
+                unsafe {
+                    (*APP).librarian.finish_naming();
+                    (*APP).change_panel(&mut *APP, (*APP).return_panel);
+                }
+                true
+            }),
+        )
+        .with_gesture_handler(Box::new(|gesture: Gesture| {
+            // long press on OK leaves without saving
+            if gesture == Gesture::LongPress {
+                unsafe {
+                    (*APP).librarian.cancel_naming();
+                    (*APP).change_panel(&mut *APP, (*APP).return_panel);
+                }
+            }
+            false
+        })),
+    ]
+}
+
+// Names the patch and saves it, or renames the stored one, in the slot picked on the
+// save or library page. Knob 1 picks the character under the cursor.
+pub fn setup<'a>() -> Panel<'a> {
+    Panel::new((setup_buttons(), Vec::new())).with_text_edit(setup_text_edit())
+}
@@ -21,18 +21,56 @@ use super::framework::*;
 use super::*;
 
 use crate::elements_handlers::*;
-use crate::InputDeviceId;
+use crate::tuning_store::NUM_TUNINGS;
+use crate::{InputDeviceId, APP};
 
+use alloc::boxed::Box;
 use alloc::vec;
 use alloc::vec::Vec;
 
+// Equal temperament followed by the stored tunings
+const TUNING_LABELS: [&str; NUM_TUNINGS + 1] =
+    ["ET", "T1", "T2", "T3", "T4", "T5", "T6", "T7", "T8"];
+
 fn setup_knobs<'a>() -> Vec<Knob<'a>> {
-    vec![Knob::new(
-        Point::new(KNOB_POS_X[2], KNOB_POS_Y),
-        "Spc",
-        InputDeviceId::Knob3 as InputId,
-        create_knob_handler(Param::Space),
-    )]
+    vec![
+        Knob::new(
+            Point::new(KNOB_POS_X[0], KNOB_POS_Y),
+            "Tun",
+            InputDeviceId::Knob1 as InputId,
+            Box::new(|delta: i8, _fine: bool| unsafe {
+                let midi_input = &mut (*APP).midi_input;
+                let mut index = midi_input.tuning_index;
+                let value = adjust_index(&mut index, delta, TUNING_LABELS.len() as u32);
+                if index != midi_input.tuning_index {
+                    let table = (*APP).tunings.table_of(index);
+                    cortex_m::interrupt::free(|_| midi_input.set_tuning(index, &table));
+                }
+                value
+            }),
+        )
+        .with_labels(&TUNING_LABELS),
+        Knob::new(
+            Point::new(KNOB_POS_X[2], KNOB_POS_Y),
+            "Spc",
+            InputDeviceId::Knob3 as InputId,
+            create_knob_handler(Param::Space),
+        )
+        .with_switch(
+            InputDeviceId::Knob3Switch as InputId,
+            KnobSwitch::Reset(create_reset_handler(Param::Space)),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[3], KNOB_POS_Y),
+            "Diff",
+            InputDeviceId::Knob4 as InputId,
+            create_knob_handler(Param::ReverbDiffusion),
+        )
+        .with_switch(
+            InputDeviceId::Knob4Switch as InputId,
+            KnobSwitch::Alternate("RvLP", create_knob_handler(Param::ReverbLp), None),
+        ),
+    ]
 }
 
 pub fn setup<'a>() -> (Vec<Button<'a>>, Vec<Knob<'a>>) {
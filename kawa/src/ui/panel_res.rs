@@ -34,24 +34,40 @@ fn setup_knobs<'a>() -> Vec<Knob<'a>> {
             "Geo",
             InputDeviceId::Knob1 as InputId,
             create_knob_handler(Param::ResGeometry),
+        )
+        .with_switch(
+            InputDeviceId::Knob1Switch as InputId,
+            KnobSwitch::Reset(create_reset_handler(Param::ResGeometry)),
         ),
         Knob::new(
             Point::new(KNOB_POS_X[1], KNOB_POS_Y),
             "Bri",
             InputDeviceId::Knob2 as InputId,
             create_knob_handler(Param::ResBrightness),
+        )
+        .with_switch(
+            InputDeviceId::Knob2Switch as InputId,
+            KnobSwitch::Reset(create_reset_handler(Param::ResBrightness)),
         ),
         Knob::new(
             Point::new(KNOB_POS_X[2], KNOB_POS_Y),
             "Damp",
             InputDeviceId::Knob3 as InputId,
             create_knob_handler(Param::ResDamping),
+        )
+        .with_switch(
+            InputDeviceId::Knob3Switch as InputId,
+            KnobSwitch::Reset(create_reset_handler(Param::ResDamping)),
         ),
         Knob::new(
             Point::new(KNOB_POS_X[3], KNOB_POS_Y),
             "Pos",
             InputDeviceId::Knob4 as InputId,
             create_knob_handler(Param::ResPosition),
+        )
+        .with_switch(
+            InputDeviceId::Knob4Switch as InputId,
+            KnobSwitch::Reset(create_reset_handler(Param::ResPosition)),
         ),
     ]
 }
@@ -80,6 +96,17 @@ pub fn setup_resonator_buttons<'a>(active: i8) -> Vec<Button<'a>> {
                 true
             }),
         ),
+        Button::new(
+            Point::new(BUTTON_POS_X[2], BUTTON_POS_Y),
+            "Mod",
+            InputDeviceId::Button3 as InputId,
+            Box::new(|_value: bool| {
+                unsafe {
+                    (*APP).change_panel(&mut *APP, PanelId::PanelModMatrix);
+                }
+                true
+            }),
+        ),
         Button::new(
             Point::new(BUTTON_POS_X[3], BUTTON_POS_Y),
             "Exc",
@@ -91,12 +118,7 @@ pub fn setup_resonator_buttons<'a>(active: i8) -> Vec<Button<'a>> {
                 true
             }),
         ),
-        Button::new(
-            Point::new(BUTTON_POS_X[4], BUTTON_POS_Y),
-            "Sys",
-            InputDeviceId::Button5 as InputId,
-            Box::new(|_value: bool| true),
-        ),
+        setup_sys_button(),
     ]
 }
 
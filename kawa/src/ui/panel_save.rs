@@ -0,0 +1,81 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::framework::*;
+use super::*;
+
+use crate::patch_store::*;
+use crate::{InputDeviceId, PanelId, APP};
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+fn setup_list() -> List {
+    List::new(
+        Point::new(0, LIST_POS_Y),
+        LIST_ROWS,
+        SLOT_LABEL_LENGTH,
+        NUM_SLOTS,
+        ListInputs {
+            scroll_knob: InputDeviceId::Knob1 as InputId,
+            page_knob: InputDeviceId::Knob2 as InputId,
+            page_size: SLOTS_PER_BANK,
+        },
+        Box::new(|slot: usize, label: &mut [u8]| unsafe {
+            (*APP).librarian.slot_label(slot, label);
+        }),
+        Box::new(|slot: usize| unsafe {
+            (*APP).librarian.select(slot);
+        }),
+    )
+}
+
+fn setup_buttons<'a>() -> Vec<Button<'a>> {
+    vec![
+        Button::new(
+            Point::new(BUTTON_POS_X[0], BUTTON_POS_Y),
+            "Back",
+            InputDeviceId::Button1 as InputId,
+            Box::new(|_value: bool| {
+                unsafe {
+                    (*APP).change_panel(&mut *APP, (*APP).return_panel);
+                }
+                true
+            }),
+        ),
+        Button::new(
+            Point::new(BUTTON_POS_X[4], BUTTON_POS_Y),
+            "Name",
+            InputDeviceId::Button5 as InputId,
+            Box::new(|_value: bool| {
+                unsafe {
+                    (*APP).librarian.begin_naming(false);
+                    (*APP).change_panel(&mut *APP, PanelId::PanelName);
+                }
+                true
+            }),
+        ),
+    ]
+}
+
+// Picks the slot to save the edited patch to, the name is entered on the next page
+pub fn setup<'a>() -> Panel<'a> {
+    Panel::new((setup_buttons(), Vec::new())).with_list(setup_list())
+}
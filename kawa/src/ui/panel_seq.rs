@@ -0,0 +1,301 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::framework::*;
+use super::panel_arp::adjust_tempo;
+use super::*;
+
+use crate::elements_handlers::*;
+use crate::midi_clock::DIVISION_TICKS;
+use crate::sequencer::*;
+use crate::{InputDeviceId, PanelId, APP};
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+const LOCK_LABELS: [&str; LOCKS_PER_STEP] = ["L1", "L2"];
+const MAX_NOTE: u32 = 127;
+const MAX_VELOCITY: u32 = 127;
+
+fn sequence() -> &'static mut Sequence {
+    unsafe { &mut (*APP).midi_input.sequencer.sequence }
+}
+
+fn selected_step() -> &'static mut Step {
+    let sequence = sequence();
+    let index = unsafe { (*APP).sequencer_step as usize } % sequence.length.max(1) as usize;
+    &mut sequence.steps[index]
+}
+
+fn selected_lock() -> &'static mut Lock {
+    unsafe { &mut selected_step().locks[(*APP).sequencer_lock as usize] }
+}
+
+// Steps a u8 field with a knob, shown as a number
+fn adjust_byte(value: &mut u8, delta: i8, min: u32, max: u32) -> i16 {
+    let mut adjusted = *value as u32;
+    let shown = adjust_range(&mut adjusted, delta, min, max);
+    *value = adjusted as u8;
+    shown
+}
+
+fn setup_step_row() -> Steps {
+    Steps::new(
+        Point::new(0, STEPS_POS_Y),
+        MAX_STEPS,
+        Box::new(|index: usize| {
+            let sequence = sequence();
+            if index >= sequence.length as usize {
+                return None;
+            }
+            let flags = sequence.steps[index].flags;
+            Some(Cell {
+                filled: flags & STEP_TRIG != 0,
+                joined: flags & STEP_TRIG != 0 && flags & STEP_TIE != 0,
+                marked: index as u32 == unsafe { (*APP).sequencer_step },
+            })
+        }),
+    )
+}
+
+// Changing the step shows its values on the rest of the knobs of the panel
+fn setup_step_knob<'a>(panel: PanelId) -> Knob<'a> {
+    Knob::new(
+        Point::new(KNOB_POS_X[0], KNOB_POS_Y),
+        "Step",
+        InputDeviceId::Knob1 as InputId,
+        Box::new(move |delta: i8, _fine: bool| unsafe {
+            let step = (*APP).sequencer_step;
+            adjust_index(&mut (*APP).sequencer_step, delta, sequence().length as u32);
+            if (*APP).sequencer_step != step {
+                (*APP).change_panel(&mut *APP, panel);
+            }
+            (*APP).sequencer_step as i16 + 1
+        }),
+    )
+    .with_switch(
+        InputDeviceId::Knob1Switch as InputId,
+        KnobSwitch::Alternate(
+            "Len",
+            Box::new(|delta: i8, _fine: bool| {
+                adjust_byte(&mut sequence().length, delta, 1, MAX_STEPS as u32)
+            }),
+            None,
+        ),
+    )
+}
+
+fn setup_step_knobs<'a>() -> Vec<Knob<'a>> {
+    vec![
+        setup_step_knob(PanelId::PanelSeq),
+        Knob::new(
+            Point::new(KNOB_POS_X[1], KNOB_POS_Y),
+            "Note",
+            InputDeviceId::Knob2 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                adjust_byte(&mut selected_step().note, delta, 0, MAX_NOTE)
+            }),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[2], KNOB_POS_Y),
+            "Vel",
+            InputDeviceId::Knob3 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                adjust_byte(&mut selected_step().velocity, delta, 1, MAX_VELOCITY)
+            }),
+        )
+        .with_switch(
+            InputDeviceId::Knob3Switch as InputId,
+            KnobSwitch::Alternate(
+                "Gate",
+                Box::new(|delta: i8, _fine: bool| {
+                    adjust_byte(
+                        &mut selected_step().gate,
+                        delta,
+                        MIN_GATE as u32,
+                        MAX_GATE as u32,
+                    )
+                }),
+                None,
+            ),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[3], KNOB_POS_Y),
+            "Rate",
+            InputDeviceId::Knob4 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                let mut division = sequence().division as u32;
+                let shown = adjust_index(&mut division, delta, DIVISION_TICKS.len() as u32);
+                sequence().division = division as u8;
+                shown
+            }),
+        )
+        .with_labels(&DIVISION_LABELS)
+        .with_switch(
+            InputDeviceId::Knob4Switch as InputId,
+            KnobSwitch::Alternate(
+                "BPM",
+                Box::new(|delta: i8, _fine: bool| adjust_tempo(delta)),
+                None,
+            ),
+        ),
+    ]
+}
+
+fn setup_lock_knobs<'a>() -> Vec<Knob<'a>> {
+    vec![
+        setup_step_knob(PanelId::PanelSeqLock),
+        Knob::new(
+            Point::new(KNOB_POS_X[1], KNOB_POS_Y),
+            "Lock",
+            InputDeviceId::Knob2 as InputId,
+            Box::new(|delta: i8, _fine: bool| unsafe {
+                let lock = (*APP).sequencer_lock;
+                let shown = adjust_index(&mut (*APP).sequencer_lock, delta, LOCKS_PER_STEP as u32);
+                if (*APP).sequencer_lock != lock {
+                    (*APP).change_panel(&mut *APP, PanelId::PanelSeqLock);
+                }
+                shown
+            }),
+        )
+        .with_labels(&LOCK_LABELS),
+        Knob::new(
+            Point::new(KNOB_POS_X[2], KNOB_POS_Y),
+            "Prm",
+            InputDeviceId::Knob3 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                let lock = selected_lock();
+                let parameter = lock.parameter;
+                let shown =
+                    adjust_index(&mut lock.parameter, delta, NUM_PATCH_PARAMETERS as u32 + 1);
+                if lock.parameter != parameter && lock.parameter != 0 {
+                    // start from the value in the patch
+                    let patch = unsafe { &*Elements_GetPatch() };
+                    let values = unsafe {
+                        core::slice::from_raw_parts(
+                            patch as *const Patch as *const f32,
+                            NUM_PATCH_PARAMETERS,
+                        )
+                    };
+                    lock.value = values[lock.parameter as usize - 1];
+                }
+                shown
+            }),
+        )
+        .with_labels(&PARAMETER_LABELS),
+        Knob::new(
+            Point::new(KNOB_POS_X[3], KNOB_POS_Y),
+            "Val",
+            InputDeviceId::Knob4 as InputId,
+            Box::new(|delta: i8, fine: bool| {
+                adjust_value(
+                    &mut selected_lock().value,
+                    delta,
+                    fine,
+                    PARAM_MIN,
+                    PARAM_MAX,
+                )
+            }),
+        ),
+    ]
+}
+
+fn setup_sequencer_buttons<'a>(active: i8) -> Vec<Button<'a>> {
+    vec![
+        Button::new(
+            Point::new(BUTTON_POS_X[0], BUTTON_POS_Y),
+            "Arp",
+            InputDeviceId::Button1 as InputId,
+            Box::new(|_value: bool| {
+                unsafe {
+                    (*APP).change_panel(&mut *APP, PanelId::PanelArp);
+                }
+                true
+            }),
+        ),
+        Button::new(
+            Point::new(BUTTON_POS_X[1], BUTTON_POS_Y),
+            "Run",
+            InputDeviceId::Button2 as InputId,
+            Box::new(|_value: bool| {
+                // the clock interrupts play the sequence
+                cortex_m::interrupt::free(|_| unsafe {
+                    let midi_input = &mut (*APP).midi_input;
+                    if midi_input.sequencer.is_playing() {
+                        midi_input.stop_sequencer();
+                    } else {
+                        midi_input.start_sequencer();
+                    }
+                });
+                false
+            }),
+        ),
+        // long press ties the step to the next one
+        Button::new(
+            Point::new(BUTTON_POS_X[2], BUTTON_POS_Y),
+            "Trig",
+            InputDeviceId::Button3 as InputId,
+            Box::new(|_value: bool| {
+                selected_step().flags ^= STEP_TRIG;
+                false
+            }),
+        )
+        .with_gesture_handler(Box::new(|gesture: Gesture| {
+            if gesture == Gesture::LongPress {
+                selected_step().flags ^= STEP_TIE;
+            }
+            false
+        })),
+        if active == 0 {
+            Button::new(
+                Point::new(BUTTON_POS_X[3], BUTTON_POS_Y),
+                "Lock",
+                InputDeviceId::Button4 as InputId,
+                Box::new(|_value: bool| {
+                    unsafe {
+                        (*APP).change_panel(&mut *APP, PanelId::PanelSeqLock);
+                    }
+                    true
+                }),
+            )
+        } else {
+            Button::new(
+                Point::new(BUTTON_POS_X[3], BUTTON_POS_Y),
+                "Step",
+                InputDeviceId::Button4 as InputId,
+                Box::new(|_value: bool| {
+                    unsafe {
+                        (*APP).change_panel(&mut *APP, PanelId::PanelSeq);
+                    }
+                    true
+                }),
+            )
+        },
+        setup_sys_button(),
+    ]
+}
+
+pub fn setup_steps<'a>() -> Panel<'a> {
+    Panel::new((setup_sequencer_buttons(0), setup_step_knobs())).with_steps(setup_step_row())
+}
+
+pub fn setup_locks<'a>() -> Panel<'a> {
+    Panel::new((setup_sequencer_buttons(1), setup_lock_knobs())).with_steps(setup_step_row())
+}
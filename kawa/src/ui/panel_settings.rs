@@ -0,0 +1,194 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::framework::*;
+use super::*;
+
+use crate::elements_handlers::*;
+use crate::midi_input::MidiInput;
+use crate::midi_output::NUM_CHANNELS;
+use crate::patch_store::NUM_SLOTS;
+use crate::settings::{Settings, MAX_BEND_RANGE};
+use crate::tuning::{MasterTuning, MAX_FINE_TUNE, MAX_OCTAVE, MAX_SEMITONE};
+use crate::{InputDeviceId, MidiUart, PanelId, APP};
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+const CHANNEL_LABELS: [&str; NUM_CHANNELS as usize + 1] = [
+    "Omni", "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12", "13", "14", "15", "16",
+];
+// the init patch followed by the slots
+const STARTUP_LABELS: [&str; NUM_SLOTS + 1] = [
+    "Init", "A01", "A02", "A03", "A04", "A05", "A06", "A07", "A08", "A09", "A10", "A11", "A12",
+    "A13", "A14", "A15", "A16", "B01", "B02", "B03", "B04", "B05", "B06", "B07", "B08", "B09",
+    "B10", "B11", "B12", "B13", "B14", "B15", "B16", "C01", "C02", "C03", "C04", "C05", "C06",
+    "C07", "C08", "C09", "C10", "C11", "C12", "C13", "C14", "C15", "C16", "D01", "D02", "D03",
+    "D04", "D05", "D06", "D07", "D08", "D09", "D10", "D11", "D12", "D13", "D14", "D15", "D16",
+];
+
+fn midi_input() -> &'static mut MidiInput<MidiUart> {
+    unsafe { &mut (*APP).midi_input }
+}
+
+// Shows changes made elsewhere, e.g. by restoring the defaults
+fn refresh() {
+    unsafe {
+        (*APP).change_panel(&mut *APP, PanelId::PanelSettings);
+    }
+}
+
+fn setup_buttons<'a>() -> Vec<Button<'a>> {
+    vec![
+        Button::new(
+            Point::new(BUTTON_POS_X[0], BUTTON_POS_Y),
+            "Back",
+            InputDeviceId::Button1 as InputId,
+            Box::new(|_value: bool| {
+                unsafe {
+                    (*APP).change_panel(&mut *APP, (*APP).return_panel);
+                }
+                true
+            }),
+        ),
+        Button::new(
+            Point::new(BUTTON_POS_X[1], BUTTON_POS_Y),
+            "Save",
+            InputDeviceId::Button2 as InputId,
+            Box::new(|_value: bool| {
+                // the page stays open when the settings could not be written
+                unsafe {
+                    if (*APP).save_settings().is_ok() {
+                        (*APP).change_panel(&mut *APP, (*APP).return_panel);
+                    }
+                }
+                true
+            }),
+        ),
+        Button::new(
+            Point::new(BUTTON_POS_X[4], BUTTON_POS_Y),
+            "Dflt",
+            InputDeviceId::Button5 as InputId,
+            Box::new(|_value: bool| true),
+        )
+        .with_gesture_handler(Box::new(|gesture: Gesture| {
+            // double press, the defaults are kept only once saved
+            if gesture == Gesture::DoublePress {
+                unsafe {
+                    (*APP).apply_settings(&Settings::new());
+                }
+                refresh();
+            }
+            false
+        })),
+    ]
+}
+
+// The MIDI interrupt tunes the notes it plays with it, so it changes in a critical section
+fn change_tuning<F: FnOnce(&mut MasterTuning) -> i16>(change: F) -> i16 {
+    cortex_m::interrupt::free(|_| change(&mut midi_input().master_tuning))
+}
+
+fn setup_settings_knobs<'a>() -> Vec<Knob<'a>> {
+    vec![
+        Knob::new(
+            Point::new(KNOB_POS_X[0], KNOB_POS_Y),
+            "Ch",
+            InputDeviceId::Knob1 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                adjust_index(
+                    &mut midi_input().channel,
+                    delta,
+                    CHANNEL_LABELS.len() as u32,
+                )
+            }),
+        )
+        .with_labels(&CHANNEL_LABELS)
+        .with_switch(
+            InputDeviceId::Knob1Switch as InputId,
+            KnobSwitch::Alternate(
+                "Bend",
+                Box::new(|delta: i8, _fine: bool| {
+                    let mpe = &mut midi_input().mpe;
+                    let mut range = mpe.default_bend_range() as u32;
+                    let value = adjust_range(&mut range, delta, 0, MAX_BEND_RANGE);
+                    cortex_m::interrupt::free(|_| mpe.set_default_bend_range(range as u8));
+                    value
+                }),
+                None,
+            ),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[1], KNOB_POS_Y),
+            "Boot",
+            InputDeviceId::Knob2 as InputId,
+            Box::new(|delta: i8, _fine: bool| unsafe {
+                adjust_index(
+                    &mut (*APP).startup_patch,
+                    delta,
+                    STARTUP_LABELS.len() as u32,
+                )
+            }),
+        )
+        .with_labels(&STARTUP_LABELS),
+        Knob::new(
+            Point::new(KNOB_POS_X[2], KNOB_POS_Y),
+            "Oct",
+            InputDeviceId::Knob3 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                change_tuning(|master_tuning| {
+                    adjust_range(&mut master_tuning.octave, delta, 0, MAX_OCTAVE);
+                    master_tuning.octaves() as i16
+                })
+            }),
+        )
+        .with_switch(
+            InputDeviceId::Knob3Switch as InputId,
+            KnobSwitch::Alternate(
+                "Semi",
+                Box::new(|delta: i8, _fine: bool| {
+                    change_tuning(|master_tuning| {
+                        adjust_range(&mut master_tuning.semitone, delta, 0, MAX_SEMITONE);
+                        master_tuning.semitones() as i16
+                    })
+                }),
+                None,
+            ),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[3], KNOB_POS_Y),
+            "Tune",
+            InputDeviceId::Knob4 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                change_tuning(|master_tuning| {
+                    adjust_range(&mut master_tuning.fine_tune, delta, 0, MAX_FINE_TUNE);
+                    master_tuning.cents() as i16
+                })
+            }),
+        ),
+    ]
+}
+
+// Global settings: the MIDI input channel, the pitch bend range, the patch loaded at power
+// on, transpose and fine tune in cents. Everything set on the other non-patch pages is
+// saved along with them.
+pub fn setup<'a>() -> (Vec<Button<'a>>, Vec<Knob<'a>>) {
+    (setup_buttons(), setup_settings_knobs())
+}
@@ -21,11 +21,15 @@ use super::framework::*;
 use super::*;
 
 use crate::elements_handlers::*;
-use crate::InputDeviceId;
+use crate::{InputDeviceId, PanelId, APP};
 
+use alloc::boxed::Box;
 use alloc::vec;
 use alloc::vec::Vec;
 
+// Built-in hits or the samples uploaded over SysEx
+const SAMPLE_LABELS: [&str; 2] = ["Int", "User"];
+
 fn setup_knobs<'a>() -> Vec<Knob<'a>> {
     vec![
         Knob::new(
@@ -33,22 +37,51 @@ fn setup_knobs<'a>() -> Vec<Knob<'a>> {
             "Lvl",
             InputDeviceId::Knob1 as InputId,
             create_knob_handler(Param::ExcStrikeLevel),
+        )
+        .with_switch(
+            InputDeviceId::Knob1Switch as InputId,
+            KnobSwitch::Reset(create_reset_handler(Param::ExcStrikeLevel)),
         ),
         Knob::new(
             Point::new(KNOB_POS_X[1], KNOB_POS_Y),
             "Tmbr",
             InputDeviceId::Knob2 as InputId,
             create_knob_handler(Param::ExcStrikeTimbre),
+        )
+        .with_switch(
+            InputDeviceId::Knob2Switch as InputId,
+            KnobSwitch::Reset(create_reset_handler(Param::ExcStrikeTimbre)),
         ),
+        Knob::new(
+            Point::new(KNOB_POS_X[2], KNOB_POS_Y),
+            "Smp",
+            InputDeviceId::Knob3 as InputId,
+            Box::new(|delta: i8, _fine: bool| unsafe {
+                let samples = &mut (*APP).samples;
+                let mut user = samples.selected() as u32;
+                let value = adjust_index(&mut user, delta, SAMPLE_LABELS.len() as u32);
+                if (user != 0) != samples.selected() {
+                    samples.select(user != 0);
+                }
+                value
+            }),
+        )
+        .with_labels(&SAMPLE_LABELS),
         Knob::new(
             Point::new(KNOB_POS_X[3], KNOB_POS_Y),
             "Mllt",
             InputDeviceId::Knob4 as InputId,
             create_knob_handler(Param::ExcStrikeMeta),
+        )
+        .with_switch(
+            InputDeviceId::Knob4Switch as InputId,
+            KnobSwitch::Reset(create_reset_handler(Param::ExcStrikeMeta)),
         ),
     ]
 }
 
 pub fn setup<'a>() -> (Vec<Button<'a>>, Vec<Knob<'a>>) {
-    (super::panel_bow::setup_exciter_buttons(2), setup_knobs())
+    let mut buttons = super::panel_bow::setup_exciter_buttons(2);
+    buttons[2] = super::panel_input::setup_strike_button(PanelId::PanelStrike);
+    (buttons, setup_knobs())
 }
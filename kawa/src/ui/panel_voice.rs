@@ -0,0 +1,269 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::framework::*;
+use super::*;
+
+use crate::elements_handlers::*;
+use crate::midi_input::MidiInput;
+use crate::velocity::*;
+use crate::voice_allocator::*;
+use crate::{InputDeviceId, MidiUart, PanelId, APP};
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+const STEAL_LABELS: [&str; STEAL_LAST as usize] = ["RRob", "Old", "Rel"];
+const UNISON_LABELS: [&str; 2] = ["Off", "On"];
+const CURVE_LABELS: [&str; CURVE_LAST as usize] = ["Lin", "Soft", "Hard", "Fix"];
+
+const AMOUNT_MIN: f32 = -1.0;
+const AMOUNT_MAX: f32 = 1.0;
+
+// The MIDI interrupt plays the notes with these settings, so they change in a critical
+// section
+fn change<F: FnOnce(&mut MidiInput<MidiUart>) -> i16>(change: F) -> i16 {
+    cortex_m::interrupt::free(|_| change(unsafe { &mut (*APP).midi_input }))
+}
+
+fn velocity_slot(index: usize) -> &'static mut VelocitySlot {
+    unsafe { &mut (*Elements_GetModulation()).velocity[index] }
+}
+
+fn setup_voice_knobs<'a>() -> Vec<Knob<'a>> {
+    vec![
+        Knob::new(
+            Point::new(KNOB_POS_X[0], KNOB_POS_Y),
+            "Stl",
+            InputDeviceId::Knob1 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                change(|midi_input| adjust_index(&mut midi_input.voices.mode, delta, STEAL_LAST))
+            }),
+        )
+        .with_labels(&STEAL_LABELS)
+        .with_switch(
+            InputDeviceId::Knob1Switch as InputId,
+            KnobSwitch::Alternate(
+                "Sld",
+                Box::new(|delta: i8, _fine: bool| {
+                    change(|midi_input| {
+                        let mpe = &mut midi_input.mpe;
+                        let value = adjust_index(
+                            &mut mpe.slide_target,
+                            delta,
+                            PARAMETER_LABELS.len() as u32,
+                        );
+                        if delta != 0 {
+                            unsafe {
+                                Elements_SetController(
+                                    CONTROLLER_SLIDE,
+                                    mpe.slide_target.wrapping_sub(1),
+                                    0.0,
+                                );
+                            }
+                        }
+                        value
+                    })
+                }),
+                Some(&PARAMETER_LABELS),
+            ),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[1], KNOB_POS_Y),
+            "Uni",
+            InputDeviceId::Knob2 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                change(|midi_input| {
+                    let mut unison = midi_input.voices.unison() as u32;
+                    adjust_index(&mut unison, delta, UNISON_LABELS.len() as u32);
+                    if (unison != 0) != midi_input.voices.unison() {
+                        midi_input.set_unison(unison != 0);
+                    }
+                    // stays off with a single voice
+                    midi_input.voices.unison() as i16
+                })
+            }),
+        )
+        .with_labels(&UNISON_LABELS)
+        .with_switch(
+            InputDeviceId::Knob2Switch as InputId,
+            KnobSwitch::Alternate(
+                "Prs",
+                Box::new(|delta: i8, _fine: bool| {
+                    change(|midi_input| {
+                        let mut destination = midi_input.pressure.destination;
+                        let value =
+                            adjust_index(&mut destination, delta, PRESSURE_LABELS.len() as u32);
+                        if destination != midi_input.pressure.destination {
+                            midi_input.set_pressure_destination(destination);
+                        }
+                        value
+                    })
+                }),
+                Some(&PRESSURE_LABELS),
+            ),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[2], KNOB_POS_Y),
+            "Det",
+            InputDeviceId::Knob3 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                change(|midi_input| {
+                    let value = adjust_range(&mut midi_input.voices.detune, delta, 0, MAX_DETUNE);
+                    midi_input.apply_voicing();
+                    value
+                })
+            }),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[3], KNOB_POS_Y),
+            "Sprd",
+            InputDeviceId::Knob4 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                change(|midi_input| {
+                    let value = adjust_range(&mut midi_input.voices.spread, delta, 0, MAX_SPREAD);
+                    midi_input.apply_voicing();
+                    value
+                })
+            }),
+        ),
+    ]
+}
+
+fn setup_velocity_knobs<'a>() -> Vec<Knob<'a>> {
+    vec![
+        Knob::new(
+            Point::new(KNOB_POS_X[0], KNOB_POS_Y),
+            "Curv",
+            InputDeviceId::Knob1 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                change(|midi_input| adjust_index(&mut midi_input.velocity.curve, delta, CURVE_LAST))
+            }),
+        )
+        .with_labels(&CURVE_LABELS),
+        Knob::new(
+            Point::new(KNOB_POS_X[1], KNOB_POS_Y),
+            "Sens",
+            InputDeviceId::Knob2 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                change(|midi_input| {
+                    adjust_range(
+                        &mut midi_input.velocity.sensitivity,
+                        delta,
+                        0,
+                        MAX_SENSITIVITY,
+                    )
+                })
+            }),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[2], KNOB_POS_Y),
+            "Dst1",
+            InputDeviceId::Knob3 as InputId,
+            Box::new(|delta: i8, _fine: bool| {
+                adjust_index(
+                    &mut velocity_slot(0).destination,
+                    delta,
+                    NUM_PATCH_PARAMETERS as u32,
+                )
+            }),
+        )
+        .with_labels(&PARAMETER_LABELS[1..])
+        .with_switch(
+            InputDeviceId::Knob3Switch as InputId,
+            KnobSwitch::Alternate(
+                "Dst2",
+                Box::new(|delta: i8, _fine: bool| {
+                    adjust_index(
+                        &mut velocity_slot(1).destination,
+                        delta,
+                        NUM_PATCH_PARAMETERS as u32,
+                    )
+                }),
+                Some(&PARAMETER_LABELS[1..]),
+            ),
+        ),
+        Knob::new(
+            Point::new(KNOB_POS_X[3], KNOB_POS_Y),
+            "Amt1",
+            InputDeviceId::Knob4 as InputId,
+            Box::new(|delta: i8, fine: bool| {
+                adjust_value(
+                    &mut velocity_slot(0).amount,
+                    delta,
+                    fine,
+                    AMOUNT_MIN,
+                    AMOUNT_MAX,
+                )
+            }),
+        )
+        .with_switch(
+            InputDeviceId::Knob4Switch as InputId,
+            KnobSwitch::Alternate(
+                "Amt2",
+                Box::new(|delta: i8, fine: bool| {
+                    adjust_value(
+                        &mut velocity_slot(1).amount,
+                        delta,
+                        fine,
+                        AMOUNT_MIN,
+                        AMOUNT_MAX,
+                    )
+                }),
+                None,
+            ),
+        ),
+    ]
+}
+
+// Pressing the voice button again cycles through the voice, velocity, external trigger
+// and MIDI pages
+pub fn setup_buttons<'a>(page: PanelId) -> Vec<Button<'a>> {
+    let (caption, next) = match page {
+        PanelId::PanelVelocity => ("*Vel", PanelId::PanelExt),
+        PanelId::PanelExt => ("*Ext", PanelId::PanelMidi),
+        PanelId::PanelMidi => ("*Mid", PanelId::PanelVoice),
+        _ => ("*Voi", PanelId::PanelVelocity),
+    };
+    let mut buttons = panel_arp::setup_play_buttons(2);
+    buttons[2] = Button::new(
+        Point::new(BUTTON_POS_X[2], BUTTON_POS_Y),
+        caption,
+        InputDeviceId::Button3 as InputId,
+        Box::new(move |_value: bool| {
+            unsafe {
+                (*APP).change_panel(&mut *APP, next);
+            }
+            true
+        }),
+    );
+    buttons
+}
+
+pub fn setup_voice<'a>() -> (Vec<Button<'a>>, Vec<Knob<'a>>) {
+    (setup_buttons(PanelId::PanelVoice), setup_voice_knobs())
+}
+
+pub fn setup_velocity<'a>() -> (Vec<Button<'a>>, Vec<Knob<'a>>) {
+    (
+        setup_buttons(PanelId::PanelVelocity),
+        setup_velocity_knobs(),
+    )
+}
@@ -0,0 +1,187 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// Turns the note velocity into the strength of the exciter.
+
+pub const CURVE_LINEAR: u32 = 0;
+// louder at low velocities
+pub const CURVE_SOFT: u32 = 1;
+// quieter at low velocities
+pub const CURVE_HARD: u32 = 2;
+// every note plays at FIXED_VELOCITY
+pub const CURVE_FIXED: u32 = 3;
+pub const CURVE_LAST: u32 = 4;
+
+// in percent, 0 plays every note at full strength
+pub const MAX_SENSITIVITY: u32 = 100;
+
+const FIXED_VELOCITY: u8 = 100;
+
+// The curves are sampled at evenly spaced velocities, 127 maps to the last point
+const LUT_SIZE: usize = 17;
+const LUT_STEP: f32 = 127.0 / (LUT_SIZE - 1) as f32;
+
+const LUT_LINEAR: [f32; LUT_SIZE] = [
+    0.0, 0.0625, 0.125, 0.1875, 0.25, 0.3125, 0.375, 0.4375, 0.5, 0.5625, 0.625, 0.6875, 0.75,
+    0.8125, 0.875, 0.9375, 1.0,
+];
+// square root
+#[allow(clippy::approx_constant)]
+const LUT_SOFT: [f32; LUT_SIZE] = [
+    0.0, 0.25, 0.3536, 0.433, 0.5, 0.559, 0.6124, 0.6614, 0.7071, 0.75, 0.7906, 0.8292, 0.866,
+    0.9014, 0.9354, 0.9682, 1.0,
+];
+// square
+const LUT_HARD: [f32; LUT_SIZE] = [
+    0.0, 0.0039, 0.0156, 0.0352, 0.0625, 0.0977, 0.1406, 0.1914, 0.25, 0.3164, 0.3906, 0.4727,
+    0.5625, 0.6602, 0.7656, 0.8789, 1.0,
+];
+
+pub struct VelocityCurve {
+    pub curve: u32,
+    pub sensitivity: u32,
+}
+
+impl Default for VelocityCurve {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VelocityCurve {
+    pub fn new() -> Self {
+        VelocityCurve {
+            curve: CURVE_LINEAR,
+            sensitivity: MAX_SENSITIVITY,
+        }
+    }
+
+    // Returns the strength, 0.0 to 1.0, for a MIDI velocity
+    pub fn strength(&self, velocity: u8) -> f32 {
+        let (lut, velocity) = match self.curve {
+            CURVE_SOFT => (&LUT_SOFT, velocity),
+            CURVE_HARD => (&LUT_HARD, velocity),
+            CURVE_FIXED => (&LUT_LINEAR, FIXED_VELOCITY),
+            _ => (&LUT_LINEAR, velocity),
+        };
+        let sensitivity = self.sensitivity.min(MAX_SENSITIVITY) as f32 / MAX_SENSITIVITY as f32;
+        1.0 - sensitivity * (1.0 - interpolate(lut, velocity.min(127)))
+    }
+}
+
+fn interpolate(lut: &[f32; LUT_SIZE], velocity: u8) -> f32 {
+    let position = velocity as f32 / LUT_STEP;
+    let index = (position as usize).min(LUT_SIZE - 2);
+    let fraction = position - index as f32;
+    lut[index] + (lut[index + 1] - lut[index]) * fraction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CURVES: [u32; 3] = [CURVE_LINEAR, CURVE_SOFT, CURVE_HARD];
+
+    fn curve(curve: u32, sensitivity: u32) -> VelocityCurve {
+        VelocityCurve { curve, sensitivity }
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn curves_span_silence_to_full_strength() {
+        for &shape in CURVES.iter() {
+            let velocity = curve(shape, MAX_SENSITIVITY);
+            assert_close(velocity.strength(0), 0.0);
+            assert_close(velocity.strength(127), 1.0);
+        }
+    }
+
+    #[test]
+    fn curves_rise_with_the_velocity() {
+        for &shape in CURVES.iter() {
+            let velocity = curve(shape, MAX_SENSITIVITY);
+            for value in 1..=127 {
+                assert!(velocity.strength(value) > velocity.strength(value - 1));
+            }
+        }
+    }
+
+    #[test]
+    fn curves_bend_the_linear_one() {
+        let soft = curve(CURVE_SOFT, MAX_SENSITIVITY);
+        let linear = curve(CURVE_LINEAR, MAX_SENSITIVITY);
+        let hard = curve(CURVE_HARD, MAX_SENSITIVITY);
+        assert_close(linear.strength(64), 64.0 / 127.0);
+        for value in 1..127 {
+            assert!(soft.strength(value) > linear.strength(value));
+            assert!(hard.strength(value) < linear.strength(value));
+        }
+    }
+
+    #[test]
+    fn no_sensitivity_plays_full_strength() {
+        for &shape in CURVES.iter() {
+            let velocity = curve(shape, 0);
+            for value in 0..=127 {
+                assert_close(velocity.strength(value), 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn half_sensitivity_halves_the_range() {
+        let velocity = curve(CURVE_LINEAR, MAX_SENSITIVITY / 2);
+        assert_close(velocity.strength(0), 0.5);
+        assert_close(velocity.strength(127), 1.0);
+    }
+
+    #[test]
+    fn sensitivity_above_the_maximum_is_the_maximum() {
+        let velocity = curve(CURVE_HARD, MAX_SENSITIVITY + 50);
+        assert_close(velocity.strength(0), 0.0);
+        assert_close(
+            velocity.strength(64),
+            curve(CURVE_HARD, MAX_SENSITIVITY).strength(64),
+        );
+    }
+
+    #[test]
+    fn fixed_curve_ignores_the_velocity() {
+        let velocity = curve(CURVE_FIXED, MAX_SENSITIVITY);
+        let fixed = velocity.strength(FIXED_VELOCITY);
+        assert_close(fixed, FIXED_VELOCITY as f32 / 127.0);
+        for value in 0..=127 {
+            assert_close(velocity.strength(value), fixed);
+        }
+    }
+
+    #[test]
+    fn velocities_past_127_are_clamped() {
+        let velocity = curve(CURVE_SOFT, MAX_SENSITIVITY);
+        assert_close(velocity.strength(255), velocity.strength(127));
+    }
+}
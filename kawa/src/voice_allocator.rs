@@ -0,0 +1,270 @@
+// Copyright 2019 Wojciech Jakóbczyk
+//
+// Author: Wojciech Jakóbczyk (jakobczyk.woj@gmail.com)
+//
+// This file is part of Kawa Synth.
+//
+// Kawa Synth is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Kawa Synth is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+// Decides which voice of the part plays a note. The MIDI input opens and closes the gates
+// of the voices it returns.
+
+use alloc::vec::Vec;
+
+// takes the voices in turn
+pub const STEAL_ROUND_ROBIN: u32 = 0;
+// takes a free voice, or steals the one playing the longest
+pub const STEAL_OLDEST: u32 = 1;
+// takes the free voice released the longest ago, its tail has decayed the most
+pub const STEAL_RELEASED: u32 = 2;
+pub const STEAL_LAST: u32 = 3;
+
+// in cents, between the lowest and the highest voice in unison
+pub const MAX_DETUNE: u32 = 100;
+pub const DEFAULT_DETUNE: u32 = 20;
+// in percent, 100 pans the outermost voices hard left and right
+pub const MAX_SPREAD: u32 = 100;
+pub const DEFAULT_SPREAD: u32 = 50;
+
+#[derive(Clone, Copy, Default)]
+struct Voice {
+    note: u8,
+    gate: bool,
+    started: u32,
+    released: u32,
+}
+
+pub struct VoiceAllocator {
+    pub mode: u32,
+    pub detune: u32,
+    pub spread: u32,
+    // all voices play the note of voice 0
+    unison: bool,
+    voices: Vec<Voice>,
+    next: usize,
+    // orders the note on and off events
+    counter: u32,
+}
+
+impl VoiceAllocator {
+    pub fn new(count: usize) -> Self {
+        VoiceAllocator {
+            mode: STEAL_RELEASED,
+            detune: DEFAULT_DETUNE,
+            spread: DEFAULT_SPREAD,
+            unison: false,
+            voices: (0..count.max(1)).map(|_| Voice::default()).collect(),
+            next: 0,
+            counter: 0,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.voices.len()
+    }
+
+    pub fn unison(&self) -> bool {
+        self.unison
+    }
+
+    // Forgets the playing notes, the caller closes the gates of all voices
+    pub fn set_unison(&mut self, unison: bool) {
+        self.unison = unison && self.voices.len() > 1;
+        for voice in self.voices.iter_mut() {
+            *voice = Voice::default();
+        }
+    }
+
+    // Returns the voice that starts playing the note
+    pub fn note_on(&mut self, note: u8) -> usize {
+        // a note that is still sounding keeps its voice
+        let voice = match self.voice_of(note) {
+            Some(voice) => voice,
+            None => self.allocate(),
+        };
+
+        self.counter = self.counter.wrapping_add(1);
+        self.voices[voice] = Voice {
+            note,
+            gate: true,
+            started: self.counter,
+            released: 0,
+        };
+        voice
+    }
+
+    // Returns the voice that stops playing the note, if any still does
+    pub fn note_off(&mut self, note: u8) -> Option<usize> {
+        let voice = self.voice_of(note)?;
+
+        self.counter = self.counter.wrapping_add(1);
+        self.voices[voice].gate = false;
+        self.voices[voice].released = self.counter;
+        Some(voice)
+    }
+
+    // The voice playing the note, if any
+    pub fn voice_of(&self, note: u8) -> Option<usize> {
+        self.voices
+            .iter()
+            .position(|voice| voice.note == note && voice.gate)
+    }
+
+    // The last started note that is still playing
+    pub fn latest(&self) -> Option<u8> {
+        self.voices
+            .iter()
+            .filter(|voice| voice.gate)
+            .max_by_key(|voice| voice.started)
+            .map(|voice| voice.note)
+    }
+
+    fn allocate(&mut self) -> usize {
+        if self.unison {
+            return 0;
+        }
+
+        match self.mode {
+            STEAL_OLDEST => self
+                .oldest(|voice| !voice.gate, |voice| voice.started)
+                .or_else(|| self.oldest(|_| true, |voice| voice.started)),
+            STEAL_RELEASED => self
+                .oldest(|voice| !voice.gate, |voice| voice.released)
+                .or_else(|| self.oldest(|_| true, |voice| voice.started)),
+            _ => None,
+        }
+        .unwrap_or_else(|| {
+            let voice = self.next % self.voices.len();
+            self.next = voice + 1;
+            voice
+        })
+    }
+
+    fn oldest(
+        &self,
+        filter: impl Fn(&Voice) -> bool,
+        age: impl Fn(&Voice) -> u32,
+    ) -> Option<usize> {
+        self.voices
+            .iter()
+            .enumerate()
+            .filter(|(_, voice)| filter(voice))
+            .min_by_key(|(_, voice)| age(voice))
+            .map(|(index, _)| index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allocator(mode: u32) -> VoiceAllocator {
+        let mut voices = VoiceAllocator::new(4);
+        voices.mode = mode;
+        voices
+    }
+
+    fn play(voices: &mut VoiceAllocator, notes: &[u8]) -> Vec<usize> {
+        notes.iter().map(|note| voices.note_on(*note)).collect()
+    }
+
+    #[test]
+    fn round_robin_takes_the_voices_in_turn() {
+        let mut voices = allocator(STEAL_ROUND_ROBIN);
+        assert_eq!(play(&mut voices, &[60, 61, 62]), [0, 1, 2]);
+        // a free voice doesn't jump the turn
+        voices.note_off(60);
+        assert_eq!(play(&mut voices, &[63, 64, 65]), [3, 0, 1]);
+    }
+
+    #[test]
+    fn round_robin_steals_in_turn_when_all_voices_play() {
+        let mut voices = allocator(STEAL_ROUND_ROBIN);
+        assert_eq!(
+            play(&mut voices, &[60, 61, 62, 63, 64, 65]),
+            [0, 1, 2, 3, 0, 1]
+        );
+        assert_eq!(voices.voice_of(60), None);
+        assert_eq!(voices.voice_of(64), Some(0));
+    }
+
+    #[test]
+    fn oldest_takes_a_free_voice_first() {
+        let mut voices = allocator(STEAL_OLDEST);
+        assert_eq!(play(&mut voices, &[60, 61, 62, 63]), [0, 1, 2, 3]);
+        voices.note_off(62);
+        assert_eq!(voices.note_on(64), 2);
+    }
+
+    #[test]
+    fn oldest_steals_the_note_playing_the_longest() {
+        let mut voices = allocator(STEAL_OLDEST);
+        assert_eq!(play(&mut voices, &[60, 61, 62, 63]), [0, 1, 2, 3]);
+        // re-triggering makes a note the newest
+        voices.note_on(60);
+        assert_eq!(play(&mut voices, &[64, 65]), [1, 2]);
+    }
+
+    #[test]
+    fn released_takes_the_voice_released_the_longest_ago() {
+        let mut voices = allocator(STEAL_RELEASED);
+        assert_eq!(play(&mut voices, &[60, 61, 62, 63]), [0, 1, 2, 3]);
+        voices.note_off(63);
+        voices.note_off(61);
+        voices.note_off(62);
+        assert_eq!(play(&mut voices, &[64, 65, 66]), [3, 1, 2]);
+    }
+
+    #[test]
+    fn released_steals_the_oldest_note_when_all_voices_play() {
+        let mut voices = allocator(STEAL_RELEASED);
+        assert_eq!(play(&mut voices, &[60, 61, 62, 63]), [0, 1, 2, 3]);
+        assert_eq!(play(&mut voices, &[64, 65]), [0, 1]);
+    }
+
+    #[test]
+    fn sounding_note_keeps_its_voice() {
+        for mode in 0..STEAL_LAST {
+            let mut voices = allocator(mode);
+            assert_eq!(play(&mut voices, &[60, 61, 62]), [0, 1, 2]);
+            assert_eq!(voices.note_on(61), 1);
+            assert_eq!(voices.voice_of(61), Some(1));
+            assert_eq!(voices.latest(), Some(61));
+        }
+    }
+
+    #[test]
+    fn note_off_finds_the_voice_of_the_note() {
+        let mut voices = allocator(STEAL_RELEASED);
+        play(&mut voices, &[60, 61]);
+        assert_eq!(voices.note_off(61), Some(1));
+        assert_eq!(voices.note_off(61), None);
+        assert_eq!(voices.note_off(70), None);
+        assert_eq!(voices.latest(), Some(60));
+    }
+
+    #[test]
+    fn unison_plays_every_note_on_voice_0() {
+        let mut voices = allocator(STEAL_ROUND_ROBIN);
+        voices.set_unison(true);
+        assert!(voices.unison());
+        assert_eq!(play(&mut voices, &[60, 61, 62]), [0, 0, 0]);
+        assert_eq!(voices.latest(), Some(62));
+
+        // a single voice has nothing to play in unison
+        let mut single = VoiceAllocator::new(1);
+        single.set_unison(true);
+        assert!(!single.unison());
+    }
+}
@@ -0,0 +1,178 @@
+#!/usr/bin/env python3
+#
+# Copyright 2019 Wojciech Jakóbczyk
+#
+# This file is part of Kawa Synth.
+#
+# Kawa Synth is free software: you can redistribute it and/or modify
+# it under the terms of the GNU General Public License as published by
+# the Free Software Foundation, either version 3 of the License, or
+# (at your option) any later version.
+#
+# Kawa Synth is distributed in the hope that it will be useful,
+# but WITHOUT ANY WARRANTY; without even the implied warranty of
+# MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+# GNU General Public License for more details.
+#
+# You should have received a copy of the GNU General Public License
+# along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+"""Converts a Scala scale (.scl) and keyboard mapping (.kbm) to a MIDI Tuning
+Standard bulk dump (.syx) that Kawa stores in one of its tuning slots.
+
+  scl2syx.py scale.scl [-k mapping.kbm] [-s slot] [-o tuning.syx]
+"""
+
+import argparse
+import math
+import os
+
+NUM_NOTES = 128
+NAME_LENGTH = 16
+NUM_SLOTS = 8
+NO_CHANGE = [0x7F, 0x7F, 0x7F]
+
+
+def read_lines(path):
+  """Returns the lines of a Scala file without the comments."""
+  with open(path, encoding='latin-1') as f:
+    return [line.strip() for line in f if not line.startswith('!')]
+
+
+def parse_pitch(text):
+  """Returns a scale pitch in cents, a dot marks cents, otherwise a ratio."""
+  text = text.split()[0]
+  if '.' in text:
+    return float(text)
+  numerator, _, denominator = text.partition('/')
+  return 1200.0 * math.log2(int(numerator) / int(denominator or 1))
+
+
+def read_scale(path):
+  """Returns the description and the pitches of the degrees, 0 included."""
+  lines = read_lines(path)
+  description = lines[0]
+  count = int(lines[1].split()[0])
+  pitches = [parse_pitch(line) for line in lines[2:2 + count]]
+  if len(pitches) != count:
+    raise ValueError('%s: expected %d pitches' % (path, count))
+  return description, [0.0] + pitches
+
+
+class Mapping(object):
+  """Keyboard mapping, the default one maps the degrees to consecutive keys
+  with degree 0 on middle C and A4 at 440 Hz."""
+
+  def __init__(self):
+    self.size = 0
+    self.first = 0
+    self.last = NUM_NOTES - 1
+    self.middle = 60
+    self.reference = 69
+    self.frequency = 440.0
+    self.octave_degree = 0
+    self.keys = []
+
+  def read(self, path):
+    lines = [line for line in read_lines(path) if line]
+    values = [line.split()[0] for line in lines]
+    self.size = int(values[0])
+    self.first, self.last = int(values[1]), int(values[2])
+    self.middle, self.reference = int(values[3]), int(values[4])
+    self.frequency = float(values[5])
+    self.octave_degree = int(values[6])
+    # missing entries are unmapped
+    keys = values[7:7 + self.size]
+    keys += ['x'] * (self.size - len(keys))
+    self.keys = [None if key == 'x' else int(key) for key in keys]
+
+  def degree(self, note):
+    """Returns the scale degree of a key, None for unmapped keys."""
+    if note < self.first or note > self.last:
+      return None
+    offset = note - self.middle
+    if self.size == 0:
+      return offset
+    octave, index = divmod(offset, self.size)
+    if self.keys[index] is None:
+      return None
+    return self.keys[index] + octave * self.octave_degree
+
+
+def cents(pitches, degree):
+  """Returns the pitch of any degree, the last pitch is the period."""
+  steps = len(pitches) - 1
+  period, index = divmod(degree, steps)
+  return period * pitches[-1] + pitches[index]
+
+
+def tuning_table(pitches, mapping):
+  """Returns the MIDI note number of each key, None for unmapped keys."""
+  reference = mapping.degree(mapping.reference)
+  if reference is None:
+    raise ValueError('the reference key is unmapped')
+  reference_note = 69.0 + 12.0 * math.log2(mapping.frequency / 440.0)
+
+  table = []
+  for note in range(NUM_NOTES):
+    degree = mapping.degree(note)
+    if degree is None:
+      table.append(None)
+    else:
+      interval = cents(pitches, degree) - cents(pitches, reference)
+      table.append(reference_note + interval / 100.0)
+  return table
+
+
+def frequency_data(note):
+  """Returns the semitone and the 14-bit fraction of the next semitone."""
+  if note is None:
+    return NO_CHANGE
+  note = min(max(note, 0.0), 127.0 + 16383.0 / 16384.0)
+  semitone = int(math.floor(note))
+  fraction = int(round((note - semitone) * 16384.0))
+  if fraction == 16384:
+    semitone, fraction = semitone + 1, 0
+  return [semitone, fraction >> 7, fraction & 0x7F]
+
+
+def bulk_dump(slot, name, table):
+  """Returns the SysEx bytes of a bulk tuning dump, F0 and F7 included."""
+  name = name.encode('ascii', 'replace')[:NAME_LENGTH].ljust(NAME_LENGTH)
+  message = [0x7E, 0x7F, 0x08, 0x01, slot]
+  message += [byte & 0x7F for byte in name]
+  for note in table:
+    message += frequency_data(note)
+  checksum = 0
+  for byte in message:
+    checksum ^= byte
+  return bytes([0xF0] + message + [checksum & 0x7F, 0xF7])
+
+
+def main():
+  parser = argparse.ArgumentParser(description=__doc__.split('\n')[0])
+  parser.add_argument('scale', help='Scala scale file')
+  parser.add_argument('-k', '--kbm', help='Scala keyboard mapping file')
+  parser.add_argument('-s', '--slot', type=int, default=1,
+                      help='tuning slot, 1 to %d' % NUM_SLOTS)
+  parser.add_argument('-n', '--name', help='name shown on the synth')
+  parser.add_argument('-o', '--output', help='SysEx file to write')
+  args = parser.parse_args()
+
+  if not 1 <= args.slot <= NUM_SLOTS:
+    parser.error('the slot must be between 1 and %d' % NUM_SLOTS)
+
+  description, pitches = read_scale(args.scale)
+  mapping = Mapping()
+  if args.kbm:
+    mapping.read(args.kbm)
+
+  base = os.path.splitext(args.scale)[0]
+  name = args.name or os.path.basename(base) or description
+  output = args.output or base + '.syx'
+  with open(output, 'wb') as f:
+    f.write(bulk_dump(args.slot - 1, name, tuning_table(pitches, mapping)))
+
+
+if __name__ == '__main__':
+  main()
@@ -0,0 +1,99 @@
+#!/usr/bin/env python3
+#
+# Copyright 2019 Wojciech Jakóbczyk
+#
+# This file is part of Kawa Synth.
+#
+# Kawa Synth is free software: you can redistribute it and/or modify
+# it under the terms of the GNU General Public License as published by
+# the Free Software Foundation, either version 3 of the License, or
+# (at your option) any later version.
+#
+# Kawa Synth is distributed in the hope that it will be useful,
+# but WITHOUT ANY WARRANTY; without even the implied warranty of
+# MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+# GNU General Public License for more details.
+#
+# You should have received a copy of the GNU General Public License
+# along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+"""Checks scl2syx.py against just.syx, the dump the firmware tests parse.
+
+  python3 kawa/tools/test/test_scl2syx.py
+"""
+
+import math
+import os
+import sys
+import unittest
+
+TEST_DIR = os.path.dirname(os.path.abspath(__file__))
+sys.path.insert(0, os.path.dirname(TEST_DIR))
+
+import scl2syx
+
+# 5-limit just intonation on C, ratios to C
+JUST_RATIOS = [1, 16 / 15, 9 / 8, 6 / 5, 5 / 4, 4 / 3, 45 / 32, 3 / 2, 8 / 5, 5 / 3,
+               9 / 5, 15 / 8]
+
+
+def just_dump():
+  _, pitches = scl2syx.read_scale(os.path.join(TEST_DIR, 'just.scl'))
+  table = scl2syx.tuning_table(pitches, scl2syx.Mapping())
+  return scl2syx.bulk_dump(0, 'just', table)
+
+
+def decode(dump):
+  """Returns the slot, the name and the note numbers of a bulk dump."""
+  message = dump[1:-1]
+  checksum = 0
+  for byte in message[:-1]:
+    checksum ^= byte
+  assert checksum & 0x7F == message[-1], 'bad checksum'
+  name = bytes(message[5:5 + scl2syx.NAME_LENGTH]).decode('ascii')
+  data = message[5 + scl2syx.NAME_LENGTH:-1]
+  notes = []
+  for note in range(scl2syx.NUM_NOTES):
+    semitone, msb, lsb = data[note * 3:note * 3 + 3]
+    notes.append(semitone + ((msb << 7) | lsb) / 16384.0)
+  return message[4], name, notes
+
+
+class Scl2SyxTest(unittest.TestCase):
+
+  def test_dump_matches_the_firmware_test_data(self):
+    with open(os.path.join(TEST_DIR, 'just.syx'), 'rb') as f:
+      self.assertEqual(just_dump(), f.read())
+
+  def test_dump_is_a_bulk_tuning_dump(self):
+    dump = just_dump()
+    self.assertEqual(len(dump), 408)
+    self.assertEqual(list(dump[:5]), [0xF0, 0x7E, 0x7F, 0x08, 0x01])
+    self.assertEqual(dump[-1], 0xF7)
+
+  def test_round_trip_keeps_the_scale(self):
+    slot, name, notes = decode(just_dump())
+    self.assertEqual(slot, 0)
+    self.assertEqual(name, 'just'.ljust(scl2syx.NAME_LENGTH))
+    # A4 keeps 440 Hz, the other notes follow the ratios to C
+    self.assertAlmostEqual(notes[69], 69.0, delta=1 / 16384)
+    c4 = notes[60]
+    for note in range(scl2syx.NUM_NOTES):
+      octave, degree = divmod(note - 60, 12)
+      expected = c4 + 12 * octave + 12 * math.log2(JUST_RATIOS[degree])
+      self.assertAlmostEqual(notes[note], expected, delta=1 / 16384, msg=note)
+
+  def test_equal_temperament_has_no_fraction(self):
+    pitches = [0.0] + [100.0 * degree for degree in range(1, 13)]
+    table = scl2syx.tuning_table(pitches, scl2syx.Mapping())
+    for note, value in enumerate(table):
+      self.assertEqual(scl2syx.frequency_data(value), [note, 0, 0])
+
+  def test_frequency_data_rounds_up_to_the_next_semitone(self):
+    self.assertEqual(scl2syx.frequency_data(60.5), [60, 0x40, 0])
+    self.assertEqual(scl2syx.frequency_data(60.99999), [61, 0, 0])
+    self.assertEqual(scl2syx.frequency_data(None), scl2syx.NO_CHANGE)
+
+
+if __name__ == '__main__':
+  unittest.main()
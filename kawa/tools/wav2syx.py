@@ -0,0 +1,130 @@
+#!/usr/bin/env python3
+#
+# Copyright 2019 Wojciech Jakóbczyk
+#
+# This file is part of Kawa Synth.
+#
+# Kawa Synth is free software: you can redistribute it and/or modify
+# it under the terms of the GNU General Public License as published by
+# the Free Software Foundation, either version 3 of the License, or
+# (at your option) any later version.
+#
+# Kawa Synth is distributed in the hope that it will be useful,
+# but WITHOUT ANY WARRANTY; without even the implied warranty of
+# MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+# GNU General Public License for more details.
+#
+# You should have received a copy of the GNU General Public License
+# along with Kawa Synth.  If not, see <https://www.gnu.org/licenses/>.
+
+"""Converts one-shot WAV files to the SysEx messages that replace Kawa's user
+strike samples.
+
+  wav2syx.py hit_1.wav hit_2.wav ... [-o samples.syx]
+
+The first message erases the sample flash, which takes a couple of seconds,
+and every message is written to flash before the next one is read. Send the
+file with a pause between the messages, e.g. amidi -s samples.syx -i 100.
+"""
+
+import argparse
+import struct
+import wave
+
+SAMPLE_RATE = 32000
+MAX_SAMPLES = 8
+# all samples together, the rest of the flash sector holds the header
+MAX_LENGTH = 65514
+# keeps the messages below the 512 bytes Kawa receives
+CHUNK_SIZE = 192
+
+HEADER = [0x7D, 0x4B, 0x57]
+SAMPLES_CLEAR = 0x10
+SAMPLES_DATA = 0x11
+SAMPLES_END_SAMPLE = 0x12
+SAMPLES_COMMIT = 0x13
+
+
+def read_wav(path):
+  """Returns the frames of a PCM WAV file as mono floats at the synth rate."""
+  with wave.open(path, 'rb') as f:
+    channels = f.getnchannels()
+    width = f.getsampwidth()
+    rate = f.getframerate()
+    frames = f.readframes(f.getnframes())
+
+  if width == 1:
+    values = [(byte - 128) / 128.0 for byte in frames]
+  elif width == 2:
+    count = len(frames) // 2
+    values = [value / 32768.0 for value in struct.unpack('<%dh' % count, frames)]
+  else:
+    raise ValueError('%s: only 8 and 16-bit files are supported' % path)
+
+  # channels are summed, like the built-in samples
+  mono = [sum(values[i:i + channels]) for i in range(0, len(values), channels)]
+  if rate == SAMPLE_RATE:
+    return mono
+
+  length = int(len(mono) * SAMPLE_RATE / rate)
+  resampled = []
+  for i in range(length):
+    position = i * rate / SAMPLE_RATE
+    index = int(position)
+    fraction = position - index
+    a = mono[index]
+    b = mono[min(index + 1, len(mono) - 1)]
+    resampled.append(a + (b - a) * fraction)
+  return resampled
+
+
+def pack(data):
+  """Packs 8-bit data into groups of 7 bytes preceded by their top bits."""
+  packed = []
+  for i in range(0, len(data), 7):
+    group = data[i:i + 7]
+    top_bits = 0
+    for bit, byte in enumerate(group):
+      top_bits |= (byte >> 7) << bit
+    packed.append(top_bits)
+    packed += [byte & 0x7F for byte in group]
+  return packed
+
+
+def message(command, payload=()):
+  return bytes([0xF0] + HEADER + [command] + list(payload) + [0xF7])
+
+
+def main():
+  parser = argparse.ArgumentParser(description=__doc__.split('\n')[0])
+  parser.add_argument('samples', nargs='+', help='WAV files, up to %d' % MAX_SAMPLES)
+  parser.add_argument('-o', '--output', default='samples.syx',
+                      help='SysEx file to write')
+  args = parser.parse_args()
+
+  if len(args.samples) > MAX_SAMPLES:
+    parser.error('at most %d samples fit' % MAX_SAMPLES)
+
+  messages = [message(SAMPLES_CLEAR)]
+  total = 0
+  for path in args.samples:
+    samples = [int(round(max(-1.0, min(1.0, value)) * 32767.0))
+               for value in read_wav(path)]
+    # one more value follows each sample for the interpolation
+    total += len(samples) + 1
+    if total > MAX_LENGTH:
+      parser.error('the samples are %.2f s too long' %
+                   ((total - MAX_LENGTH) / float(SAMPLE_RATE)))
+    for i in range(0, len(samples), CHUNK_SIZE):
+      chunk = samples[i:i + CHUNK_SIZE]
+      data = list(struct.pack('<%dh' % len(chunk), *chunk))
+      messages.append(message(SAMPLES_DATA, pack(data)))
+    messages.append(message(SAMPLES_END_SAMPLE))
+  messages.append(message(SAMPLES_COMMIT))
+
+  with open(args.output, 'wb') as f:
+    f.write(b''.join(messages))
+
+
+if __name__ == '__main__':
+  main()